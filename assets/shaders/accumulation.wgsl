@@ -0,0 +1,32 @@
+// running average of the frames, see accumulation.rs
+// writes the same color to the view and to the history for the next frame
+
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct AccumulationWeight {
+ weight: f32,
+#ifdef SIXTEEN_BYTE_ALIGNMENT
+ _webgl2_padding: vec3<f32>,
+#endif
+}
+
+@group(0) @binding(0) var current_texture: texture_2d<f32>;
+@group(0) @binding(1) var history_texture: texture_2d<f32>;
+@group(0) @binding(2) var<uniform> accumulation: AccumulationWeight;
+
+struct AccumulationOutput {
+  @location(0) view_target: vec4<f32>,
+  @location(1) history: vec4<f32>,
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> AccumulationOutput {
+  let coord = vec2<i32>(in.position.xy);
+  let current = textureLoad(current_texture, coord, 0);
+  // a weight of 1.0 starts over, whatever is in the history doesn't matter then
+  var color = current;
+  if accumulation.weight < 1.0 {
+      color = mix(textureLoad(history_texture, coord, 0), current, accumulation.weight);
+    }
+  return AccumulationOutput(color, color);
+}
@@ -5,54 +5,95 @@ mesh_view_bindings::view,
   utils::coords_to_viewport_uv,
   pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
   pbr_types::pbr_input_new,
+  pbr_types::PbrInput,
   pbr_types::StandardMaterial,
   pbr_types::standard_material_new,
+  pbr_types::STANDARD_MATERIAL_FLAGS_ATTENUATION_ENABLED_BIT,
   pbr_functions::alpha_discard,
   pbr_fragment::pbr_input_from_standard_material,
   mesh_view_bindings::globals,
+  mesh_view_bindings::lights,
+  mesh_view_bindings::clusterable_objects,
+  clustered_forward,
+  lighting::getDistanceAttenuation,
 }
+#import "shaders/sdf.wgsl"::{
+  object1,
+  object2,
+  raymarch_global_settings,
+  RaymarchObjectDescriptor,
+  sdf_world,
+  sdf_world_min,
+  sdf_object,
+  get_object,
+  translate_ray,
+  rotate_dir_to_object,
+  rotate_dir_to_world,
+  my_min,
+}
+#import "shaders/sdf_primitives.wgsl"::{PI, sdfMandel}
+
+@group(2) @binding(103) var object1_base_color_texture: texture_2d<f32>;
+@group(2) @binding(104) var object1_base_color_sampler: sampler;
+@group(2) @binding(105) var object1_metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(106) var object1_metallic_roughness_sampler: sampler;
+@group(2) @binding(107) var object1_normal_map_texture: texture_2d<f32>;
+@group(2) @binding(108) var object1_normal_map_sampler: sampler;
+@group(2) @binding(109) var object2_base_color_texture: texture_2d<f32>;
+@group(2) @binding(110) var object2_base_color_sampler: sampler;
+@group(2) @binding(111) var object2_metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(112) var object2_metallic_roughness_sampler: sampler;
+@group(2) @binding(113) var object2_normal_map_texture: texture_2d<f32>;
+@group(2) @binding(114) var object2_normal_map_sampler: sampler;
+@group(2) @binding(115) var cone_prepass_texture: texture_2d<f32>;
 
-@group(2) @binding(100) var<uniform> object1: RaymarchObjectDescriptor;
-@group(2) @binding(101) var<uniform> object2: RaymarchObjectDescriptor;
-@group(2) @binding(102) var<uniform> raymarch_global_settings: RaymarchGlobalSettings;
+// has to match TILE_SIZE in cone_prepass.rs
+const CONE_PREPASS_TILE_SIZE = 8.0;
 
-const PI = 3.14159265359;
-const BAILOUT = 3.0;
 
 @fragment
 fn fragment(
 	    mesh: VertexOutput,
 	    // @builtin(sample_index) sample_index: u32,
 	    ) ->  FragmentOutput {
-  let march = perform_march(mesh.position.xy);
-  let sdf_out = sdf_world(march.hit_pos);
+  let ray = get_camera_ray(mesh.position.xy);
+  let march = perform_march(ray, mesh.position.xy);
 
   if march.has_hit {
       // return color & material
       var out: FragmentOutput;
-      var normal = get_normal_of_surface(march.hit_pos);
-      var material: StandardMaterial;
-      var distances = vec2<f32>(sdf_out.distance_to_1object, sdf_out.distance_to_2object);
-      distances = normalize(distances);
-      let material_lerp_amount = ((distances.x - distances.y) + 1.0) * 0.5;
-      let desc = lerp_descriptors(object1, object2, material_lerp_amount);
-
-      material = obj_descriptor_to_material(desc);
-      var pbr_input = pbr_input_new();
-      pbr_input.frag_coord = mesh.position;
-      pbr_input.material = material;
-      pbr_input.world_normal = normal;
-      pbr_input.N = normal; // this is also the normal??
-      pbr_input.V = normalize(view.world_position - march.hit_pos);
-      pbr_input.flags = pbr_input.flags | 1u << 29u; // set the MESH_FLAGS_SHADOW_RECEIVER_BIT
-      // pbr_input.world_position = vec4<f32>(march.hit_pos, 0.0);
-      pbr_input.world_position = vec4<f32>(march.hit_pos, 1.0);
-      out.color = apply_pbr_lighting(pbr_input);
+      let ray_dir = ray.dir;
+      let shaded = shade_surface(march.hit_pos, -ray_dir, mesh.position);
+      let pbr_input = shaded.pbr_input;
+      out.color = shaded.color;
+      if raymarch_global_settings.bounce_limit > 0u {
+	  out.color = vec4<f32>(add_secondary_rays(shaded, ray_dir, mesh.position), out.color.w);
+	}
+      if raymarch_global_settings.glow_mode == 1u {
+	  // accumulated glow also shows up in front of surfaces
+	  out.color = vec4<f32>(out.color.xyz + get_object_glow(march).xyz, out.color.w);
+	}
+      if has_volumes() {
+	  let hit_distance = distance(ray.origin, march.hit_pos);
+	  let volume = integrate_volumes(ray.origin, ray_dir, hit_distance, mesh.position);
+	  out.color = vec4<f32>(out.color.xyz * volume.transmittance + volume.light, out.color.w);
+	}
+      if raymarch_global_settings.dynamic_resolution != 0u {
+	  let hit_distance = distance(ray.origin, march.hit_pos);
+	  out.color = encode_low_res(main_pass_post_lighting_processing(pbr_input, out.color), hit_distance);
+	  return out;
+	}
 
       // TODO: write to depth texture
       // right now: if something is in front, I just discard the pixel!
       // also: we cannot read from depth texture in webgl2
 #ifdef WEBGL2
+      out.color = discard_if_not_blending(out.color);
+      return out;
+#else
+#ifndef DEPTH_PREPASS
+      // the low resolution camera has no prepass (see dynamic_resolution.rs)
+      out.color = main_pass_post_lighting_processing(pbr_input, out.color);
       return out;
 #else
       let depth = bevy_pbr::prepass_utils::prepass_depth(mesh.position, 0);
@@ -63,7 +104,9 @@ fn fragment(
       if depth > curr_pos_depth {
 	  out.color.w = 0.0;
 	}
+      out.color = discard_if_not_blending(out.color);
       return out;
+#endif //DEPTH_PREPASS
 #endif //WEBGL2
     } else {
     var out: FragmentOutput;
@@ -71,288 +114,652 @@ fn fragment(
     let glow_amount = (clamp(min_step_normalized, 0.0, 1.0) * -1.0 + 1.0)
       * raymarch_global_settings.glow_color.w;
     out.color = vec4<f32>(raymarch_global_settings.glow_color.xyz, glow_amount);
+    let object_glow = get_object_glow(march);
+    if object_glow.w > 0.0 {
+	let premultiplied = out.color.xyz * out.color.w + object_glow.xyz;
+	let alpha = clamp(out.color.w + object_glow.w, 0.0, 1.0);
+	out.color = vec4<f32>(premultiplied / max(alpha, 0.0001), alpha);
+      }
+    if has_volumes() {
+	let max_distance = get_scene_distance(mesh.position, ray);
+	let volume = integrate_volumes(ray.origin, ray.dir, max_distance, mesh.position);
+	// the glow is behind the volume, blend it in premultiplied and convert back
+	let premultiplied = out.color.xyz * out.color.w * volume.transmittance + volume.light;
+	let alpha = 1.0 - (1.0 - out.color.w) * volume.transmittance;
+	out.color = vec4<f32>(premultiplied / max(alpha, 0.0001), alpha);
+      }
+    if raymarch_global_settings.dynamic_resolution != 0u {
+	out.color = encode_low_res(out.color, 0.0);
+	return out;
+      }
+    out.color = discard_if_not_blending(out.color);
     return out;
   }
 }
 
+// with dynamic resolution we render into the low resolution target of dynamic_resolution.rs
+// there is no blending in there, so the alpha channel is free to hold the distance for the upsampling:
+// hits -> the distance to the hit (always > 0.0, the surface counts as opaque)
+// misses -> minus the alpha of the glow/volumes
+// rgb is premultiplied with the alpha
+fn encode_low_res(color: vec4<f32>, hit_distance: f32) -> vec4<f32> {
+  if hit_distance > 0.0 {
+      return vec4<f32>(color.xyz, hit_distance);
+    }
+  return vec4<f32>(color.xyz * color.w, -color.w);
+}
+
+// rgb is premultiplied with the alpha
+fn get_object_glow(march: MarchOutput) -> vec4<f32> {
+  var amounts: vec2<f32>;
+  if raymarch_global_settings.glow_mode == 1u {
+      amounts = march.accumulated_glow * 0.05;
+    } else {
+      // only the closest approach, like the global glow
+      let glow_range = max(raymarch_global_settings.glow_range, 0.0001);
+      amounts = 1.0 - clamp(march.min_dist_per_object / glow_range, vec2<f32>(0.0), vec2<f32>(1.0));
+    }
+  let glow1 = amounts.x * object1.glow_intensity * object1.glow_color.w;
+  let glow2 = amounts.y * object2.glow_intensity * object2.glow_color.w;
+  return vec4<f32>(object1.glow_color.xyz * glow1 + object2.glow_color.xyz * glow2,
+		   clamp(glow1 + glow2, 0.0, 1.0));
+}
+
+struct ShadedSurface {
+ pbr_input: PbrInput,
+ desc: RaymarchObjectDescriptor,
+ color: vec4<f32>,
+}
+
+// material blending, patterns, textures and bevys pbr lighting for a point on the surface
+// V points from the surface towards the viewer
+fn shade_surface(hit_pos: vec3<f32>, V: vec3<f32>, frag_coord: vec4<f32>) -> ShadedSurface {
+  let sdf_out = sdf_world(hit_pos);
+  var normal = get_normal_of_surface(hit_pos);
+  let material_lerp_amount = get_material_lerp_amount(sdf_out.distance_to_1object,
+						      sdf_out.distance_to_2object);
+  let patterned1 = apply_pattern(object1, hit_pos);
+  let patterned2 = apply_pattern(object2, hit_pos);
+  let textured1 = apply_object1_textures(patterned1, hit_pos, normal);
+  let textured2 = apply_object2_textures(patterned2, hit_pos, normal);
+  let desc = lerp_descriptors(textured1.desc, textured2.desc, material_lerp_amount);
+  normal = normalize(mix(textured1.normal, textured2.normal, material_lerp_amount));
+
+  var pbr_input = pbr_input_new();
+  pbr_input.frag_coord = frag_coord;
+  pbr_input.material = obj_descriptor_to_material(desc);
+  pbr_input.world_normal = normal;
+  pbr_input.N = normal; // this is also the normal??
+  pbr_input.clearcoat_N = normal;
+  pbr_input.V = V;
+  pbr_input.anisotropy_strength = desc.anisotropy_strength;
+  pbr_input.anisotropy_T = get_anisotropy_tangent(normal, desc.anisotropy_rotation);
+  pbr_input.anisotropy_B = normalize(cross(normal, pbr_input.anisotropy_T));
+  pbr_input.flags = pbr_input.flags | 1u << 29u; // set the MESH_FLAGS_SHADOW_RECEIVER_BIT
+  // pbr_input.world_position = vec4<f32>(hit_pos, 0.0);
+  pbr_input.world_position = vec4<f32>(hit_pos, 1.0);
+  return ShadedSurface(pbr_input, desc, apply_pbr_lighting(pbr_input));
+}
+
+// with specular transmission, bevy needs the material to be in the transmissive pass
+// that one doesn't blend, so main.rs switches to alpha masking and we have to discard ourselves
+// (this also means there is no soft glow while transmission is on)
+fn discard_if_not_blending(color: vec4<f32>) -> vec4<f32> {
+  if object1.specular_transmission <= 0.0 && object2.specular_transmission <= 0.0 {
+      return color;
+    }
+  if color.a < 0.5 {
+      discard;
+    }
+  return vec4<f32>(color.xyz, 1.0);
+}
+
+// anisotropy_rotation is (cos, sin) of the angle in the tangent plane, like in bevy
+// we have no uvs, so the tangent just follows the world y axis
+fn get_anisotropy_tangent(normal: vec3<f32>, rotation: vec2<f32>) -> vec3<f32> {
+  var up = vec3<f32>(0.0, 1.0, 0.0);
+  if abs(normal.y) > 0.99 {
+      up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+  let tangent = normalize(cross(up, normal));
+  let bitangent = cross(normal, tangent);
+  return normalize(tangent * rotation.x + bitangent * rotation.y);
+}
+
+// secondary bounces
+// the lighting for the secondary hits uses the clusters of the original pixel,
+// which is fine as long as there are only a couple of lights
+struct TraceOutput {
+ has_hit: bool,
+ color: vec3<f32>,
+}
+
+// marches reflected and refracted rays through the sdf world, so the objects can see each other
+// if the secondary rays miss we keep what bevy gave us (environment reflections and transmission)
+fn add_secondary_rays(primary: ShadedSurface, ray_dir: vec3<f32>, frag_coord: vec4<f32>) -> vec3<f32> {
+  let N = primary.pbr_input.N;
+  let pos = primary.pbr_input.world_position.xyz;
+  let fresnel = get_surface_fresnel(primary.desc, N, -ray_dir);
+  var color = primary.color.xyz;
+
+  let reflected = trace_reflection(pos, reflect(ray_dir, N), N, frag_coord);
+  if reflected.has_hit {
+      color = mix(color, reflected.color, fresnel);
+    }
+
+  let transmission = primary.desc.specular_transmission;
+  if transmission > 0.0 {
+      let refracted = trace_refraction(pos, ray_dir, N, primary.desc.ior, frag_coord);
+      if refracted.has_hit {
+	  let weight = (vec3<f32>(1.0) - fresnel) * transmission;
+	  color = mix(color, refracted.color * primary.desc.base_color.xyz, weight);
+	}
+    }
+  return color;
+}
+
+// follows the mirror direction for up to bounce_limit surfaces
+fn trace_reflection(start_pos: vec3<f32>,
+		    start_dir: vec3<f32>,
+		    start_normal: vec3<f32>,
+		    frag_coord: vec4<f32>) -> TraceOutput {
+  var out = TraceOutput(false, vec3<f32>(0.0));
+  var throughput = vec3<f32>(1.0);
+  var pos = start_pos;
+  var dir = start_dir;
+  var normal = start_normal;
+  let bounce_limit = raymarch_global_settings.bounce_limit;
+  for (var i = 0u; i < bounce_limit; i++) {
+    let march = march_ray(pos + normal * get_bounce_offset(), dir, false, 0.0);
+    if !march.has_hit {
+      break;
+    }
+    let shaded = shade_surface(march.hit_pos, -dir, frag_coord);
+    normal = shaded.pbr_input.N;
+    var reflectivity = get_surface_fresnel(shaded.desc, normal, -dir);
+    if i + 1u == bounce_limit {
+      // last bounce, nothing left to reflect
+      reflectivity = vec3<f32>(0.0);
+    }
+    out.has_hit = true;
+    out.color += throughput * (vec3<f32>(1.0) - reflectivity) * shaded.color.xyz;
+    throughput *= reflectivity;
+    pos = march.hit_pos;
+    dir = reflect(dir, normal);
+  }
+  return out;
+}
+
+// goes through the object (marching the inside of the sdf) and out the other side
+// keeps going as long as the surfaces it hits are see-through
+fn trace_refraction(start_pos: vec3<f32>,
+		    start_dir: vec3<f32>,
+		    start_normal: vec3<f32>,
+		    start_ior: f32,
+		    frag_coord: vec4<f32>) -> TraceOutput {
+  var out = TraceOutput(false, vec3<f32>(0.0));
+  var pos = start_pos;
+  var normal = start_normal; // always points out of the object
+  var ior = start_ior;
+  var dir = refract(start_dir, normal, 1.0 / ior);
+  for (var i = 0u; i < raymarch_global_settings.bounce_limit; i++) {
+    let inside = march_ray(pos - normal * get_bounce_offset(), dir, true, 0.0);
+    if !inside.has_hit {
+      break;
+    }
+    let exit_normal = get_normal_of_surface(inside.hit_pos);
+    let out_dir = refract(dir, -exit_normal, ior);
+    if dot(out_dir, out_dir) == 0.0 {
+      // total internal reflection, stay inside
+      pos = inside.hit_pos;
+      normal = exit_normal;
+      dir = reflect(dir, -exit_normal);
+      continue;
+    }
+
+    let outside = march_ray(inside.hit_pos + exit_normal * get_bounce_offset(), out_dir, false, 0.0);
+    if !outside.has_hit {
+      break;
+    }
+    let shaded = shade_surface(outside.hit_pos, -out_dir, frag_coord);
+    out.has_hit = true;
+    out.color = shaded.color.xyz;
+    if shaded.desc.specular_transmission <= 0.0 {
+      break;
+    }
+    pos = outside.hit_pos;
+    normal = shaded.pbr_input.N;
+    ior = shaded.desc.ior;
+    dir = refract(out_dir, normal, 1.0 / ior);
+  }
+  return out;
+}
+
+// schlick, with the same F0 as bevy
+fn get_surface_fresnel(desc: RaymarchObjectDescriptor, N: vec3<f32>, V: vec3<f32>) -> vec3<f32> {
+  let dielectric_f0 = 0.16 * desc.reflectance * desc.reflectance;
+  let f0 = mix(dielectric_f0, desc.base_color.xyz, desc.metallic);
+  let NdotV = clamp(dot(N, V), 0.0, 1.0);
+  return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - NdotV, 5.0);
+}
+
+// start the secondary rays a bit away from the surface, otherwise they hit right away
+fn get_bounce_offset() -> f32 {
+  return raymarch_global_settings.termination_distance * 4.0;
+}
+
+// volumes
+// objects with a volume_type_id are not marched as surfaces
+// instead we step through them, sample the density and collect the light scattered towards us
+struct VolumeOutput {
+ // how much of whats behind the volume is still visible
+ transmittance: f32,
+ // already multiplied by the transmittance in front of it
+ light: vec3<f32>,
+}
+
+fn has_volumes() -> bool {
+  return object1.volume_type_id != 0u || object2.volume_type_id != 0u;
+}
+
+fn integrate_volumes(origin: vec3<f32>,
+		     ray_dir: vec3<f32>,
+		     max_distance: f32,
+		     frag_coord: vec4<f32>) -> VolumeOutput {
+  var out = VolumeOutput(1.0, vec3<f32>(0.0));
+  let step = max(raymarch_global_settings.volume_step_size, 0.001);
+  let end = min(max_distance, raymarch_global_settings.far_clip);
+  var t = 0.0;
+  // progressive accumulation: start somewhere in the first step, the banding averages out
+  if raymarch_global_settings.accumulation != 0u {
+    t = step * random2(frag_coord.xy, RANDOM_VOLUME).x;
+  }
+  for (var i = 0; i < 256; i++) {
+    if t >= end || out.transmittance < 0.01 {
+      break;
+    }
+    let p = origin + ray_dir * t;
+
+    // skip the empty space until we are in one of the volumes
+    let d = min(get_volume_distance(p, 1u), get_volume_distance(p, 2u));
+    if d > step {
+      t += d;
+      continue;
+    }
+
+    let sample1 = sample_volume(p, 1u);
+    let sample2 = sample_volume(p, 2u);
+    let absorption = sample1.absorption + sample2.absorption;
+    let scattering = sample1.scattering + sample2.scattering;
+    let extinction = absorption + scattering;
+    if extinction > 0.0 {
+      // average the phase over both volumes, weighted by how much they scatter
+      let g = (sample1.scattering * object1.volume_phase_g + sample2.scattering * object2.volume_phase_g)
+	/ max(scattering, 0.0001);
+      let albedo = (sample1.scattering * object1.base_color.xyz + sample2.scattering * object2.base_color.xyz)
+	/ max(scattering, 0.0001);
+      let in_light = get_volume_light(p, ray_dir, g, frag_coord) * albedo * scattering
+	+ sample1.emission + sample2.emission;
+
+      // energy conserving integration over the step, see:
+      // https://www.ea.com/frostbite/news/physically-based-unified-volumetric-rendering-in-frostbite
+      let step_transmittance = exp(-extinction * step);
+      out.light += out.transmittance * (in_light - in_light * step_transmittance) / extinction;
+      out.transmittance *= step_transmittance;
+    }
+    t += step;
+  }
+  return out;
+}
+
+// distance to the volume, or something big if the object isn't a volume
+// object_index is 1 or 2, like in sdf_object
+fn get_volume_distance(p: vec3<f32>, object_index: u32) -> f32 {
+  let obj = get_object(object_index);
+  if obj.volume_type_id == 0u {
+      return 100000.0;
+    }
+  return sdf_object(translate_ray(p, obj), object_index);
+}
+
+struct VolumeSample {
+ absorption: f32,
+ scattering: f32,
+ emission: vec3<f32>,
+}
+
+fn sample_volume(p: vec3<f32>, object_index: u32) -> VolumeSample {
+  let obj = get_object(object_index);
+  let density = get_volume_density(p, object_index);
+  return VolumeSample(density * obj.volume_absorption,
+		      density * obj.volume_scattering,
+		      density * obj.emissive.xyz);
+}
+
+fn get_volume_density(p: vec3<f32>, object_index: u32) -> f32 {
+  let obj = get_object(object_index);
+  if obj.volume_type_id == 0u {
+      return 0.0;
+    }
+  let object_pos = translate_ray(p, obj);
+  let d = sdf_object(object_pos, object_index);
+  if d > 0.0 {
+      return 0.0;
+    }
+
+  if obj.volume_type_id == 1u {
+      // constant
+      return obj.volume_density;
+    } else if obj.volume_type_id == 2u {
+      // noise, slowly drifting upwards
+      let drift = vec3<f32>(0.0, raymarch_global_settings.time * 0.1, 0.0);
+      let noise = fbm((object_pos - drift) * obj.volume_noise_scale);
+      return obj.volume_density * clamp(noise * 2.0 - 0.5, 0.0, 1.0);
+    } else if obj.volume_type_id == 3u {
+      // thickens towards the inside of the sdf
+      return obj.volume_density * clamp(-d / max(obj.volume_falloff, 0.0001), 0.0, 1.0);
+    }
+  return 0.0;
+}
+
+// light arriving at p and scattered into -ray_dir
+// uses the same lights as bevys pbr, but the clusters of the original pixel
+fn get_volume_light(p: vec3<f32>, ray_dir: vec3<f32>, g: f32, frag_coord: vec4<f32>) -> vec3<f32> {
+  var light = lights.ambient_color.xyz;
+
+  for (var i = 0u; i < lights.n_directional_lights; i++) {
+    let directional = lights.directional_lights[i];
+    let to_light = directional.direction_to_light;
+    light += directional.color.xyz
+      * henyey_greenstein(dot(ray_dir, to_light), g)
+      * get_volume_shadow(p, to_light, 2.0);
+  }
+
+  let view_z = dot(vec4<f32>(view.view_from_world[0].z,
+			     view.view_from_world[1].z,
+			     view.view_from_world[2].z,
+			     view.view_from_world[3].z),
+		   vec4<f32>(p, 1.0));
+  let is_orthographic = view.clip_from_view[3].w == 1.0;
+  let cluster_index = clustered_forward::fragment_cluster_index(frag_coord.xy, view_z, is_orthographic);
+  let ranges = clustered_forward::unpack_clusterable_object_index_ranges(cluster_index);
+  for (var i = ranges.first_point_light_index_offset; i < ranges.first_spot_light_index_offset; i++) {
+    let light_id = clustered_forward::get_clusterable_object_id(i);
+    let point_light = clusterable_objects.data[light_id];
+    let light_offset = point_light.position_radius.xyz - p;
+    let distance_squared = dot(light_offset, light_offset);
+    let to_light = light_offset * inverseSqrt(distance_squared);
+    light += point_light.color_inverse_square_range.xyz
+      * getDistanceAttenuation(distance_squared, point_light.color_inverse_square_range.w)
+      * henyey_greenstein(dot(ray_dir, to_light), g)
+      * get_volume_shadow(p, to_light, sqrt(distance_squared));
+  }
+  return light * view.exposure;
+}
+
+// cos_theta is between the ray direction and the direction to the light
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+  let g2 = g * g;
+  let denom = 1.0 + g2 - 2.0 * g * cos_theta;
+  return (1.0 - g2) / (4.0 * PI * denom * sqrt(denom));
+}
+
+// how much light makes it from the light through the volumes to p
+// only a couple of steps, this runs for every volume sample
+fn get_volume_shadow(p: vec3<f32>, to_light: vec3<f32>, max_distance: f32) -> f32 {
+  let step = min(max_distance, 1.0) / 6.0;
+  var optical_depth = 0.0;
+  for (var i = 1; i <= 6; i++) {
+    let sample_pos = p + to_light * step * f32(i);
+    let sample1 = sample_volume(sample_pos, 1u);
+    let sample2 = sample_volume(sample_pos, 2u);
+    optical_depth += (sample1.absorption + sample1.scattering + sample2.absorption + sample2.scattering) * step;
+  }
+  return exp(-optical_depth);
+}
+
+// distance to whatever is already in the depth buffer, so volumes don't draw over things in front
+fn get_scene_distance(frag_coord: vec4<f32>, ray: CameraRay) -> f32 {
+#ifdef WEBGL2
+  return raymarch_global_settings.far_clip;
+#else
+#ifndef DEPTH_PREPASS
+  // no prepass in this view, nothing else to run into
+  return raymarch_global_settings.far_clip;
+#else
+  let depth = bevy_pbr::prepass_utils::prepass_depth(frag_coord, 0);
+  if depth <= 0.0 {
+      return raymarch_global_settings.far_clip;
+    }
+  let ndc = vec2<f32>(coords_to_viewport_uv(frag_coord.xy, view.viewport) * 2.0 - 1.0) * vec2<f32>(1.0, -1.0);
+  var world = view.world_from_clip * vec4<f32>(ndc, depth, 1.0);
+  world /= world.w;
+  return dot(world.xyz - ray.origin, ray.dir);
+#endif //DEPTH_PREPASS
+#endif //WEBGL2
+}
+
 // depending if it has_hit some data is left empty/useless
 // unions would go crazy here
 struct MarchOutput {
  has_hit: bool,
  hit_pos: vec3<f32>,
  min_dist_from_object: f32,
+ // for the per object glow, x -> object1, y -> object2
+ min_dist_per_object: vec2<f32>,
+ accumulated_glow: vec2<f32>,
 };
 fn perform_march(
+		 ray: CameraRay,
 		 coord: vec2<f32>,
 		 // sample_index: u32,
 ) -> MarchOutput {
-  // get the Ray direction
-  let cam_pos = view.world_position;
-  var viewport_uv = coords_to_viewport_uv(coord, view.viewport) * 2.0 - 1.0;
-  viewport_uv.y *= -1;
-  let clip = vec4<f32>(viewport_uv, 1.0, 1.0);
-  var world = view.world_from_clip * clip;
-  world /= world.w;
-  let ray_dir = normalize(world.xyz - cam_pos);
-
-  // start the marching
-  var curr_pos = cam_pos;
-  var dist_marched = 0.0;
-  var min_step_length = 1000.0; // TODO: change to +inf
-  while dist_marched < raymarch_global_settings.far_clip {
-      let sdf_out = sdf_world(curr_pos);
-      if my_min(sdf_out.distance_to_1object, sdf_out.distance_to_2object)
-		 < raymarch_global_settings.termination_distance {
-	  // HIT!
-	  return MarchOutput(true, curr_pos, 0.0);
-	}
+  return march_ray(ray.origin, ray.dir, false, get_start_distance(coord));
+}
 
-      // no hit yet, continue marching..
-      var step: vec3<f32>;
-      let step_min_distance = my_min(sdf_out.distance_to_1object,
-				     sdf_out.distance_to_2object);
-      step = ray_dir * step_min_distance;
-      let step_length = length(step);
-      if step_length < min_step_length {
-	  min_step_length = step_length;
-	}
-      dist_marched += step_length;
-      curr_pos += step;
+// the cone prepass already marched this tile, everything before that distance is empty
+// (accumulated glow only starts counting from there)
+// its cones start at the camera position, that only works for a perspective pinhole camera
+fn get_start_distance(coord: vec2<f32>) -> f32 {
+  let settings = raymarch_global_settings;
+  if settings.cone_prepass == 0u || settings.aperture > 0.0 || is_orthographic() {
+      return 0.0;
     }
-
-  // no hit :c
-  return MarchOutput(false, vec3<f32>(0.0), min_step_length);
-}
-
-struct SdfOutput {
- distance_to_1object: f32,
- distance_to_2object: f32,
-}
-fn sdf_world(ray_position: vec3<f32>) -> SdfOutput {
-  let rp1 = translate_ray(ray_position, object1);
-  let rp2 = translate_ray(ray_position, object2);
-
-  let distance_to_1object = sdf_object(rp1, object1);
-  let distance_to_2object = sdf_object(rp2, object2);
-  return SdfOutput(distance_to_1object, distance_to_2object);
-}
-
-fn sdf_object(ray_position: vec3<f32>, obj: RaymarchObjectDescriptor) -> f32 {
-  if obj.shape_type_id == 1 {
-      return sdf_circle(ray_position, obj.shape_var);
-    } else if obj.shape_type_id == 2 {
-      return sdBox(ray_position, vec3<f32>(obj.shape_var));
-    } else if obj.shape_type_id == 3 {
-      // for some reason, the cone default position is really low
-      // fix it here
-      var rp_higher = ray_position;
-      rp_higher.y -= 0.25;
-      return sdConeBound(rp_higher,
-			 obj.shape_var,
-			 vec2<f32>(sin(obj.scale),
-				   cos(obj.scale)));
-    } else if obj.shape_type_id == 4 {
-      return sdfMandel(ray_position, obj.shape_var);
-    }
-  return 100000.0;
-}
-
-//   ,--.                                 ,---.                                  ,--.  ,--.
-// ,-'  '-.,--.--. ,--,--.,--,--,  ,---. /  .-' ,---. ,--.--.,--,--,--. ,--,--.,-'  '-.`--' ,---. ,--,--,
-// '-.  .-'|  .--'' ,-.  ||      \(  .-' |  `-,| .-. ||  .--'|        |' ,-.  |'-.  .-',--.| .-. ||      \
-//   |  |  |  |   \ '-'  ||  ||  |.-'  `)|  .-'' '-' '|  |   |  |  |  |\ '-'  |  |  |  |  |' '-' '|  ||  |
-//   `--'  `--'    `--`--'`--''--'`----' `--'   `---' `--'   `--`--`--' `--`--'  `--'  `--' `---' `--''--'
-// transformation
-// TODO: implement y and z euler angles
-fn translate_ray(r: vec3<f32>, obj: RaymarchObjectDescriptor) -> vec3<f32> {
-  var out = r;
-  // translation
-  var added_translation = vec3<f32>(0.0);
-  added_translation.x = sin(raymarch_global_settings.time * 0.5) * obj.move_amount;
-  added_translation.y = cos(raymarch_global_settings.time) * obj.move_amount;
-  added_translation.z = cos(raymarch_global_settings.time) * obj.move_amount * 0.2;
-  out -= obj.world_position - added_translation;
-
-  // rotation
-  let added_rotation = obj.rotation_amount * raymarch_global_settings.time;
-  out = (vec4<f32>(out, 1.0) * rotation_mat_x(obj.rotation.x + added_rotation)).xyz;
-
-  // scale
-  return out;
+  let max_tile = vec2<i32>(textureDimensions(cone_prepass_texture)) - 1;
+  let tile = clamp(vec2<i32>(coord / CONE_PREPASS_TILE_SIZE), vec2<i32>(0), max_tile);
+  return textureLoad(cone_prepass_texture, tile, 0).x;
 }
 
-fn rotation_mat_x(angle_x: f32) -> mat4x4<f32> {
-  return mat4x4<f32>(
-		     vec4<f32>(1.0, 0.0, 0.0, 0.0),
-		     vec4<f32>(0.0, cos(angle_x), sin(angle_x), 0.0),
-		     vec4<f32>(0.0, -sin(angle_x), cos(angle_x), 0.0),
-		     vec4<f32>(0.0, 0.0, 0.0, 1.0));
+struct CameraRay {
+ origin: vec3<f32>,
+ dir: vec3<f32>,
 }
 
+// perspective -> all rays start at the camera
+// orthographic -> all rays go straight ahead, from where the pixel is on the near plane
+// with an aperture, this is a thin lens: the ray starts at a random point on the lens and goes
+// through the point on the focus plane the pinhole ray would have hit
+// the point on the lens changes every frame, the accumulation (accumulation.rs) averages them
+// progressive accumulation also moves the ray around inside of the pixel, which averages to AA
+fn get_camera_ray(frag_coord: vec2<f32>) -> CameraRay {
+  var coord = frag_coord;
+  if raymarch_global_settings.accumulation != 0u {
+      coord += random2(frag_coord, RANDOM_PIXEL) - 0.5;
+    }
+  var viewport_uv = coords_to_viewport_uv(coord, view.viewport) * 2.0 - 1.0;
+  viewport_uv.y *= -1;
+  // reversed z, the near plane is at 1.0
+  var near = view.world_from_clip * vec4<f32>(viewport_uv, 1.0, 1.0);
+  near /= near.w;
+  let forward = -view.world_from_view[2].xyz;
+  var ray = CameraRay(view.world_position, normalize(near.xyz - view.world_position));
+  if is_orthographic() {
+      ray = CameraRay(near.xyz, forward);
+    }
 
-fn my_min(a: f32, b: f32) -> f32 {
-  if raymarch_global_settings.intersection_method == 0 {
-      return opSmoothUnion(a, b, raymarch_global_settings.intersection_smooth_amount);
-    } else if raymarch_global_settings.intersection_method == 1 {
-      return opSmoothIntersect(a, b, raymarch_global_settings.intersection_smooth_amount);
-    } else if raymarch_global_settings.intersection_method == 2 {
-      return opSmoothSubtract(a, b, raymarch_global_settings.intersection_smooth_amount);
+  let settings = raymarch_global_settings;
+  if settings.aperture <= 0.0 {
+      return ray;
     }
-  return 100000.0; // Todo: +inf
+  // the focus plane is flat, so rays at the edge of the screen go a bit further
+  let focus_point = ray.origin + ray.dir * settings.focus_distance / dot(ray.dir, forward);
+  let lens = sample_disk(random2(frag_coord, RANDOM_LENS)) * settings.aperture * 0.5;
+  let origin = ray.origin + view.world_from_view[0].xyz * lens.x
+    + view.world_from_view[1].xyz * lens.y;
+  return CameraRay(origin, normalize(focus_point - origin));
 }
 
-
-//  ,---.  ,------.  ,------.    ,------.                        ,--.  ,--.
-// '   .-' |  .-.  \ |  .---'    |  .---',--.,--.,--,--,  ,---.,-'  '-.`--' ,---. ,--,--,  ,---.
-// `.  `-. |  |  \  :|  `--,     |  `--, |  ||  ||      \| .--''-.  .-',--.| .-. ||      \(  .-'
-// .-'    ||  '--'  /|  |`       |  |`   '  ''  '|  ||  |\ `--.  |  |  |  |' '-' '|  ||  |.-'  `)
-// `-----' `-------' `--'        `--'     `----' `--''--' `---'  `--'  `--' `---' `--''--'`----'
-// I got them from: https://gist.github.com/munrocket/f247155fc22ecb8edf974d905c677de1
-fn sdf_circle(p: vec3<f32>, rad: f32) -> f32 {
-  return length(p) - rad;
+fn is_orthographic() -> bool {
+  return view.clip_from_view[3].w == 1.0;
 }
 
-fn sdBox(p: vec3f, b: vec3f) -> f32 {
-  let q = abs(p) - b;
-  return length(max(q, vec3f(0.))) + min(max(q.x, max(q.y, q.z)), 0.);
+// uniform point in the unit disk
+fn sample_disk(random: vec2<f32>) -> vec2<f32> {
+  let angle = random.x * 2.0 * PI;
+  return vec2<f32>(cos(angle), sin(angle)) * sqrt(random.y);
 }
 
-fn sdConeBound(p: vec3f, h: f32, sincos: vec2f) -> f32 {
-  return max(dot(sincos.yx, vec2f(length(p.xz), p.y)), -h - p.y);
-}
+// every use of random2 gets its own stream, so they don't line up
+const RANDOM_LENS = 0u;
+const RANDOM_PIXEL = 1u;
+const RANDOM_VOLUME = 2u;
 
-fn opSmoothUnion(d1: f32, d2: f32, k: f32) -> f32 {
-  let h = clamp(0.5 + 0.5 * (d2 - d1) / k, 0., 1.);
-  return mix(d2, d1, h) - k * h * (1. - h);
+// two random numbers 0.0-1.0, different for every pixel and frame
+// pcg hash, from "Hash Functions for GPU Rendering" (Jarzynski and Olano 2020)
+fn random2(coord: vec2<f32>, stream: u32) -> vec2<f32> {
+  let pixel = vec2<u32>(coord);
+  let frame = pcg_hash(globals.frame_count + pcg_hash(stream));
+  let seed = pcg_hash(pixel.x + pcg_hash(pixel.y + frame));
+  return vec2<f32>(f32(seed), f32(pcg_hash(seed))) / 4294967295.0;
 }
 
-fn opSmoothSubtract(d1: f32, d2: f32, k: f32) -> f32 {
-  let h = clamp(0.5 - 0.5 * (d1 + d2) / k, 0., 1.);
-  return mix(d1, -d2, h) + k * h * (1. - h);
+fn pcg_hash(input: u32) -> u32 {
+  let state = input * 747796405u + 2891336453u;
+  let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
 }
 
-fn opSmoothIntersect(d1: f32, d2: f32, k: f32) -> f32 {
-  let h = clamp(0.5 - 0.5 * (d2 - d1) / k, 0., 1.);
-  return mix(d2, d1, h) + k * h * (1. - h);
-}
+// inside -> march the negative sdf, to find where the ray leaves the object
+// start_distance -> how far along the ray it's known to be empty
+fn march_ray(origin: vec3<f32>,
+	     ray_dir: vec3<f32>,
+	     inside: bool,
+	     start_distance: f32) -> MarchOutput {
+  var sdf_sign = 1.0;
+  if inside {
+      sdf_sign = -1.0;
+    }
 
-fn smin_circular(a: f32, b: f32, k: f32) -> f32 {
-  let km = k * (1.0/(1.0-sqrt(0.5)));
-  let h = max(k - abs(a-b), 0.0) / k;
-  return min(a,b) - k * 0.5 * (1.0 + h - sqrt(1.0 - h * (h - 2.0)));
-}
+  // start the marching
+  let settings = raymarch_global_settings;
+  var curr_pos = origin;
+  var dist_marched = start_distance;
+  var min_step_length = 1000.0; // TODO: change to +inf
+  var min_dist_per_object = vec2<f32>(1000.0);
+  var accumulated_glow = vec2<f32>(0.0);
+  let glow_range = max(settings.glow_range, 0.0001);
 
-fn opScale(p: vec3f, s: f32) -> vec3f {
-  return p / s;
+  // over-relaxed sphere tracing, from "Enhanced Sphere Tracing" (Keinert et al. 2014)
+  // we step further than the sdf says, and go back if the spheres of two steps don't overlap
+  var omega = max(settings.over_relaxation, 1.0);
+  var previous_radius = 0.0;
+  var step_length = 0.0;
+  for (var i = 0u; i < settings.max_iterations; i++) {
+      if dist_marched >= settings.far_clip {
+	  break;
+	}
+      curr_pos = origin + ray_dir * dist_marched;
+      let sdf_out = sdf_world(curr_pos);
+      let distances = vec2<f32>(sdf_out.distance_to_1object, sdf_out.distance_to_2object);
+      let signed_distance = my_min(sdf_out.distance_to_1object, sdf_out.distance_to_2object) * sdf_sign;
+
+      if omega > 1.0 && abs(signed_distance) + previous_radius < step_length {
+	  // we might have jumped over something
+	  // go back to where normal sphere tracing would have taken us and stop relaxing
+	  dist_marched -= step_length - previous_radius;
+	  step_length = previous_radius;
+	  omega = 1.0;
+	  continue;
+	}
+
+      min_dist_per_object = min(min_dist_per_object, distances);
+      // inverse square falloff, capped at 1.0 per step
+      let relative_distances = distances / glow_range;
+      accumulated_glow += 1.0 / (1.0 + relative_distances * relative_distances);
+
+      // far away, a pixel covers more space, so we can stop earlier
+      let termination_distance = max(settings.termination_distance,
+				     get_pixel_size(dist_marched) * settings.termination_cone_scale);
+      if signed_distance < termination_distance {
+	  // HIT!
+	  curr_pos = refine_hit(curr_pos, ray_dir, sdf_sign);
+	  return MarchOutput(true, curr_pos, 0.0, min_dist_per_object, accumulated_glow);
+	}
+
+      // no hit yet, continue marching..
+      if signed_distance < min_step_length {
+	  min_step_length = signed_distance;
+	}
+      previous_radius = signed_distance;
+      step_length = signed_distance * omega;
+      dist_marched += step_length;
+    }
+
+  // no hit :c
+  return MarchOutput(false, vec3<f32>(0.0), min_step_length, min_dist_per_object, accumulated_glow);
 }
 
-// https://github.com/zordone/fractal-webgpu/blob/main/madelbulb/shaders.wgsl
-fn sdfMandel(point0: vec3<f32>, power: f32) -> f32 {
-  // the mandelbulb is at scene scene center. translate to origin.
-  let point = point0;
-  // params
-  //let blob = 1 - uniforms.blob;
-  //let spike = uniforms.spike * PI / 2;
-  let blob = 1 - 0.0;
-  let spike = 0.0 * PI / 2;
- // iterate to find distance
-  var z = point;
-  var dr = 1.0;
-  var dist: f32;
-  for (var step = 0; step < 16; step++) {
-    dist = length(z);
-    if (dist > BAILOUT) { break; }
-    // to polar coordinates
-    let theta = acos(z.z / dist) * power * blob;
-    let phi = atan2(z.y, z.x) * power;
-    // scale and rotate
-    let distPowMinusOne = pow(dist, power - 1.0);
-    let zr = distPowMinusOne * dist;
-    dr = distPowMinusOne * power * dr + 1.0;
-    // back to cartesian coordinates
-    let sinTheta = sin(theta);
-    z = zr * vec3<f32>(sinTheta * cos(phi), sin(phi + spike) * sinTheta, cos(theta));
-    z += point;
+// a couple of extra plain sphere tracing steps after a hit, to get closer to the actual surface
+fn refine_hit(hit_pos: vec3<f32>, ray_dir: vec3<f32>, sdf_sign: f32) -> vec3<f32> {
+  var pos = hit_pos;
+  for (var i = 0u; i < raymarch_global_settings.hit_refinement_steps; i++) {
+    let sdf_out = sdf_world(pos);
+    pos += ray_dir * my_min(sdf_out.distance_to_1object, sdf_out.distance_to_2object) * sdf_sign;
   }
-  return 0.5 * log(dist) * dist / dr;
+  return pos;
 }
 
-
-
-// stolen from https://github.com/rust-adventure/bevy-examples/blob/fabbb45b5c6adbfc8d317c95fcd9097b08666c7c/examples/raymarch-sphere/assets/shaders/sdf.wgsl#L160
-fn get_normal_of_surface(position_of_hit: vec3<f32>) -> vec3<f32> {
-  let tiny_change_x = vec3(0.001, 0.0, 0.0);
-  let tiny_change_y = vec3(0.0 , 0.001 , 0.0);
-  let tiny_change_z = vec3(0.0 , 0.0 , 0.001);
-
-  let up_tiny_change_in_x: f32 = my_min(sdf_world(position_of_hit + tiny_change_x).distance_to_1object,
-					sdf_world(position_of_hit + tiny_change_x).distance_to_2object);
-  let down_tiny_change_in_x: f32 = my_min(sdf_world(position_of_hit - tiny_change_x).distance_to_1object,
-					  sdf_world(position_of_hit - tiny_change_x).distance_to_2object);
-  let tiny_change_in_x: f32 = up_tiny_change_in_x - down_tiny_change_in_x;
-
-  let up_tiny_change_in_y: f32 = my_min(sdf_world(position_of_hit + tiny_change_y).distance_to_1object,
-					sdf_world(position_of_hit + tiny_change_y).distance_to_2object);
-  let down_tiny_change_in_y: f32 = my_min(sdf_world(position_of_hit - tiny_change_y).distance_to_1object,
-					  sdf_world(position_of_hit - tiny_change_y).distance_to_2object);
-  let tiny_change_in_y: f32 = up_tiny_change_in_y - down_tiny_change_in_y;
-
-  let up_tiny_change_in_z: f32 = my_min(sdf_world(position_of_hit + tiny_change_z).distance_to_1object,
-					       sdf_world(position_of_hit + tiny_change_z).distance_to_2object);
-  let down_tiny_change_in_z: f32 = my_min(sdf_world(position_of_hit - tiny_change_z).distance_to_1object,
-					  sdf_world(position_of_hit - tiny_change_z).distance_to_2object);
-  let tiny_change_in_z: f32 = up_tiny_change_in_z - down_tiny_change_in_z;
-
-
-  let normal_dir = vec3(tiny_change_in_x,
-			tiny_change_in_y,
-			tiny_change_in_z);
-
-  return normalize(normal_dir);
-}
-
-//                 ,--. ,---.
-// ,--.,--.,--,--, `--'/  .-' ,---. ,--.--.,--,--,--. ,---.
-// |  ||  ||      \,--.|  `-,| .-. ||  .--'|        |(  .-'
-// '  ''  '|  ||  ||  ||  .-'' '-' '|  |   |  |  |  |.-'  `)
-//  `----' `--''--'`--'`--'   `---' `--'   `--`--`--'`----'
-// uniforms
-struct RaymarchObjectDescriptor {
- world_position: vec3<f32>,
- rotation: vec3<f32>,
- move_amount: f32,
- rotation_amount: f32,
- shape_type_id: u32,
- shape_var: f32,
- scale: f32,
- base_color: vec4<f32>,
- emissive: vec4<f32>,
- reflectance: vec3<f32>,
- perceptual_roughness: f32,
- metallic: f32,
- diffuse_transmission: f32,
- specular_transmission: f32,
- thickness: f32,
- ior: f32,
- attenuation_distance: f32,
- attenuation_color: vec4<f32>,
- clearcoat: f32,
- clearcoat_perceptual_roughness: f32,
- anisotropy_strength: f32,
- anisotropy_rotation: vec2<f32>,
-}
-
-struct RaymarchGlobalSettings {
- intersection_method: u32,
- intersection_smooth_amount: f32,
- glow_range: f32,
- glow_color: vec4<f32>,
- far_clip: f32,
- termination_distance: f32,
- time: f32,
+// how big one pixel is at this distance from the camera
+fn get_pixel_size(distance: f32) -> f32 {
+  // perspective: clip_from_view[1][1] is 1 / tan(fov_y / 2)
+  // orthographic: it's 2 / the height of the view, and pixels are the same size everywhere
+  let pixel_size = 2.0 / (view.clip_from_view[1][1] * view.viewport.w);
+  if is_orthographic() {
+      return pixel_size;
+    }
+  return distance * pixel_size;
 }
 
+// 0 -> tetrahedron, 1 -> central differences, 2 -> forward differences (normal_method)
+fn get_normal_of_surface(position_of_hit: vec3<f32>) -> vec3<f32> {
+  let settings = raymarch_global_settings;
+  // far away, a pixel covers more space. a too small epsilon just picks up noise there (mandelbulb)
+  let distance_to_camera = distance(view.world_position, position_of_hit);
+  let e = max(settings.normal_epsilon,
+	      get_pixel_size(distance_to_camera) * settings.normal_epsilon_scale);
 
+  if settings.normal_method == 1u {
+      // central differences, 6 samples
+      let h = vec2<f32>(e, 0.0);
+      return normalize(vec3<f32>(sdf_world_min(position_of_hit + h.xyy) - sdf_world_min(position_of_hit - h.xyy),
+				 sdf_world_min(position_of_hit + h.yxy) - sdf_world_min(position_of_hit - h.yxy),
+				 sdf_world_min(position_of_hit + h.yyx) - sdf_world_min(position_of_hit - h.yyx)));
+    } else if settings.normal_method == 2u {
+      // forward differences, 4 samples, a bit biased
+      let h = vec2<f32>(e, 0.0);
+      let center = sdf_world_min(position_of_hit);
+      return normalize(vec3<f32>(sdf_world_min(position_of_hit + h.xyy) - center,
+				 sdf_world_min(position_of_hit + h.yxy) - center,
+				 sdf_world_min(position_of_hit + h.yyx) - center));
+    }
+  // tetrahedron technique, 4 samples
+  // from: https://iquilezles.org/articles/normalsSDF/
+  let k = vec2<f32>(1.0, -1.0);
+  return normalize(k.xyy * sdf_world_min(position_of_hit + k.xyy * e)
+		   + k.yyx * sdf_world_min(position_of_hit + k.yyx * e)
+		   + k.yxy * sdf_world_min(position_of_hit + k.yxy * e)
+		   + k.xxx * sdf_world_min(position_of_hit + k.xxx * e));
+}
 
 //                     ,--.                ,--.        ,--.
 // ,--,--,--. ,--,--.,-'  '-. ,---. ,--.--.`--' ,--,--.|  | ,---.
@@ -392,6 +799,227 @@ fn lerp_descriptors(desc1: RaymarchObjectDescriptor,
 }
 
 
+// how much of object2's material to use at a point with these distances
+// 0.0 -> just object1, 1.0 -> just object2 (same as lerp_val in lerp_descriptors)
+fn get_material_lerp_amount(d1: f32, d2: f32) -> f32 {
+  let mode = raymarch_global_settings.material_blend_mode;
+  if mode == 0 {
+      // the old way: smears even when the objects are far apart
+      let distances = normalize(vec2<f32>(d1, d2));
+      return ((distances.x - distances.y) + 1.0) * 0.5;
+    } else if mode == 1 {
+      return material_blend_weight(d1, d2, 0.0);
+    } else if mode == 2 {
+      return material_blend_weight(d1, d2, raymarch_global_settings.intersection_smooth_amount);
+    } else if mode == 3 {
+      return material_blend_weight(d1, d2, raymarch_global_settings.material_blend_width);
+    }
+  return 0.0;
+}
+
+// this is the h factor of the smooth operators, but flipped so it's the weight of object2
+// with k = 0 this is a hard switch to whatever object is the surface
+fn material_blend_weight(d1: f32, d2: f32, k: f32) -> f32 {
+  let safe_k = max(k, 0.00001); // don't divide by zero
+  // same as my_min, only the solid object has a surface
+  if object1.volume_type_id != 0u {
+      return 1.0;
+    }
+  if object2.volume_type_id != 0u {
+      return 0.0;
+    }
+#if INTERSECTION_METHOD == 0
+  return 1.0 - clamp(0.5 + 0.5 * (d2 - d1) / safe_k, 0., 1.);
+#else if INTERSECTION_METHOD == 1
+  return 1.0 - clamp(0.5 - 0.5 * (d2 - d1) / safe_k, 0., 1.);
+#else if INTERSECTION_METHOD == 2
+  return clamp(0.5 - 0.5 * (d1 + d2) / safe_k, 0., 1.);
+#else
+  return 0.0;
+#endif
+}
+
+// textures
+struct TexturedObject {
+ desc: RaymarchObjectDescriptor,
+ normal: vec3<f32>,
+}
+
+// you can't put textures into arrays or structs, so each object gets its own function
+fn apply_object1_textures(desc: RaymarchObjectDescriptor,
+			  world_pos: vec3<f32>,
+			  world_normal: vec3<f32>) -> TexturedObject {
+  return apply_textures(desc, world_pos, world_normal,
+			object1_base_color_texture, object1_base_color_sampler,
+			object1_metallic_roughness_texture, object1_metallic_roughness_sampler,
+			object1_normal_map_texture, object1_normal_map_sampler);
+}
+
+fn apply_object2_textures(desc: RaymarchObjectDescriptor,
+			  world_pos: vec3<f32>,
+			  world_normal: vec3<f32>) -> TexturedObject {
+  return apply_textures(desc, world_pos, world_normal,
+			object2_base_color_texture, object2_base_color_sampler,
+			object2_metallic_roughness_texture, object2_metallic_roughness_sampler,
+			object2_normal_map_texture, object2_normal_map_sampler);
+}
+
+// everything is sampled in object space, so the textures move along with the object
+fn apply_textures(obj: RaymarchObjectDescriptor,
+		  world_pos: vec3<f32>,
+		  world_normal: vec3<f32>,
+		  base_color_texture: texture_2d<f32>,
+		  base_color_sampler: sampler,
+		  metallic_roughness_texture: texture_2d<f32>,
+		  metallic_roughness_sampler: sampler,
+		  normal_map_texture: texture_2d<f32>,
+		  normal_map_sampler: sampler) -> TexturedObject {
+  var out = TexturedObject(obj, world_normal);
+  if obj.texture_flags == 0u {
+      return out;
+    }
+
+  let p = translate_ray(world_pos, obj) * obj.texture_scale;
+  let n = normalize(rotate_dir_to_object(world_normal, obj));
+  let weights = get_triplanar_weights(n, obj.texture_blend_sharpness);
+
+  if (obj.texture_flags & 1u) != 0u {
+      out.desc.base_color *= triplanar_sample(base_color_texture, base_color_sampler, p, weights);
+    }
+  if (obj.texture_flags & 2u) != 0u {
+      let mr = triplanar_sample(metallic_roughness_texture, metallic_roughness_sampler, p, weights);
+      out.desc.perceptual_roughness *= mr.g;
+      out.desc.metallic *= mr.b;
+    }
+  if (obj.texture_flags & 4u) != 0u {
+      let object_normal = triplanar_normal(normal_map_texture, normal_map_sampler, p, n, weights);
+      out.normal = normalize(rotate_dir_to_world(object_normal, obj));
+    }
+  return out;
+}
+
+fn get_triplanar_weights(n: vec3<f32>, sharpness: f32) -> vec3<f32> {
+  let w = pow(abs(n), vec3<f32>(sharpness));
+  return w / (w.x + w.y + w.z);
+}
+
+// we are inside of the "if has_hit" branch, so we can't use textureSample (no derivatives there)
+fn triplanar_sample(t: texture_2d<f32>, s: sampler, p: vec3<f32>, w: vec3<f32>) -> vec4<f32> {
+  let x = textureSampleLevel(t, s, fract(p.zy), 0.0);
+  let y = textureSampleLevel(t, s, fract(p.xz), 0.0);
+  let z = textureSampleLevel(t, s, fract(p.xy), 0.0);
+  return x * w.x + y * w.y + z * w.z;
+}
+
+// whiteout blend, from: https://bgolus.medium.com/normal-mapping-for-a-triplanar-shader-10bf39dca05a
+fn triplanar_normal(t: texture_2d<f32>,
+		    s: sampler,
+		    p: vec3<f32>,
+		    n: vec3<f32>,
+		    w: vec3<f32>) -> vec3<f32> {
+  var tx = textureSampleLevel(t, s, fract(p.zy), 0.0).xyz * 2.0 - 1.0;
+  var ty = textureSampleLevel(t, s, fract(p.xz), 0.0).xyz * 2.0 - 1.0;
+  var tz = textureSampleLevel(t, s, fract(p.xy), 0.0).xyz * 2.0 - 1.0;
+  tx = vec3<f32>(tx.xy + n.zy, abs(tx.z) * n.x);
+  ty = vec3<f32>(ty.xy + n.xz, abs(ty.z) * n.y);
+  tz = vec3<f32>(tz.xy + n.xy, abs(tz.z) * n.z);
+  return normalize(tx.zyx * w.x + ty.xzy * w.y + tz.xyz * w.z);
+}
+
+// procedural patterns
+// like the textures, these are in object space
+fn apply_pattern(obj: RaymarchObjectDescriptor, world_pos: vec3<f32>) -> RaymarchObjectDescriptor {
+  var out = obj;
+  if obj.pattern_type_id == 0u {
+      return out;
+    }
+  let p = translate_ray(world_pos, obj) * obj.pattern_scale;
+  var t = 0.0;
+  if obj.pattern_type_id == 1u {
+      // checker
+      let cell = floor(p.x) + floor(p.y) + floor(p.z);
+      t = step(0.5, fract(cell * 0.5));
+    } else if obj.pattern_type_id == 2u {
+      // stripes
+      t = step(0.5, fract(p.x));
+    } else if obj.pattern_type_id == 3u {
+      // noise
+      t = fbm(p);
+    } else if obj.pattern_type_id == 4u {
+      // marble
+      t = sin(p.x + fbm(p) * 6.0) * 0.5 + 0.5;
+    } else if obj.pattern_type_id == 5u {
+      // gradient by height, -1 to 1 in object space (before scaling)
+      t = clamp(p.y / obj.pattern_scale * 0.5 + 0.5, 0.0, 1.0);
+    } else if obj.pattern_type_id == 6u {
+      // gradient by curvature, 0.5 is flat
+      t = clamp(0.5 + get_curvature(world_pos) * 0.01 * obj.pattern_scale, 0.0, 1.0);
+    } else if obj.pattern_type_id == 7u {
+      // orbit trap, uses the palette instead of base_color -> pattern_color
+      if obj.shape_type_id != 4u {
+	  return out;
+	}
+      let trap = sdfMandel(translate_ray(world_pos, obj), obj.shape_var).trap;
+      var trap_value = trap.x;
+      if obj.orbit_trap_type_id == 1u {
+	  trap_value = trap.y;
+	} else if obj.orbit_trap_type_id == 2u {
+	  trap_value = trap.z;
+	}
+      out.base_color = sample_palette(obj.palette, clamp(trap_value * obj.pattern_scale, 0.0, 1.0));
+      return out;
+    }
+  out.base_color = mix(obj.base_color, obj.pattern_color, t);
+  return out;
+}
+
+// 4 colors, evenly spaced from 0.0 to 1.0
+fn sample_palette(palette: array<vec4<f32>, 4>, t: f32) -> vec4<f32> {
+  // naga only lets us index into a var with a runtime index
+  var colors = palette;
+  let scaled = t * 3.0;
+  let i = u32(min(floor(scaled), 2.0));
+  return mix(colors[i], colors[i + 1u], scaled - f32(i));
+}
+
+// laplacian of the sdf, positive on convex and negative on concave surfaces
+fn get_curvature(p: vec3<f32>) -> f32 {
+  let e = 0.01;
+  let d = sdf_world_min(p);
+  let sum = sdf_world_min(p + vec3<f32>(e, 0.0, 0.0)) + sdf_world_min(p - vec3<f32>(e, 0.0, 0.0))
+    + sdf_world_min(p + vec3<f32>(0.0, e, 0.0)) + sdf_world_min(p - vec3<f32>(0.0, e, 0.0))
+    + sdf_world_min(p + vec3<f32>(0.0, 0.0, e)) + sdf_world_min(p - vec3<f32>(0.0, 0.0, e));
+  return (sum - 6.0 * d) / (e * e);
+}
+
+fn hash3(p: vec3<f32>) -> f32 {
+  return fract(sin(dot(p, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+// value noise, 0.0-1.0
+fn noise3(p: vec3<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+  let x00 = mix(hash3(i), hash3(i + vec3<f32>(1.0, 0.0, 0.0)), u.x);
+  let x10 = mix(hash3(i + vec3<f32>(0.0, 1.0, 0.0)), hash3(i + vec3<f32>(1.0, 1.0, 0.0)), u.x);
+  let x01 = mix(hash3(i + vec3<f32>(0.0, 0.0, 1.0)), hash3(i + vec3<f32>(1.0, 0.0, 1.0)), u.x);
+  let x11 = mix(hash3(i + vec3<f32>(0.0, 1.0, 1.0)), hash3(i + vec3<f32>(1.0, 1.0, 1.0)), u.x);
+  return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+fn fbm(p: vec3<f32>) -> f32 {
+  var out = 0.0;
+  var amplitude = 0.5;
+  var q = p;
+  for (var i = 0; i < 4; i++) {
+    out += noise3(q) * amplitude;
+    q *= 2.0;
+    amplitude *= 0.5;
+  }
+  return out / 0.9375; // sum of the amplitudes
+}
+
 fn obj_descriptor_to_material(desc: RaymarchObjectDescriptor) -> StandardMaterial {
   var mat = standard_material_new();
 
@@ -410,6 +1038,8 @@ fn obj_descriptor_to_material(desc: RaymarchObjectDescriptor) -> StandardMateria
   mat.clearcoat_perceptual_roughness = desc.clearcoat_perceptual_roughness;
   mat.anisotropy_strength = desc.anisotropy_strength;
   mat.anisotropy_rotation = desc.anisotropy_rotation;
+  // with a white attenuation color this does nothing, so we can just always turn it on
+  mat.flags |= STANDARD_MATERIAL_FLAGS_ATTENUATION_ENABLED_BIT;
 
   return mat;
 }
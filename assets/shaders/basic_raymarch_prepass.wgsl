@@ -4,6 +4,7 @@
 }
 
 #import "shaders/basic_raymarch.wgsl"::perform_march
+#import "shaders/basic_raymarch.wgsl"::get_camera_ray
 #import "shaders/basic_raymarch.wgsl"::MarchOutput
 
 @fragment
@@ -11,7 +12,7 @@ fn fragment(
 	    mesh: VertexOutput,
 	    // @builtin(sample_index) sample_index: u32,
 	    ) -> @builtin(frag_depth) f32 {
-  let march = perform_march(mesh.position.xy);
+  let march = perform_march(get_camera_ray(mesh.position.xy), mesh.position.xy);
   if march.has_hit {
       let clip_curr_pos = view.clip_from_world * vec4<f32>(march.hit_pos, 1.0);
       let ndc_curr_pos = clip_curr_pos.xyz / clip_curr_pos.w;
@@ -0,0 +1,62 @@
+// low resolution prepass, see cone_prepass.rs
+// one invocation per tile: march a cone that covers the whole tile
+// and store how far it got before it touched something
+// the full resolution march in basic_raymarch.wgsl then starts from there
+
+#import "shaders/sdf.wgsl"::{raymarch_global_settings, sdf_world_min}
+
+struct ConePrepassCamera {
+ world_from_clip: mat4x4<f32>,
+ world_position: vec3<f32>,
+ // how big one pixel is at a distance of 1.0 from the camera
+ pixel_size: f32,
+ viewport_size: vec2<f32>,
+ tile_size: f32,
+}
+
+@group(0) @binding(0) var start_distances: texture_storage_2d<r32float, write>;
+@group(0) @binding(1) var<uniform> camera: ConePrepassCamera;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+  let tiles = textureDimensions(start_distances);
+  if id.x >= tiles.x || id.y >= tiles.y {
+      return;
+    }
+
+  let tile_center = (vec2<f32>(id.xy) + 0.5) * camera.tile_size;
+  let ray_dir = get_ray_dir(tile_center);
+
+  // the cone has to contain the rays of all the pixels in the tile, so it goes through the corners
+  let cone_ratio = camera.pixel_size * camera.tile_size * 0.7072;
+  let settings = raymarch_global_settings;
+
+  var t = 0.0;
+  for (var i = 0u; i < settings.max_iterations; i++) {
+      if t >= settings.far_clip {
+	  break;
+	}
+      let d = sdf_world_min(camera.world_position + ray_dir * t);
+      let cone_radius = t * cone_ratio;
+      if d < cone_radius + settings.termination_distance {
+	  break;
+	}
+      // biggest step that keeps the cone inside of the empty sphere around the ray
+      t += (d - cone_radius) / (1.0 + cone_ratio);
+    }
+
+  // the rays at the corners of the tile are a little longer than the center one
+  // step back a bit, so none of them start behind a surface
+  let start_distance = max(t - t * cone_ratio - settings.termination_distance, 0.0);
+  textureStore(start_distances, vec2<i32>(id.xy), vec4<f32>(start_distance, 0.0, 0.0, 0.0));
+}
+
+// same as get_camera_ray_dir in basic_raymarch.wgsl
+fn get_ray_dir(coord: vec2<f32>) -> vec3<f32> {
+  var viewport_uv = coord / camera.viewport_size * 2.0 - 1.0;
+  viewport_uv.y *= -1;
+  let clip = vec4<f32>(viewport_uv, 1.0, 1.0);
+  var world = camera.world_from_clip * clip;
+  world /= world.w;
+  return normalize(world.xyz - camera.world_position);
+}
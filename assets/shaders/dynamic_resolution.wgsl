@@ -0,0 +1,122 @@
+// puts the low resolution raymarch (see dynamic_resolution.rs) back into the main view
+// depth aware (joint bilateral) upsampling: of the 4 closest low resolution texels
+// we only really mix the ones that are at about the same distance, so edges stay sharp
+
+#import bevy_pbr::{
+  mesh_view_bindings::view,
+  forward_io::VertexOutput,
+  utils::coords_to_viewport_uv,
+}
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping::tone_mapping
+#endif
+
+@group(2) @binding(0) var low_res_texture: texture_2d<f32>;
+
+// how different the distances can be (relative to the distance) before they stop mixing
+const DEPTH_SIGMA = 0.05;
+const MISS_DISTANCE = 100000.0;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+  let low_res_size = vec2<i32>(textureDimensions(low_res_texture));
+  let uv = coords_to_viewport_uv(mesh.position.xy, view.viewport);
+  // position in low resolution texels, relative to the texel centers
+  let texel_pos = uv * vec2<f32>(low_res_size) - 0.5;
+  let base = vec2<i32>(floor(texel_pos));
+  let f = texel_pos - floor(texel_pos);
+
+  // the nearest texel decides which surface this pixel belongs to
+  let nearest = load_texel(base + vec2<i32>(round(f)), low_res_size);
+  let reference_distance = get_distance(nearest);
+
+  var color = vec4<f32>(0.0);
+  var total_weight = 0.0;
+  var hit_distance = 0.0;
+  var hit_weight = 0.0;
+  for (var i = 0; i < 4; i++) {
+      let offset = vec2<i32>(i % 2, i / 2);
+      let texel = load_texel(base + offset, low_res_size);
+      let bilinear = mix(1.0 - f, f, vec2<f32>(offset));
+      let relative_difference = (get_distance(texel) - reference_distance)
+	/ (reference_distance * DEPTH_SIGMA);
+      let weight = bilinear.x * bilinear.y
+	* exp(-relative_difference * relative_difference)
+	+ 0.00001;
+      color += vec4<f32>(texel.xyz, get_alpha(texel)) * weight;
+      total_weight += weight;
+      if texel.w > 0.0 {
+	  hit_distance += texel.w * weight;
+	  hit_weight += weight;
+	}
+    }
+  color /= total_weight;
+  hit_distance /= max(hit_weight, 0.00001);
+
+#ifndef WEBGL2
+  // something in front of the surface, same as in basic_raymarch.wgsl
+  if nearest.w > 0.0 {
+      let hit_pos = get_ray_origin(mesh.position.xy) + get_camera_ray_dir(mesh.position.xy) * hit_distance;
+      let clip_hit_pos = view.clip_from_world * vec4<f32>(hit_pos, 1.0);
+      let hit_depth = clip_hit_pos.z / clip_hit_pos.w;
+      if bevy_pbr::prepass_utils::prepass_depth(mesh.position, 0) > hit_depth {
+	  discard;
+	}
+    }
+#endif //WEBGL2
+
+  // back from premultiplied, the material blends normally
+  var out = vec4<f32>(color.xyz / max(color.w, 0.0001), color.w);
+#ifdef TONEMAP_IN_SHADER
+  out = tone_mapping(out, view.color_grading);
+#endif
+  return out;
+}
+
+fn load_texel(coord: vec2<i32>, size: vec2<i32>) -> vec4<f32> {
+  return textureLoad(low_res_texture, clamp(coord, vec2<i32>(0), size - 1), 0);
+}
+
+// see encode_low_res in basic_raymarch.wgsl
+fn get_distance(texel: vec4<f32>) -> f32 {
+  if texel.w > 0.0 {
+      return texel.w;
+    }
+  return MISS_DISTANCE;
+}
+
+fn get_alpha(texel: vec4<f32>) -> f32 {
+  if texel.w > 0.0 {
+      return 1.0;
+    }
+  return -texel.w;
+}
+
+// same as get_camera_ray in basic_raymarch.wgsl, without the lens
+// (the hit distance is from a point on the lens, but this is only for the depth test)
+fn get_near_point(coord: vec2<f32>) -> vec3<f32> {
+  var viewport_uv = coords_to_viewport_uv(coord, view.viewport) * 2.0 - 1.0;
+  viewport_uv.y *= -1;
+  let clip = vec4<f32>(viewport_uv, 1.0, 1.0);
+  var world = view.world_from_clip * clip;
+  world /= world.w;
+  return world.xyz;
+}
+
+fn is_orthographic() -> bool {
+  return view.clip_from_view[3].w == 1.0;
+}
+
+fn get_ray_origin(coord: vec2<f32>) -> vec3<f32> {
+  if is_orthographic() {
+      return get_near_point(coord);
+    }
+  return view.world_position;
+}
+
+fn get_camera_ray_dir(coord: vec2<f32>) -> vec3<f32> {
+  if is_orthographic() {
+      return -view.world_from_view[2].xyz;
+    }
+  return normalize(get_near_point(coord) - view.world_position);
+}
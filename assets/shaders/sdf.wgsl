@@ -0,0 +1,265 @@
+// everything that is needed to evaluate the distance to the scene
+// shared between the material and the cone prepass compute shader
+
+#import "shaders/sdf_primitives.wgsl"::{
+  sdf_circle,
+  sdBox,
+  sdConeBound,
+  opSmoothUnion,
+  opSmoothSubtract,
+  opSmoothIntersect,
+  sdfMandel,
+  rotation_mat_x,
+}
+// with GENERATED_SDF this file is compiled as the copy from codegen.rs, which also imports
+// generated_sdf_world and generated_my_min. don't import them here, see the top of codegen.rs
+
+@group(2) @binding(100) var<uniform> object1: RaymarchObjectDescriptor;
+@group(2) @binding(101) var<uniform> object2: RaymarchObjectDescriptor;
+@group(2) @binding(102) var<uniform> raymarch_global_settings: RaymarchGlobalSettings;
+
+struct SdfOutput {
+ distance_to_1object: f32,
+ distance_to_2object: f32,
+}
+fn sdf_world(ray_position: vec3<f32>) -> SdfOutput {
+#ifdef GENERATED_SDF
+  // the same thing, but with the scene baked in
+  let generated = generated_sdf_world(ray_position, raymarch_global_settings.time);
+  return SdfOutput(generated.x, generated.y);
+#else
+  let rp1 = translate_ray(ray_position, object1);
+  let rp2 = translate_ray(ray_position, object2);
+
+  var distance_to_1object = sdf_object1(rp1);
+  var distance_to_2object = sdf_object2(rp2);
+  // volumes don't have a surface, integrate_volumes takes care of them
+  if object1.volume_type_id != 0u {
+      distance_to_1object = 100000.0;
+    }
+  if object2.volume_type_id != 0u {
+      distance_to_2object = 100000.0;
+    }
+  return SdfOutput(distance_to_1object, distance_to_2object);
+#endif
+}
+
+// the OBJECT1_SHAPE, OBJECT2_SHAPE and INTERSECTION_METHOD shader defs come from
+// RaymarchMaterialKey in main.rs, each object only compiles the sdf of its own shape
+fn sdf_object1(ray_position: vec3<f32>) -> f32 {
+#if OBJECT1_SHAPE == 1
+  return sdf_sphere(ray_position, object1);
+#else if OBJECT1_SHAPE == 2
+  return sdf_cube(ray_position, object1);
+#else if OBJECT1_SHAPE == 3
+  return sdf_cone(ray_position, object1);
+#else if OBJECT1_SHAPE == 4
+  return sdf_mandelbulb(ray_position, object1);
+#else
+  return 100000.0;
+#endif
+}
+
+fn sdf_object2(ray_position: vec3<f32>) -> f32 {
+#if OBJECT2_SHAPE == 1
+  return sdf_sphere(ray_position, object2);
+#else if OBJECT2_SHAPE == 2
+  return sdf_cube(ray_position, object2);
+#else if OBJECT2_SHAPE == 3
+  return sdf_cone(ray_position, object2);
+#else if OBJECT2_SHAPE == 4
+  return sdf_mandelbulb(ray_position, object2);
+#else
+  return 100000.0;
+#endif
+}
+
+// for code that does the same for both objects, object_index is 1 or 2
+fn sdf_object(ray_position: vec3<f32>, object_index: u32) -> f32 {
+  if object_index == 1u {
+      return sdf_object1(ray_position);
+    }
+  return sdf_object2(ray_position);
+}
+
+fn get_object(object_index: u32) -> RaymarchObjectDescriptor {
+  if object_index == 1u {
+      return object1;
+    }
+  return object2;
+}
+
+fn sdf_sphere(ray_position: vec3<f32>, obj: RaymarchObjectDescriptor) -> f32 {
+  return sdf_circle(ray_position, obj.shape_var);
+}
+
+fn sdf_cube(ray_position: vec3<f32>, obj: RaymarchObjectDescriptor) -> f32 {
+  return sdBox(ray_position, vec3<f32>(obj.shape_var));
+}
+
+fn sdf_cone(ray_position: vec3<f32>, obj: RaymarchObjectDescriptor) -> f32 {
+  // for some reason, the cone default position is really low
+  // fix it here
+  var rp_higher = ray_position;
+  rp_higher.y -= 0.25;
+  return sdConeBound(rp_higher,
+		     obj.shape_var,
+		     vec2<f32>(sin(obj.scale),
+			       cos(obj.scale)));
+}
+
+fn sdf_mandelbulb(ray_position: vec3<f32>, obj: RaymarchObjectDescriptor) -> f32 {
+  return sdfMandel(ray_position, obj.shape_var).distance;
+}
+
+//   ,--.                                 ,---.                                  ,--.  ,--.
+// ,-'  '-.,--.--. ,--,--.,--,--,  ,---. /  .-' ,---. ,--.--.,--,--,--. ,--,--.,-'  '-.`--' ,---. ,--,--,
+// '-.  .-'|  .--'' ,-.  ||      \(  .-' |  `-,| .-. ||  .--'|        |' ,-.  |'-.  .-',--.| .-. ||      \
+//   |  |  |  |   \ '-'  ||  ||  |.-'  `)|  .-'' '-' '|  |   |  |  |  |\ '-'  |  |  |  |  |' '-' '|  ||  |
+//   `--'  `--'    `--`--'`--''--'`----' `--'   `---' `--'   `--`--`--' `--`--'  `--'  `--' `---' `--''--'
+// transformation
+// TODO: implement y and z euler angles
+fn translate_ray(r: vec3<f32>, obj: RaymarchObjectDescriptor) -> vec3<f32> {
+  var out = r;
+  // translation
+  var added_translation = vec3<f32>(0.0);
+  added_translation.x = sin(raymarch_global_settings.time * 0.5) * obj.move_amount;
+  added_translation.y = cos(raymarch_global_settings.time) * obj.move_amount;
+  added_translation.z = cos(raymarch_global_settings.time) * obj.move_amount * 0.2;
+  out -= obj.world_position - added_translation;
+
+  // rotation
+  out = (vec4<f32>(out, 1.0) * rotation_mat_x(get_rotation_x(obj))).xyz;
+
+  // scale
+  return out;
+}
+
+// same rotation as translate_ray, but for directions (like normals)
+fn rotate_dir_to_object(dir: vec3<f32>, obj: RaymarchObjectDescriptor) -> vec3<f32> {
+  return (vec4<f32>(dir, 0.0) * rotation_mat_x(get_rotation_x(obj))).xyz;
+}
+
+// inverse of rotate_dir_to_object
+fn rotate_dir_to_world(dir: vec3<f32>, obj: RaymarchObjectDescriptor) -> vec3<f32> {
+  return (rotation_mat_x(get_rotation_x(obj)) * vec4<f32>(dir, 0.0)).xyz;
+}
+
+fn get_rotation_x(obj: RaymarchObjectDescriptor) -> f32 {
+  let added_rotation = obj.rotation_amount * raymarch_global_settings.time;
+  return obj.rotation.x + added_rotation;
+}
+
+
+
+fn my_min(a: f32, b: f32) -> f32 {
+#ifdef GENERATED_SDF
+  return generated_my_min(a, b);
+#else
+  // a volume has no surface to combine with, so the solid object is on its own
+  // (sdf_world gives volumes a huge distance, which would swallow everything with AND or NOT)
+  if object1.volume_type_id != 0u {
+      return b;
+    }
+  if object2.volume_type_id != 0u {
+      return a;
+    }
+#if INTERSECTION_METHOD == 0
+  return opSmoothUnion(a, b, raymarch_global_settings.intersection_smooth_amount);
+#else if INTERSECTION_METHOD == 1
+  return opSmoothIntersect(a, b, raymarch_global_settings.intersection_smooth_amount);
+#else if INTERSECTION_METHOD == 2
+  return opSmoothSubtract(a, b, raymarch_global_settings.intersection_smooth_amount);
+#else
+  return 100000.0; // Todo: +inf
+#endif
+#endif
+}
+
+
+
+
+
+fn sdf_world_min(p: vec3<f32>) -> f32 {
+  let sdf_out = sdf_world(p);
+  return my_min(sdf_out.distance_to_1object, sdf_out.distance_to_2object);
+}
+
+
+//                 ,--. ,---.
+// ,--.,--.,--,--, `--'/  .-' ,---. ,--.--.,--,--,--. ,---.
+// |  ||  ||      \,--.|  `-,| .-. ||  .--'|        |(  .-'
+// '  ''  '|  ||  ||  ||  .-'' '-' '|  |   |  |  |  |.-'  `)
+//  `----' `--''--'`--'`--'   `---' `--'   `--`--`--'`----'
+// uniforms
+struct RaymarchObjectDescriptor {
+ world_position: vec3<f32>,
+ rotation: vec3<f32>,
+ move_amount: f32,
+ rotation_amount: f32,
+ shape_type_id: u32,
+ shape_var: f32,
+ scale: f32,
+ base_color: vec4<f32>,
+ emissive: vec4<f32>,
+ reflectance: vec3<f32>,
+ perceptual_roughness: f32,
+ metallic: f32,
+ diffuse_transmission: f32,
+ specular_transmission: f32,
+ thickness: f32,
+ ior: f32,
+ attenuation_distance: f32,
+ attenuation_color: vec4<f32>,
+ clearcoat: f32,
+ clearcoat_perceptual_roughness: f32,
+ anisotropy_strength: f32,
+ anisotropy_rotation: vec2<f32>,
+ texture_scale: f32,
+ texture_blend_sharpness: f32,
+ texture_flags: u32,
+ pattern_type_id: u32,
+ pattern_color: vec4<f32>,
+ pattern_scale: f32,
+ orbit_trap_type_id: u32,
+ palette: array<vec4<f32>, 4>,
+ volume_type_id: u32,
+ volume_density: f32,
+ volume_absorption: f32,
+ volume_scattering: f32,
+ volume_phase_g: f32,
+ volume_noise_scale: f32,
+ volume_falloff: f32,
+ glow_color: vec4<f32>,
+ glow_intensity: f32,
+}
+
+struct RaymarchGlobalSettings {
+ intersection_method: u32,
+ intersection_smooth_amount: f32,
+ material_blend_mode: u32,
+ material_blend_width: f32,
+ glow_mode: u32,
+ glow_range: f32,
+ glow_color: vec4<f32>,
+ far_clip: f32,
+ termination_distance: f32,
+ max_iterations: u32,
+ over_relaxation: f32,
+ termination_cone_scale: f32,
+ hit_refinement_steps: u32,
+ normal_method: u32,
+ normal_epsilon: f32,
+ normal_epsilon_scale: f32,
+ bounce_limit: u32,
+ volume_step_size: f32,
+ cone_prepass: u32,
+ dynamic_resolution: u32,
+ resolution_scale: f32,
+ target_frame_time: f32,
+ aperture: f32,
+ focus_distance: f32,
+ accumulation: u32,
+ max_samples: u32,
+ time: f32,
+}
@@ -0,0 +1,101 @@
+// the sdf building blocks, without any scene in them
+// its own module so the generated sdf (codegen.rs) can use them too
+
+const PI = 3.14159265359;
+const BAILOUT = 3.0;
+
+fn rotation_mat_x(angle_x: f32) -> mat4x4<f32> {
+  return mat4x4<f32>(
+		     vec4<f32>(1.0, 0.0, 0.0, 0.0),
+		     vec4<f32>(0.0, cos(angle_x), sin(angle_x), 0.0),
+		     vec4<f32>(0.0, -sin(angle_x), cos(angle_x), 0.0),
+		     vec4<f32>(0.0, 0.0, 0.0, 1.0));
+}
+
+//  ,---.  ,------.  ,------.    ,------.                        ,--.  ,--.
+// '   .-' |  .-.  \ |  .---'    |  .---',--.,--.,--,--,  ,---.,-'  '-.`--' ,---. ,--,--,  ,---.
+// `.  `-. |  |  \  :|  `--,     |  `--, |  ||  ||      \| .--''-.  .-',--.| .-. ||      \(  .-'
+// .-'    ||  '--'  /|  |`       |  |`   '  ''  '|  ||  |\ `--.  |  |  |  |' '-' '|  ||  |.-'  `)
+// `-----' `-------' `--'        `--'     `----' `--''--' `---'  `--'  `--' `---' `--''--'`----'
+// I got them from: https://gist.github.com/munrocket/f247155fc22ecb8edf974d905c677de1
+fn sdf_circle(p: vec3<f32>, rad: f32) -> f32 {
+  return length(p) - rad;
+}
+
+fn sdBox(p: vec3f, b: vec3f) -> f32 {
+  let q = abs(p) - b;
+  return length(max(q, vec3f(0.))) + min(max(q.x, max(q.y, q.z)), 0.);
+}
+
+fn sdConeBound(p: vec3f, h: f32, sincos: vec2f) -> f32 {
+  return max(dot(sincos.yx, vec2f(length(p.xz), p.y)), -h - p.y);
+}
+
+fn opSmoothUnion(d1: f32, d2: f32, k: f32) -> f32 {
+  let h = clamp(0.5 + 0.5 * (d2 - d1) / k, 0., 1.);
+  return mix(d2, d1, h) - k * h * (1. - h);
+}
+
+fn opSmoothSubtract(d1: f32, d2: f32, k: f32) -> f32 {
+  let h = clamp(0.5 - 0.5 * (d1 + d2) / k, 0., 1.);
+  return mix(d1, -d2, h) + k * h * (1. - h);
+}
+
+fn opSmoothIntersect(d1: f32, d2: f32, k: f32) -> f32 {
+  let h = clamp(0.5 - 0.5 * (d2 - d1) / k, 0., 1.);
+  return mix(d2, d1, h) + k * h * (1. - h);
+}
+
+fn smin_circular(a: f32, b: f32, k: f32) -> f32 {
+  let km = k * (1.0/(1.0-sqrt(0.5)));
+  let h = max(k - abs(a-b), 0.0) / k;
+  return min(a,b) - k * 0.5 * (1.0 + h - sqrt(1.0 - h * (h - 2.0)));
+}
+
+fn opScale(p: vec3f, s: f32) -> vec3f {
+  return p / s;
+}
+
+// trap:
+// x -> min distance to the origin
+// y -> min distance to the xy, yz, xz planes
+// z -> iterations until bailout, 0.0-1.0
+struct FractalOutput {
+ distance: f32,
+ trap: vec3<f32>,
+}
+
+// https://github.com/zordone/fractal-webgpu/blob/main/madelbulb/shaders.wgsl
+fn sdfMandel(point0: vec3<f32>, power: f32) -> FractalOutput {
+  // the mandelbulb is at scene scene center. translate to origin.
+  let point = point0;
+  // params
+  //let blob = 1 - uniforms.blob;
+  //let spike = uniforms.spike * PI / 2;
+  let blob = 1 - 0.0;
+  let spike = 0.0 * PI / 2;
+ // iterate to find distance
+  var z = point;
+  var dr = 1.0;
+  var dist: f32;
+  var trap = vec3<f32>(1000000.0, 1000000.0, 0.0);
+  for (var step = 0; step < 16; step++) {
+    dist = length(z);
+    if (dist > BAILOUT) { break; }
+    trap.x = min(trap.x, dist);
+    trap.y = min(trap.y, min(abs(z.x), min(abs(z.y), abs(z.z))));
+    trap.z += 1.0 / 16.0;
+    // to polar coordinates
+    let theta = acos(z.z / dist) * power * blob;
+    let phi = atan2(z.y, z.x) * power;
+    // scale and rotate
+    let distPowMinusOne = pow(dist, power - 1.0);
+    let zr = distPowMinusOne * dist;
+    dr = distPowMinusOne * power * dr + 1.0;
+    // back to cartesian coordinates
+    let sinTheta = sin(theta);
+    z = zr * vec3<f32>(sinTheta * cos(phi), sin(phi + spike) * sinTheta, cos(theta));
+    z += point;
+  }
+  return FractalOutput(0.5 * log(dist) * dist / dr, trap);
+}
@@ -0,0 +1,425 @@
+// accumulation of jittered frames
+// the thin lens (see get_camera_ray in basic_raymarch.wgsl) marches from a different point on the
+// lens every frame, and progressive accumulation also jitters the position in the pixel, the
+// shadows and the volume steps. single frames are noisy, but while nothing changes every new frame
+// is blended into a history texture with a running average, and the view shows that instead
+// any change to the camera or the material starts over, the history would smear otherwise
+// after max_samples frames the image is done, and the history stays as it is
+
+use bevy::{
+    core_pipeline::{
+        core_3d::graph::{Core3d, Node3d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    diagnostic::FrameCount,
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    pbr::{ExtendedMaterial, ShadowFilteringMethod},
+    prelude::*,
+    render::{
+        camera::{CameraUpdateSystem, ExtractedCamera},
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin,
+            UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_resource::{
+            binding_types::{texture_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::{CachedTexture, TextureCache},
+        view::{ExtractedView, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    RaymarchGlobalSettings, RaymarchMaterial, RaymarchMaterialHandle, RaymarchObjectDescriptor,
+};
+/// the history has to be float, 8 bits are not enough to average that many frames
+const HISTORY_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+pub struct AccumulationPlugin;
+impl Plugin for AccumulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<Accumulation>::default(),
+            UniformComponentPlugin::<AccumulationWeight>::default(),
+        ));
+        app.add_systems(
+            PostUpdate,
+            update_accumulation
+                .after(TransformSystem::TransformPropagate)
+                .after(CameraUpdateSystem),
+        );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            (
+                prepare_accumulation_pipelines.in_set(RenderSet::Prepare),
+                prepare_accumulation_history.in_set(RenderSet::PrepareResources),
+            ),
+        );
+        render_app
+            .add_render_graph_node::<ViewNodeRunner<AccumulationNode>>(Core3d, AccumulationLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (Node3d::EndMainPass, AccumulationLabel, Node3d::Tonemapping),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<AccumulationPipeline>();
+        render_app.init_resource::<SpecializedRenderPipelines<AccumulationPipeline>>();
+    }
+}
+
+/// on the camera that should accumulate, does nothing while nothing is jittered
+#[derive(Component, Default)]
+pub struct Accumulation {
+    enabled: bool,
+    /// frames in the history, including the one that is rendered next
+    /// one more than max_samples once the image is done
+    samples: u32,
+    max_samples: u32,
+    /// what the history was rendered with
+    state: Option<AccumulatedState>,
+}
+
+impl Accumulation {
+    /// frames in the image on screen, and how many it will have when it's done
+    pub fn progress(&self) -> Option<(u32, u32)> {
+        if !self.enabled {
+            return None;
+        }
+        return Some((self.samples.min(self.max_samples), self.max_samples));
+    }
+}
+
+/// everything that changes what a frame looks like
+#[derive(Clone, PartialEq, Debug)]
+struct AccumulatedState {
+    world_from_view: Mat4,
+    clip_from_view: Mat4,
+    size: UVec2,
+    material1: RaymarchObjectDescriptor,
+    material2: RaymarchObjectDescriptor,
+    settings: RaymarchGlobalSettings,
+    textures: [Option<Handle<Image>>; 6],
+}
+
+/// the lens is always jittered, everything else only with progressive accumulation
+pub fn is_accumulating(settings: &RaymarchGlobalSettings) -> bool {
+    return settings.accumulation != 0 || settings.aperture > 0.0;
+}
+
+/// frames from a change until the image is done again, for screenshots and offline renders
+pub fn frames_to_converge(settings: &RaymarchGlobalSettings) -> u32 {
+    if !is_accumulating(settings) {
+        return 0;
+    }
+    return settings.max_samples.max(1);
+}
+
+// has to reflect AccumulationWeight in accumulation.wgsl
+#[derive(Component, Clone, Copy, ShaderType)]
+pub struct AccumulationWeight {
+    /// how much of the new frame goes into the history, 1.0 -> start over
+    weight: f32,
+    #[cfg(target_arch = "wasm32")]
+    _webgl2_padding: Vec3,
+}
+
+impl ExtractComponent for Accumulation {
+    type QueryData = &'static Self;
+    type QueryFilter = ();
+    type Out = AccumulationWeight;
+
+    fn extract_component(accumulation: QueryItem<'_, Self::QueryData>) -> Option<Self::Out> {
+        if !accumulation.enabled {
+            return None;
+        }
+        // done, keep the history and ignore the new frame
+        let weight = if accumulation.samples > accumulation.max_samples {
+            0.0
+        } else {
+            1.0 / accumulation.samples.max(1) as f32
+        };
+        return Some(AccumulationWeight {
+            weight,
+            #[cfg(target_arch = "wasm32")]
+            _webgl2_padding: Vec3::ZERO,
+        });
+    }
+}
+
+// runs after the camera matrices are up to date for this frame
+fn update_accumulation(
+    mut commands: Commands,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut cameras: Query<(&Camera, &GlobalTransform, &mut Accumulation)>,
+    shadow_cameras: Query<(Entity, Option<&ShadowFilteringMethod>), With<Camera3d>>,
+) {
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let ext = &mat.extension;
+    let enabled = is_accumulating(&ext.raymarch_global_settings);
+    let max_samples = ext.raymarch_global_settings.max_samples.max(1);
+    // raising the limit can just keep going
+    let mut settings = ext.raymarch_global_settings.clone();
+    settings.max_samples = 0;
+    let textures = [
+        ext.object1_base_color_texture.clone(),
+        ext.object1_metallic_roughness_texture.clone(),
+        ext.object1_normal_map_texture.clone(),
+        ext.object2_base_color_texture.clone(),
+        ext.object2_metallic_roughness_texture.clone(),
+        ext.object2_normal_map_texture.clone(),
+    ];
+    for (camera, transform, mut accumulation) in cameras.iter_mut() {
+        let state = camera
+            .physical_viewport_size()
+            .map(|size| AccumulatedState {
+                world_from_view: transform.compute_matrix(),
+                clip_from_view: camera.clip_from_view(),
+                size,
+                material1: ext.material1.clone(),
+                material2: ext.material2.clone(),
+                settings: settings.clone(),
+                textures: textures.clone(),
+            });
+        if !enabled || state != accumulation.state {
+            accumulation.samples = 0;
+        }
+        accumulation.enabled = enabled;
+        accumulation.max_samples = max_samples;
+        accumulation.state = state;
+        accumulation.samples = (accumulation.samples + 1).min(max_samples + 1);
+    }
+
+    // bevys temporal shadow filter jitters the shadow map lookups every frame, made for averaging
+    // (the low resolution camera draws the raymarch with dynamic resolution, so all of them)
+    let shadow_filtering = if ext.raymarch_global_settings.accumulation != 0 {
+        ShadowFilteringMethod::Temporal
+    } else {
+        ShadowFilteringMethod::default()
+    };
+    for (entity, method) in shadow_cameras.iter() {
+        if method != Some(&shadow_filtering) {
+            commands.entity(entity).insert(shadow_filtering);
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AccumulationPipeline {
+    layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl FromWorld for AccumulationPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "accumulation_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // the frame that was just rendered
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // the history up to the last frame
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    uniform_buffer::<AccumulationWeight>(true),
+                ),
+            ),
+        );
+        let shader = world.load_asset("shaders/accumulation.wgsl");
+        return AccumulationPipeline { layout, shader };
+    }
+}
+
+impl SpecializedRenderPipeline for AccumulationPipeline {
+    /// hdr, the view target has a different format then
+    type Key = bool;
+
+    fn specialize(&self, hdr: Self::Key) -> RenderPipelineDescriptor {
+        let view_format = if hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+        return RenderPipelineDescriptor {
+            label: Some("accumulation_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: Vec::new(),
+                entry_point: "fragment".into(),
+                targets: vec![
+                    Some(ColorTargetState {
+                        format: view_format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                    Some(ColorTargetState {
+                        format: HISTORY_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: Vec::new(),
+            zero_initialize_workgroup_memory: false,
+        };
+    }
+}
+
+#[derive(Component)]
+struct AccumulationPipelineId(CachedRenderPipelineId);
+
+fn prepare_accumulation_pipelines(
+    mut commands: Commands,
+    pipeline: Res<AccumulationPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<AccumulationPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    views: Query<(Entity, &ExtractedView), With<AccumulationWeight>>,
+) {
+    for (entity, view) in views.iter() {
+        let id = pipelines.specialize(&pipeline_cache, &pipeline, view.hdr);
+        commands.entity(entity).insert(AccumulationPipelineId(id));
+    }
+}
+
+/// read the history from last frame, write the new one, swapped every frame
+#[derive(Component)]
+struct AccumulationHistory {
+    read: CachedTexture,
+    write: CachedTexture,
+}
+
+// same as bevys taa, the texture cache hands out the same two textures every frame
+// as long as the size doesn't change (and then update_accumulation starts over anyway)
+fn prepare_accumulation_history(
+    mut commands: Commands,
+    mut texture_cache: ResMut<TextureCache>,
+    render_device: Res<RenderDevice>,
+    frame_count: Res<FrameCount>,
+    views: Query<(Entity, &ExtractedCamera), With<AccumulationWeight>>,
+) {
+    for (entity, camera) in views.iter() {
+        let Some(size) = camera.physical_target_size else {
+            continue;
+        };
+        let mut descriptor = TextureDescriptor {
+            label: Some("accumulation_history_1"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: HISTORY_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        let history1 = texture_cache.get(&render_device, descriptor.clone());
+        descriptor.label = Some("accumulation_history_2");
+        let history2 = texture_cache.get(&render_device, descriptor);
+        let history = if frame_count.0 % 2 == 0 {
+            AccumulationHistory {
+                read: history1,
+                write: history2,
+            }
+        } else {
+            AccumulationHistory {
+                read: history2,
+                write: history1,
+            }
+        };
+        commands.entity(entity).insert(history);
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct AccumulationLabel;
+
+#[derive(Default)]
+struct AccumulationNode;
+impl ViewNode for AccumulationNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedCamera,
+        &'static AccumulationHistory,
+        &'static AccumulationPipelineId,
+        // the other components stay around when accumulation gets turned off, this one doesn't
+        &'static AccumulationWeight,
+        &'static DynamicUniformIndex<AccumulationWeight>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, camera, history, pipeline_id, _, weight_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let accumulation_pipeline = world.resource::<AccumulationPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id.0) else {
+            return Ok(());
+        };
+        let weights = world.resource::<ComponentUniforms<AccumulationWeight>>();
+        let Some(weights) = weights.uniforms().binding() else {
+            return Ok(());
+        };
+
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context.render_device().create_bind_group(
+            "accumulation_bind_group",
+            &accumulation_pipeline.layout,
+            &BindGroupEntries::sequential((
+                post_process.source,
+                &history.read.default_view,
+                weights,
+            )),
+        );
+        let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("accumulation"),
+            color_attachments: &[
+                Some(RenderPassColorAttachment {
+                    view: post_process.destination,
+                    resolve_target: None,
+                    ops: Operations::default(),
+                }),
+                Some(RenderPassColorAttachment {
+                    view: &history.write.default_view,
+                    resolve_target: None,
+                    ops: Operations::default(),
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_render_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[weight_index.index()]);
+        if let Some(viewport) = camera.viewport.as_ref() {
+            pass.set_camera_viewport(viewport);
+        }
+        pass.draw(0..3, 0..1);
+        return Ok(());
+    }
+}
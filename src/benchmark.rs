@@ -0,0 +1,230 @@
+// measures the frame time of the marcher options (over-relaxation, termination cone,
+// hit refinement) one after another, on the current scene and the Default and Mandelbulb demos
+// vsync is off while it runs, and adaptive resolution is held at its current scale, otherwise
+// every option would end up at the same frame time
+// the results go to the log, the global settings window and (not on web) to BENCHMARK_FILE as a
+// markdown table, in a form that can be pasted into a commit message or an issue
+
+use bevy::{
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::renderer::RenderAdapterInfo,
+    window::{PresentMode, PrimaryWindow},
+};
+
+use crate::{RaymarchGlobalSettings, RaymarchMaterial, RaymarchMaterialHandle};
+
+/// frames before measuring a case, for the pipeline to settle after the settings changed
+const WARMUP_FRAMES: u32 = 60;
+const MEASURED_FRAMES: u32 = 300;
+
+/// None -> whatever is loaded right now
+const SCENES: [(&str, Option<fn() -> RaymarchMaterial>); 3] = [
+    ("current scene", None),
+    ("Default", Some(RaymarchMaterial::get_basic_config)),
+    ("Mandelbulb", Some(RaymarchMaterial::get_mandelbulb_config)),
+];
+
+#[cfg(not(target_arch = "wasm32"))]
+const BENCHMARK_FILE: &str = "benchmark.md";
+
+const CASES: [&str; 5] = [
+    "plain sphere tracing",
+    "over-relaxation 1.4",
+    "termination cone 1 px",
+    "over-relaxation 1.4 + termination cone 1 px",
+    "plain + 2 hit refinement steps",
+];
+
+pub struct MarcherBenchmarkPlugin;
+impl Plugin for MarcherBenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MarcherBenchmark>();
+        app.add_systems(Update, run_marcher_benchmark);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MarcherBenchmark {
+    requested: bool,
+    run: Option<BenchmarkRun>,
+    pub results: Vec<BenchmarkResult>,
+}
+
+pub struct BenchmarkResult {
+    pub scene: &'static str,
+    pub case: &'static str,
+    /// average, in ms
+    pub frame_time: f32,
+}
+
+impl MarcherBenchmark {
+    pub fn start(&mut self) {
+        self.requested = true;
+    }
+
+    /// (current case, number of cases), over all scenes
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        return self.run.as_ref().map(|run| {
+            (
+                run.scene * CASES.len() + run.case + 1,
+                SCENES.len() * CASES.len(),
+            )
+        });
+    }
+
+    /// how much faster than plain sphere tracing on the same scene, in percent
+    pub fn speedup(&self, result: &BenchmarkResult) -> Option<f32> {
+        let plain = self
+            .results
+            .iter()
+            .find(|plain| plain.scene == result.scene && plain.case == CASES[0])?;
+        return Some((plain.frame_time / result.frame_time - 1.0) * 100.0);
+    }
+}
+
+struct BenchmarkRun {
+    scene: usize,
+    case: usize,
+    frame: u32,
+    total_seconds: f32,
+    /// put back when it's done
+    original: RaymarchMaterial,
+    present_mode: PresentMode,
+}
+
+// the scene and the marcher settings of a case, everything else stays like the user had it
+fn apply_scene(scene: usize, original: &RaymarchMaterial, material: &mut RaymarchMaterial) {
+    *material = match SCENES[scene].1 {
+        Some(demo) => demo(),
+        None => original.clone(),
+    };
+    let settings = &mut material.raymarch_global_settings;
+    // adaptive -> fixed at the current scale
+    settings.dynamic_resolution = original.raymarch_global_settings.dynamic_resolution;
+    settings.resolution_scale = original.raymarch_global_settings.resolution_scale;
+    if settings.dynamic_resolution == 2 {
+        settings.dynamic_resolution = 1;
+    }
+}
+
+fn apply_case(case: usize, settings: &mut RaymarchGlobalSettings) {
+    let defaults = RaymarchGlobalSettings::default();
+    settings.over_relaxation = defaults.over_relaxation;
+    settings.termination_cone_scale = defaults.termination_cone_scale;
+    settings.hit_refinement_steps = defaults.hit_refinement_steps;
+    match case {
+        1 => settings.over_relaxation = 1.4,
+        2 => settings.termination_cone_scale = 1.0,
+        3 => {
+            settings.over_relaxation = 1.4;
+            settings.termination_cone_scale = 1.0;
+        }
+        4 => settings.hit_refinement_steps = 2,
+        _ => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_results(benchmark: &MarcherBenchmark, adapter_info: &RenderAdapterInfo) {
+    let mut out = format!(
+        "marcher benchmark on {} ({:?}), {MEASURED_FRAMES} frames per case\n\n",
+        adapter_info.name, adapter_info.backend
+    );
+    out.push_str("| scene | case | frame time | vs plain |\n");
+    out.push_str("| --- | --- | --- | --- |\n");
+    for result in benchmark.results.iter() {
+        let speedup = benchmark.speedup(result).unwrap_or(0.0);
+        out.push_str(&format!(
+            "| {} | {} | {:.2} ms | {speedup:+.1} % |\n",
+            result.scene, result.case, result.frame_time
+        ));
+    }
+    match std::fs::write(BENCHMARK_FILE, out) {
+        Ok(()) => info!("wrote {BENCHMARK_FILE}"),
+        Err(err) => error!("could not write {BENCHMARK_FILE}: {err}"),
+    }
+}
+
+fn run_marcher_benchmark(
+    mut benchmark: ResMut<MarcherBenchmark>,
+    time: Res<Time<Real>>,
+    adapter_info: Res<RenderAdapterInfo>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    if !benchmark.requested && benchmark.run.is_none() {
+        return;
+    }
+    let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+        return;
+    };
+    let Ok(mut window) = windows.single_mut() else {
+        return;
+    };
+
+    if benchmark.requested {
+        benchmark.requested = false;
+        if benchmark.run.is_some() {
+            return;
+        }
+        info!(
+            "benchmarking the marcher on {} ({:?})",
+            adapter_info.name, adapter_info.backend
+        );
+        benchmark.results.clear();
+        let original = mat.extension.clone();
+        apply_scene(0, &original, &mut mat.extension);
+        apply_case(0, &mut mat.extension.raymarch_global_settings);
+        benchmark.run = Some(BenchmarkRun {
+            scene: 0,
+            case: 0,
+            frame: 0,
+            total_seconds: 0.0,
+            original,
+            present_mode: window.present_mode,
+        });
+        window.present_mode = PresentMode::AutoNoVsync;
+        return;
+    }
+
+    let benchmark = benchmark.into_inner();
+    let Some(run) = benchmark.run.as_mut() else {
+        return;
+    };
+    run.frame += 1;
+    if run.frame <= WARMUP_FRAMES {
+        return;
+    }
+    run.total_seconds += time.delta_secs();
+    if run.frame < WARMUP_FRAMES + MEASURED_FRAMES {
+        return;
+    }
+    let frame_time = run.total_seconds / MEASURED_FRAMES as f32 * 1000.0;
+    let scene = SCENES[run.scene].0;
+    info!("{scene}, {}: {:.2} ms", CASES[run.case], frame_time);
+    benchmark.results.push(BenchmarkResult {
+        scene,
+        case: CASES[run.case],
+        frame_time,
+    });
+
+    run.frame = 0;
+    run.total_seconds = 0.0;
+    if run.case + 1 < CASES.len() {
+        run.case += 1;
+    } else if run.scene + 1 < SCENES.len() {
+        run.scene += 1;
+        run.case = 0;
+        apply_scene(run.scene, &run.original, &mut mat.extension);
+    } else {
+        mat.extension = run.original.clone();
+        window.present_mode = run.present_mode;
+        benchmark.run = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        write_results(benchmark, &adapter_info);
+        return;
+    }
+    apply_case(run.case, &mut mat.extension.raymarch_global_settings);
+}
@@ -0,0 +1,278 @@
+// interactive cameras next to the SpinningCam
+// orbit: left drag rotates around the focus, right/middle drag pans, scroll zooms
+// fly: hold right mouse to look around, wasd to move, q/e down/up, shift to go faster
+// every mode computes the whole transform from its own state, so switching between them can
+// blend from the old transform into the new one without anything feeding back
+
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit},
+    prelude::*,
+};
+use bevy_egui::EguiContexts;
+
+use crate::{spin_camera, transform_gizmo::TransformGizmo, RaymarchObjectDescriptor, SpinningCam};
+
+/// how long switching modes or framing an object takes, in seconds
+const TRANSITION_TIME: f32 = 0.5;
+
+pub struct CameraControllerPlugin;
+impl Plugin for CameraControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraController>();
+        app.add_systems(
+            Update,
+            (orbit_camera, fly_camera, blend_camera_transition)
+                .chain()
+                .after(spin_camera),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    Spinning,
+    Orbit,
+    Fly,
+    /// follows the CameraPath, see camera_path.rs
+    Path,
+}
+
+#[derive(Resource)]
+pub struct CameraController {
+    mode: CameraMode,
+    pub orbit: OrbitState,
+    pub fly: FlyState,
+    /// where the camera was when the transition started, and how far along it is (0-1)
+    transition: Option<(Transform, f32)>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        return CameraController {
+            mode: CameraMode::Spinning,
+            orbit: OrbitState::default(),
+            fly: FlyState::default(),
+            transition: None,
+        };
+    }
+}
+
+pub struct OrbitState {
+    pub focus: Vec3,
+    /// around the y axis
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub rotate_sensitivity: f32,
+}
+
+impl Default for OrbitState {
+    fn default() -> Self {
+        return OrbitState {
+            focus: Vec3::new(0.0, 0.5, 0.0),
+            yaw: 0.0,
+            pitch: -0.4,
+            distance: 4.0,
+            rotate_sensitivity: 0.005,
+        };
+    }
+}
+
+impl OrbitState {
+    fn transform(&self) -> Transform {
+        let rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+        return Transform::from_translation(self.focus + rotation * Vec3::Z * self.distance)
+            .with_rotation(rotation);
+    }
+}
+
+pub struct FlyState {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// units per second
+    pub speed: f32,
+    pub look_sensitivity: f32,
+}
+
+impl Default for FlyState {
+    fn default() -> Self {
+        return FlyState {
+            position: Vec3::new(0.0, 2.0, 4.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 2.0,
+            look_sensitivity: 0.003,
+        };
+    }
+}
+
+impl FlyState {
+    fn rotation(&self) -> Quat {
+        return Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+    }
+}
+
+impl CameraController {
+    pub fn mode(&self) -> CameraMode {
+        return self.mode;
+    }
+
+    /// switches the mode, the new one starts out where the camera is right now
+    pub fn set_mode(&mut self, mode: CameraMode, current: &Transform) {
+        if mode == self.mode {
+            return;
+        }
+        let (yaw, pitch, _) = current.rotation.to_euler(EulerRot::YXZ);
+        match mode {
+            CameraMode::Spinning | CameraMode::Path => {}
+            CameraMode::Orbit => {
+                // keep the focus, but look at it from where we are
+                let offset = current.translation - self.orbit.focus;
+                self.orbit.distance = offset.length().max(0.1);
+                let direction = offset / self.orbit.distance;
+                self.orbit.yaw = direction.x.atan2(direction.z);
+                self.orbit.pitch = -direction.y.clamp(-1.0, 1.0).asin();
+            }
+            CameraMode::Fly => {
+                self.fly.position = current.translation;
+                self.fly.yaw = yaw;
+                self.fly.pitch = pitch;
+            }
+        }
+        self.mode = mode;
+        self.transition = Some((*current, 0.0));
+    }
+
+    /// orbits around the point from a distance where something of this radius fits on screen
+    pub fn frame(&mut self, focus: Vec3, radius: f32, current: &Transform) {
+        self.set_mode(CameraMode::Orbit, current);
+        self.orbit.focus = focus;
+        self.orbit.distance = (radius * 3.0).max(0.5);
+        self.transition = Some((*current, 0.0));
+    }
+}
+
+fn orbit_camera(
+    mut contexts: EguiContexts,
+    mut controller: ResMut<CameraController>,
+    gizmo: Res<TransformGizmo>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    mut cameras: Query<&mut Transform, With<SpinningCam>>,
+) {
+    if controller.mode != CameraMode::Orbit {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+    // left drag is taken while moving an object with the gizmo
+    let use_mouse =
+        !ctx.wants_pointer_input() && !ctx.is_pointer_over_area() && !gizmo.is_dragging();
+    let orbit = &mut controller.orbit;
+    if use_mouse {
+        let delta = mouse_motion.delta;
+        if mouse_buttons.pressed(MouseButton::Left) {
+            orbit.yaw -= delta.x * orbit.rotate_sensitivity;
+            orbit.pitch = (orbit.pitch - delta.y * orbit.rotate_sensitivity).clamp(-1.55, 1.55);
+        }
+        if mouse_buttons.pressed(MouseButton::Right) || mouse_buttons.pressed(MouseButton::Middle) {
+            // moves the focus along with the mouse, faster the further away we are
+            let rotation = Quat::from_euler(EulerRot::YXZ, orbit.yaw, orbit.pitch, 0.0);
+            let pan = rotation * Vec3::new(-delta.x, delta.y, 0.0);
+            orbit.focus += pan * orbit.distance * 0.002;
+        }
+        let scroll = match mouse_scroll.unit {
+            MouseScrollUnit::Line => mouse_scroll.delta.y,
+            MouseScrollUnit::Pixel => mouse_scroll.delta.y / 32.0,
+        };
+        orbit.distance = (orbit.distance * (1.0 - scroll * 0.1)).clamp(0.05, 100.0);
+    }
+    let transform = orbit.transform();
+    for mut camera_transform in cameras.iter_mut() {
+        *camera_transform = transform;
+    }
+}
+
+fn fly_camera(
+    mut contexts: EguiContexts,
+    mut controller: ResMut<CameraController>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, With<SpinningCam>>,
+) {
+    if controller.mode != CameraMode::Fly {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+    let use_mouse = !ctx.wants_pointer_input() && !ctx.is_pointer_over_area();
+    let use_keys = !ctx.wants_keyboard_input();
+    let fly = &mut controller.fly;
+    if use_mouse && mouse_buttons.pressed(MouseButton::Right) {
+        fly.yaw -= mouse_motion.delta.x * fly.look_sensitivity;
+        fly.pitch = (fly.pitch - mouse_motion.delta.y * fly.look_sensitivity).clamp(-1.55, 1.55);
+    }
+    if use_keys {
+        let mut movement = Vec3::ZERO;
+        let bindings = [
+            (KeyCode::KeyW, Vec3::NEG_Z),
+            (KeyCode::KeyS, Vec3::Z),
+            (KeyCode::KeyA, Vec3::NEG_X),
+            (KeyCode::KeyD, Vec3::X),
+            (KeyCode::KeyQ, Vec3::NEG_Y),
+            (KeyCode::KeyE, Vec3::Y),
+        ];
+        for (key, direction) in bindings {
+            if keys.pressed(key) {
+                movement += direction;
+            }
+        }
+        let mut speed = fly.speed;
+        if keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight) {
+            speed *= 4.0;
+        }
+        // q/e go straight up and down, the rest follows where we look
+        let horizontal = fly.rotation() * Vec3::new(movement.x, 0.0, movement.z);
+        let vertical = Vec3::Y * movement.y;
+        fly.position += (horizontal + vertical).normalize_or_zero() * speed * time.delta_secs();
+    }
+    let transform = Transform::from_translation(fly.position).with_rotation(fly.rotation());
+    for mut camera_transform in cameras.iter_mut() {
+        *camera_transform = transform;
+    }
+}
+
+// runs after whichever mode set the transform this frame
+pub fn blend_camera_transition(
+    mut controller: ResMut<CameraController>,
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, With<SpinningCam>>,
+) {
+    let Some((from, progress)) = controller.transition else {
+        return;
+    };
+    let progress = progress + time.delta_secs() / TRANSITION_TIME;
+    if progress >= 1.0 {
+        controller.transition = None;
+        return;
+    }
+    controller.transition = Some((from, progress));
+    let t = progress * progress * (3.0 - 2.0 * progress);
+    for mut transform in cameras.iter_mut() {
+        transform.translation = from.translation.lerp(transform.translation, t);
+        transform.rotation = from.rotation.slerp(transform.rotation, t);
+    }
+}
+
+/// roughly how big an object is, for framing it
+pub fn object_radius(desc: &RaymarchObjectDescriptor) -> f32 {
+    match desc.shape_type_id {
+        // half the side length
+        2 => desc.shape_var * 3.0_f32.sqrt(),
+        // the mandelbulb fits in a sphere of about this size, shape_var is the power
+        4 => 1.2,
+        _ => desc.shape_var,
+    }
+}
@@ -0,0 +1,402 @@
+// camera paths for flythroughs
+// keys have a time, a position, a point to look at and a field of view
+// in between, all three go along a catmull-rom spline (with the tangents scaled by the time
+// between keys, so uneven key spacing doesn't overshoot)
+// the path runs on the playback time, so the offline render can use it like any other animation
+// the key positions and look at points can be clicked in the viewport and moved with the
+// transform gizmo (see transform_gizmo.rs)
+// the path is saved with the rest of the scene (see scene_file.rs)
+
+use std::ops::{Add, Div, Mul, Sub};
+
+use bevy::{prelude::*, render::camera::RenderTarget};
+
+use crate::{
+    camera_controller::{blend_camera_transition, CameraController, CameraMode},
+    playback::Playback,
+    spin_camera, SpinningCam,
+};
+
+/// how close a click has to be to a key to select it, in pixels
+const KEY_PICK_DISTANCE: f32 = 8.0;
+
+pub struct CameraPathPlugin;
+impl Plugin for CameraPathPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraPath>();
+        app.add_systems(
+            Update,
+            (
+                follow_camera_path
+                    .after(spin_camera)
+                    .before(blend_camera_transition),
+                draw_camera_path,
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CameraKey {
+    pub time: f32,
+    pub position: Vec3,
+    pub look_at: Vec3,
+    /// vertical, in radians
+    pub fov: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyPoint {
+    Position,
+    LookAt,
+}
+
+/// one of the two points of a key, what the transform gizmo moves
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CameraKeyPoint {
+    /// index into CameraPath::keys
+    pub key: usize,
+    pub point: KeyPoint,
+}
+
+#[derive(Resource)]
+pub struct CameraPath {
+    /// sorted by time
+    pub keys: Vec<CameraKey>,
+    /// draw the path into the scene
+    pub show: bool,
+    /// clicked in the viewport, only one of this and Selection::object is set
+    pub selected: Option<CameraKeyPoint>,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        return CameraPath {
+            keys: Vec::new(),
+            show: true,
+            selected: None,
+        };
+    }
+}
+
+impl CameraPath {
+    // the selection stays on the same key
+    pub fn sort_keys(&mut self) {
+        let mut order: Vec<usize> = (0..self.keys.len()).collect();
+        order.sort_by(|&a, &b| self.keys[a].time.total_cmp(&self.keys[b].time));
+        if let Some(selected) = self.selected.as_mut() {
+            selected.key = order
+                .iter()
+                .position(|&index| index == selected.key)
+                .unwrap_or(0);
+        }
+        self.keys = order.iter().map(|&index| self.keys[index]).collect();
+    }
+
+    pub fn remove_key(&mut self, index: usize) {
+        self.keys.remove(index);
+        self.selected = match self.selected {
+            Some(selected) if selected.key == index => None,
+            Some(selected) if selected.key > index => Some(CameraKeyPoint {
+                key: selected.key - 1,
+                ..selected
+            }),
+            selected => selected,
+        };
+    }
+
+    pub fn point(&self, point: CameraKeyPoint) -> Option<Vec3> {
+        let key = self.keys.get(point.key)?;
+        return Some(match point.point {
+            KeyPoint::Position => key.position,
+            KeyPoint::LookAt => key.look_at,
+        });
+    }
+
+    pub fn set_point(&mut self, point: CameraKeyPoint, value: Vec3) {
+        if let Some(key) = self.keys.get_mut(point.key) {
+            match point.point {
+                KeyPoint::Position => key.position = value,
+                KeyPoint::LookAt => key.look_at = value,
+            }
+        }
+    }
+
+    /// the key point closest to the cursor, if it is close enough and the path is shown
+    pub fn point_at(
+        &self,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        cursor: Vec2,
+    ) -> Option<CameraKeyPoint> {
+        if !self.show {
+            return None;
+        }
+        let mut closest = None;
+        let mut closest_distance = KEY_PICK_DISTANCE;
+        for key in 0..self.keys.len() {
+            for point in [KeyPoint::Position, KeyPoint::LookAt] {
+                let point = CameraKeyPoint { key, point };
+                let Some(position) = self.point(point) else {
+                    continue;
+                };
+                let Ok(on_screen) = camera.world_to_viewport(camera_transform, position) else {
+                    continue;
+                };
+                let distance = on_screen.distance(cursor);
+                if distance < closest_distance {
+                    closest = Some(point);
+                    closest_distance = distance;
+                }
+            }
+        }
+        return closest;
+    }
+
+    pub fn evaluate(&self, time: f32) -> Option<CameraKey> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.time {
+            return Some(*first);
+        }
+        if time >= last.time {
+            return Some(*last);
+        }
+        let index = self.keys.iter().rposition(|key| key.time <= time)?;
+        let before = &self.keys[index.saturating_sub(1)];
+        let from = &self.keys[index];
+        let to = &self.keys[index + 1];
+        let after = &self.keys[(index + 2).min(self.keys.len() - 1)];
+        let segment = [before, from, to, after];
+        return Some(CameraKey {
+            time,
+            position: catmull_rom(segment.map(|key| (key.time, key.position)), time),
+            look_at: catmull_rom(segment.map(|key| (key.time, key.look_at)), time),
+            fov: catmull_rom(segment.map(|key| (key.time, key.fov)), time),
+        });
+    }
+
+    /// one line per key, for the scene file (see scene_file.rs)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_keys(&self, out: &mut String) {
+        out.push_str("# time, position xyz, look at xyz, fov (radians)\n");
+        for key in self.keys.iter() {
+            out.push_str(&format!(
+                "{} {} {} {} {} {} {} {}\n",
+                key.time,
+                key.position.x,
+                key.position.y,
+                key.position.z,
+                key.look_at.x,
+                key.look_at.y,
+                key.look_at.z,
+                key.fov
+            ));
+        }
+    }
+
+    /// a line from write_keys
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn parse_key(line: &str) -> std::io::Result<CameraKey> {
+        let values: Vec<f32> = line
+            .split_whitespace()
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        let [time, px, py, pz, lx, ly, lz, fov] = values[..] else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("expected 8 values, got {}", values.len()),
+            ));
+        };
+        return Ok(CameraKey {
+            time,
+            position: Vec3::new(px, py, pz),
+            look_at: Vec3::new(lx, ly, lz),
+            fov,
+        });
+    }
+}
+
+// hermite form of catmull-rom, with the tangents from the neighbours
+fn catmull_rom<T>(points: [(f32, T); 4], time: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> + Div<f32, Output = T>,
+{
+    let [(t0, p0), (t1, p1), (t2, p2), (t3, p3)] = points;
+    let length = (t2 - t1).max(0.0001);
+    // at the ends, the missing neighbour is the key itself
+    let m1 = (p2 - p0) / (t2 - t0).max(0.0001);
+    let m2 = (p3 - p1) / (t3 - t1).max(0.0001);
+    let s = ((time - t1) / length).clamp(0.0, 1.0);
+    let s2 = s * s;
+    let s3 = s2 * s;
+    return p1 * (2.0 * s3 - 3.0 * s2 + 1.0)
+        + m1 * ((s3 - 2.0 * s2 + s) * length)
+        + p2 * (-2.0 * s3 + 3.0 * s2)
+        + m2 * ((s3 - s2) * length);
+}
+
+pub fn follow_camera_path(
+    camera_path: Res<CameraPath>,
+    controller: Res<CameraController>,
+    playback: Res<Playback>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<SpinningCam>>,
+    // the other modes don't touch the fov, so it goes back to this when we leave path mode
+    mut fov_before_path: Local<Option<f32>>,
+) {
+    if controller.mode() != CameraMode::Path {
+        let Some(fov) = fov_before_path.take() else {
+            return;
+        };
+        for (_, mut projection) in cameras.iter_mut() {
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                perspective.fov = fov;
+            }
+        }
+        return;
+    }
+    let Some(key) = camera_path.evaluate(playback.time) else {
+        return;
+    };
+    for (mut transform, mut projection) in cameras.iter_mut() {
+        // looking_at can't deal with looking at yourself
+        if key.position.distance_squared(key.look_at) > 0.000001 {
+            *transform = Transform::from_translation(key.position).looking_at(key.look_at, Vec3::Y);
+        }
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            fov_before_path.get_or_insert(perspective.fov);
+            perspective.fov = key.fov;
+        }
+    }
+}
+
+fn draw_camera_path(
+    camera_path: Res<CameraPath>,
+    cameras: Query<&Camera, With<SpinningCam>>,
+    mut gizmos: Gizmos,
+) {
+    if !camera_path.show || camera_path.keys.is_empty() {
+        return;
+    }
+    // not in screenshots and offline renders
+    let to_window = cameras
+        .iter()
+        .all(|camera| matches!(camera.target, RenderTarget::Window(_)));
+    if !to_window {
+        return;
+    }
+    let first = camera_path.keys[0].time;
+    let last = camera_path.keys[camera_path.keys.len() - 1].time;
+    let steps = 100;
+    let points = (0..=steps).filter_map(|step| {
+        let time = first + (last - first) * step as f32 / steps as f32;
+        return camera_path.evaluate(time).map(|key| key.position);
+    });
+    gizmos.linestrip(points, Color::srgb(1.0, 0.8, 0.0));
+    for (index, key) in camera_path.keys.iter().enumerate() {
+        let selected = |point| camera_path.selected == Some(CameraKeyPoint { key: index, point });
+        let position_color = if selected(KeyPoint::Position) {
+            Color::srgb(1.0, 1.0, 0.3)
+        } else {
+            Color::srgb(1.0, 0.5, 0.0)
+        };
+        let look_at_color = if selected(KeyPoint::LookAt) {
+            Color::srgb(1.0, 1.0, 0.3)
+        } else {
+            Color::srgb(0.3, 0.6, 1.0)
+        };
+        gizmos.sphere(
+            Isometry3d::from_translation(key.position),
+            0.05,
+            position_color,
+        );
+        gizmos.line(key.position, key.look_at, Color::srgb(0.3, 0.6, 1.0));
+        gizmos.sphere(
+            Isometry3d::from_translation(key.look_at),
+            0.03,
+            look_at_color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32) -> CameraKey {
+        return CameraKey {
+            time,
+            position: Vec3::new(x, 0.0, 1.0),
+            look_at: Vec3::new(0.0, x, 0.0),
+            fov: 0.5 + x * 0.1,
+        };
+    }
+
+    fn path(keys: Vec<CameraKey>) -> CameraPath {
+        return CameraPath { keys, ..default() };
+    }
+
+    #[test]
+    fn catmull_rom_hits_the_keys() {
+        let points = [(0.0, 3.0), (1.0, -1.0), (4.0, 2.0), (5.0, 7.0)];
+        assert_eq!(catmull_rom(points, 1.0), -1.0);
+        assert_eq!(catmull_rom(points, 4.0), 2.0);
+        // clamped to the segment
+        assert_eq!(catmull_rom(points, 10.0), 2.0);
+    }
+
+    #[test]
+    fn catmull_rom_uneven_spacing() {
+        // the tangents are per second, so keys on a line stay on the line however far apart they are
+        let points = [0.0, 0.5, 3.0, 3.25].map(|time| (time, time * 2.0));
+        for time in [0.5, 1.0, 2.0, 2.9, 3.0] {
+            assert!(
+                (catmull_rom(points, time) - time * 2.0).abs() < 0.0001,
+                "{time}"
+            );
+        }
+    }
+
+    #[test]
+    fn evaluate_endpoints() {
+        let path = path(vec![key(1.0, 0.0), key(2.0, 5.0), key(6.0, 1.0)]);
+        assert_eq!(path.evaluate(0.0).unwrap().position, key(1.0, 0.0).position);
+        assert_eq!(path.evaluate(1.0).unwrap().position, key(1.0, 0.0).position);
+        assert_eq!(path.evaluate(2.0).unwrap().position, key(2.0, 5.0).position);
+        assert_eq!(path.evaluate(6.0).unwrap().fov, key(6.0, 1.0).fov);
+        assert_eq!(path.evaluate(100.0).unwrap().look_at, key(6.0, 1.0).look_at);
+        // the time is the one asked for, not the one of the key
+        assert_eq!(path.evaluate(4.0).unwrap().time, 4.0);
+        assert!(CameraPath::default().evaluate(1.0).is_none());
+    }
+
+    #[test]
+    fn evaluate_single_key() {
+        let path = path(vec![key(1.0, 3.0)]);
+        assert_eq!(path.evaluate(0.0).unwrap().position, key(1.0, 3.0).position);
+        assert_eq!(path.evaluate(2.0).unwrap().position, key(1.0, 3.0).position);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse_what_was_written() {
+        let path = path(vec![key(0.0, 1.5), key(2.25, -0.1)]);
+        let mut text = String::new();
+        path.write_keys(&mut text);
+        let keys: Vec<CameraKey> = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| CameraPath::parse_key(line).unwrap())
+            .collect();
+        assert_eq!(keys.len(), path.keys.len());
+        for (parsed, written) in keys.iter().zip(path.keys.iter()) {
+            assert_eq!(parsed.time, written.time);
+            assert_eq!(parsed.position, written.position);
+            assert_eq!(parsed.look_at, written.look_at);
+            assert_eq!(parsed.fov, written.fov);
+        }
+        assert!(CameraPath::parse_key("1 2 3").is_err());
+        assert!(CameraPath::parse_key("1 2 3 4 5 6 7 x").is_err());
+    }
+}
@@ -0,0 +1,492 @@
+// scene to wgsl code generation
+// instead of interpreting the object descriptors in every step (sdf_object, my_min in sdf.wgsl),
+// the current scene gets written out as a straight line wgsl function with all constants inlined
+// it is registered as the raymarch::generated_sdf shader
+//
+// the interpreter shaders must not import that module, bevy recompiles everything that imports
+// a shader when it changes (even behind an #ifdef). so the generated path has its own copies of
+// sdf.wgsl, basic_raymarch.wgsl and basic_raymarch_prepass.wgsl, made from the loaded sources
+// with the imports pointed at each other, and specialize in main.rs picks them with GENERATED_SDF
+// a new scene only recompiles those, the cone prepass and the interpreter stay as they are
+//
+// the generated version is a second material (a copy of the real one with use_generated_sdf),
+// the cube only switches to it once its pipelines are compiled, until then the interpreter draws
+// to get those pipelines compiled without drawing anything, a child of the cube with a degenerate
+// mesh and the generated material is visible the whole time codegen is on
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use bevy::{
+    asset::weak_handle,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::{CachedPipelineState, PipelineCache, PipelineDescriptor, ShaderDefVal},
+        view::{NoFrustumCulling, RenderLayers},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    timeline::Timeline, RaymarchCube, RaymarchMaterial, RaymarchMaterialHandle,
+    RaymarchObjectDescriptor,
+};
+
+const GENERATED_SDF_SHADER: Handle<Shader> = weak_handle!("5d0f1a3e-8c2b-4a47-9e61-3b7c2f9d4e18");
+// the copies of the material shaders that use it
+const GENERATED_SCENE_SHADER: Handle<Shader> = weak_handle!("a83c6e21-4f7d-4b09-8d5e-1c9b2e7f6a34");
+const GENERATED_RAYMARCH_SHADER: Handle<Shader> =
+    weak_handle!("2e94b7c0-6d1a-4f38-b5c2-7a0e9d3f8b61");
+const GENERATED_PREPASS_SHADER: Handle<Shader> =
+    weak_handle!("c17f5d92-3b8e-4a6c-9f20-5e4d1b8a7c09");
+
+pub struct SceneCodegenPlugin;
+impl Plugin for SceneCodegenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GeneratedSdf>();
+        app.init_resource::<GeneratedShaderSources>();
+        app.add_systems(Update, (update_generated_shaders, update_generated_sdf));
+        app.add_plugins(ExtractResourcePlugin::<GeneratedSdfState>::default());
+        app.sub_app_mut(RenderApp)
+            .add_systems(Render, check_generated_sdf.in_set(RenderSet::Cleanup));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct GeneratedSdf {
+    /// compile the scene into its own shader instead of using the interpreter
+    pub enabled: bool,
+    /// what is in GENERATED_SDF_SHADER right now
+    source: String,
+    /// goes up every time the source changes
+    generation: u32,
+    /// the last generation the render world finished compiling
+    ready: Arc<AtomicU32>,
+    /// a timeline track animates something that is baked into the generated code
+    /// every frame would be a new shader, so the interpreter draws meanwhile
+    paused_by_timeline: bool,
+    /// a baked value is NaN or infinite, wgsl has no way to write those down
+    non_finite: bool,
+    /// the copy of the raymarch material with use_generated_sdf
+    material: Option<Handle<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+}
+
+impl GeneratedSdf {
+    pub fn is_ready(&self) -> bool {
+        return self.generation > 0 && self.ready.load(Ordering::Relaxed) == self.generation;
+    }
+
+    pub fn is_paused_by_timeline(&self) -> bool {
+        return self.paused_by_timeline;
+    }
+
+    pub fn has_non_finite_values(&self) -> bool {
+        return self.non_finite;
+    }
+}
+
+/// the shaders the generated copies are made from
+#[derive(Resource)]
+struct GeneratedShaderSources {
+    sdf: Handle<Shader>,
+    raymarch: Handle<Shader>,
+    prepass: Handle<Shader>,
+}
+
+impl FromWorld for GeneratedShaderSources {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        return GeneratedShaderSources {
+            sdf: asset_server.load("shaders/sdf.wgsl"),
+            raymarch: asset_server.load("shaders/basic_raymarch.wgsl"),
+            prepass: asset_server.load("shaders/basic_raymarch_prepass.wgsl"),
+        };
+    }
+}
+
+/// the fragment shader for a material pipeline with GENERATED_SDF (see specialize in main.rs)
+pub fn generated_fragment_shader(shader_defs: &[ShaderDefVal]) -> Handle<Shader> {
+    if shader_defs.contains(&"PREPASS_PIPELINE".into()) {
+        return GENERATED_PREPASS_SHADER;
+    }
+    return GENERATED_RAYMARCH_SHADER;
+}
+
+// (re)makes the copies whenever one of the originals is loaded or hot reloaded
+fn update_generated_shaders(
+    mut events: EventReader<AssetEvent<Shader>>,
+    mut shaders: ResMut<Assets<Shader>>,
+    sources: Res<GeneratedShaderSources>,
+) {
+    let changed = events.read().any(|event| {
+        [&sources.sdf, &sources.raymarch, &sources.prepass]
+            .into_iter()
+            .any(|source| event.is_added(source) || event.is_modified(source))
+    });
+    if !changed {
+        return;
+    }
+    let (Some(sdf), Some(raymarch), Some(prepass)) = (
+        shaders.get(&sources.sdf),
+        shaders.get(&sources.raymarch),
+        shaders.get(&sources.prepass),
+    ) else {
+        return;
+    };
+    let scene = format!(
+        "#define_import_path raymarch::generated_scene\n\
+         #import raymarch::generated_sdf::{{generated_sdf_world, generated_my_min}}\n{}",
+        sdf.source.as_str()
+    );
+    let raymarch = format!(
+        "#define_import_path raymarch::generated_raymarch\n{}",
+        raymarch
+            .source
+            .as_str()
+            .replace("\"shaders/sdf.wgsl\"", "raymarch::generated_scene")
+    );
+    let prepass = prepass.source.as_str().replace(
+        "\"shaders/basic_raymarch.wgsl\"",
+        "raymarch::generated_raymarch",
+    );
+    shaders.insert(
+        &GENERATED_SCENE_SHADER,
+        Shader::from_wgsl(scene, "generated_scene.wgsl"),
+    );
+    shaders.insert(
+        &GENERATED_RAYMARCH_SHADER,
+        Shader::from_wgsl(raymarch, "generated_raymarch.wgsl"),
+    );
+    shaders.insert(
+        &GENERATED_PREPASS_SHADER,
+        Shader::from_wgsl(prepass, "generated_raymarch_prepass.wgsl"),
+    );
+}
+
+/// the degenerate mesh that keeps the generated pipelines compiled
+#[derive(Component)]
+struct GeneratedSdfWarmup;
+
+fn update_generated_sdf(
+    mut commands: Commands,
+    mut generated_sdf: ResMut<GeneratedSdf>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    timeline: Res<Timeline>,
+    mut cubes: Query<
+        (
+            Entity,
+            &mut MeshMaterial3d<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>,
+            Option<&RenderLayers>,
+        ),
+        (With<RaymarchCube>, Without<GeneratedSdfWarmup>),
+    >,
+    mut warmups: Query<(Entity, &mut Visibility, Option<&RenderLayers>), With<GeneratedSdfWarmup>>,
+) {
+    let Ok((cube, mut cube_material, cube_layers)) = cubes.single_mut() else {
+        return;
+    };
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+
+    generated_sdf.paused_by_timeline = timeline.tracks.iter().any(|track| track.bakes_into_sdf());
+    let source = generate_sdf_wgsl(&mat.extension);
+    generated_sdf.non_finite = source.is_none();
+    let source = match source {
+        Some(source) if generated_sdf.enabled && !generated_sdf.paused_by_timeline => source,
+        _ => {
+            for (_, mut visibility, _) in warmups.iter_mut() {
+                visibility.set_if_neq(Visibility::Hidden);
+            }
+            if cube_material.0 != rm_material_handle.0 {
+                cube_material.0 = rm_material_handle.0.clone();
+            }
+            return;
+        }
+    };
+
+    // the generated material follows every change of the real one
+    // but only gets replaced on a change, every insert means a new bind group
+    let mut generated_mat = mat.clone();
+    generated_mat.extension.use_generated_sdf = true;
+    let up_to_date = generated_sdf
+        .material
+        .as_ref()
+        .and_then(|handle| rm_materials.get(handle))
+        .is_some_and(|copy| is_same_material(copy, &generated_mat));
+    let generated_handle = match &generated_sdf.material {
+        Some(handle) if up_to_date => handle.clone(),
+        Some(handle) => {
+            rm_materials.insert(handle, generated_mat);
+            handle.clone()
+        }
+        None => {
+            let handle = rm_materials.add(generated_mat);
+            generated_sdf.material = Some(handle.clone());
+            handle
+        }
+    };
+
+    if warmups.is_empty() {
+        commands.entity(cube).with_child((
+            Mesh3d(meshes.add(Cuboid::from_size(Vec3::ZERO))),
+            MeshMaterial3d(generated_handle.clone()),
+            // the aabb is a point, don't let that decide whether it is drawn
+            NoFrustumCulling,
+            GeneratedSdfWarmup,
+        ));
+    }
+    for (warmup, mut visibility, layers) in warmups.iter_mut() {
+        visibility.set_if_neq(Visibility::Inherited);
+        // same cameras as the cube (dynamic resolution moves it to the low resolution one)
+        if layers != cube_layers {
+            commands
+                .entity(warmup)
+                .insert(cube_layers.cloned().unwrap_or_default());
+        }
+    }
+
+    if source != generated_sdf.source {
+        generated_sdf.generation += 1;
+        shaders.insert(
+            &GENERATED_SDF_SHADER,
+            Shader::from_wgsl(source.clone(), "generated_sdf.wgsl"),
+        );
+        generated_sdf.source = source;
+    }
+    // the interpreter takes over while the new version compiles
+    let target = if generated_sdf.is_ready() {
+        generated_handle
+    } else {
+        rm_material_handle.0.clone()
+    };
+    if cube_material.0 != target {
+        cube_material.0 = target;
+    }
+}
+
+// StandardMaterial can't be compared, but only update_raymarch_pbr_features changes it
+fn is_same_material(
+    a: &ExtendedMaterial<StandardMaterial, RaymarchMaterial>,
+    b: &ExtendedMaterial<StandardMaterial, RaymarchMaterial>,
+) -> bool {
+    return a.extension == b.extension
+        && a.base.specular_transmission == b.base.specular_transmission
+        && a.base.diffuse_transmission == b.base.diffuse_transmission
+        && a.base.clearcoat == b.base.clearcoat
+        && a.base.anisotropy_strength == b.base.anisotropy_strength
+        && a.base.alpha_mode == b.base.alpha_mode;
+}
+
+/// writes the raymarch::generated_sdf module for the current scene
+/// has to do the same as sdf_world and my_min in sdf.wgsl
+/// None if a baked value is NaN or infinite, the interpreter copes with those, wgsl can't
+fn generate_sdf_wgsl(material: &RaymarchMaterial) -> Option<String> {
+    let settings = &material.raymarch_global_settings;
+    let mut out = String::new();
+    out.push_str("// generated by codegen.rs from the current scene\n");
+    out.push_str("#define_import_path raymarch::generated_sdf\n\n");
+    out.push_str("#import \"shaders/sdf_primitives.wgsl\"::{\n");
+    out.push_str("  sdf_circle,\n  sdBox,\n  sdConeBound,\n  sdfMandel,\n  rotation_mat_x,\n");
+    out.push_str("  opSmoothUnion,\n  opSmoothSubtract,\n  opSmoothIntersect,\n}\n\n");
+
+    out.push_str("fn generated_sdf_world(p: vec3<f32>, time: f32) -> vec2<f32> {\n");
+    out.push_str("  return vec2<f32>(generated_object1(p, time), generated_object2(p, time));\n");
+    out.push_str("}\n\n");
+
+    let smooth_amount = wgsl_float(settings.intersection_smooth_amount)?;
+    let intersection = match settings.intersection_method {
+        // same as my_min, a volume leaves the solid object on its own
+        _ if material.material1.volume_type_id != 0 => "b".to_string(),
+        _ if material.material2.volume_type_id != 0 => "a".to_string(),
+        0 => format!("opSmoothUnion(a, b, {smooth_amount})"),
+        1 => format!("opSmoothIntersect(a, b, {smooth_amount})"),
+        2 => format!("opSmoothSubtract(a, b, {smooth_amount})"),
+        _ => "100000.0".to_string(),
+    };
+    out.push_str("fn generated_my_min(a: f32, b: f32) -> f32 {\n");
+    out.push_str(&format!("  return {intersection};\n"));
+    out.push_str("}\n\n");
+
+    write_object_wgsl(&mut out, "generated_object1", &material.material1)?;
+    out.push('\n');
+    write_object_wgsl(&mut out, "generated_object2", &material.material2)?;
+    return Some(out);
+}
+
+// translate_ray + sdf_object
+fn write_object_wgsl(out: &mut String, name: &str, obj: &RaymarchObjectDescriptor) -> Option<()> {
+    out.push_str(&format!("fn {name}(p: vec3<f32>, time: f32) -> f32 {{\n"));
+    // volumes don't have a surface
+    if obj.volume_type_id != 0 {
+        out.push_str("  return 100000.0;\n}\n");
+        return Some(());
+    }
+    out.push_str(&format!(
+        "  var rp = p - {};\n",
+        wgsl_vec3(obj.world_position)?
+    ));
+    if obj.move_amout != 0.0 {
+        out.push_str(&format!(
+            "  rp += vec3<f32>(sin(time * 0.5), cos(time), cos(time) * 0.2) * {};\n",
+            wgsl_float(obj.move_amout)?
+        ));
+    }
+    if obj.rotation_amount != 0.0 {
+        out.push_str(&format!(
+            "  rp = (vec4<f32>(rp, 1.0) * rotation_mat_x({} + {} * time)).xyz;\n",
+            wgsl_float(obj.rotation.x)?,
+            wgsl_float(obj.rotation_amount)?
+        ));
+    } else if obj.rotation.x != 0.0 {
+        out.push_str(&format!(
+            "  rp = (vec4<f32>(rp, 1.0) * rotation_mat_x({})).xyz;\n",
+            wgsl_float(obj.rotation.x)?
+        ));
+    }
+    let shape_var = wgsl_float(obj.shape_var)?;
+    let distance = match obj.shape_type_id {
+        1 => format!("sdf_circle(rp, {shape_var})"),
+        2 => format!("sdBox(rp, vec3<f32>({shape_var}))"),
+        // same offset as in sdf_object
+        3 => format!(
+            "sdConeBound(rp - vec3<f32>(0.0, 0.25, 0.0), {shape_var}, vec2<f32>({}, {}))",
+            wgsl_float(obj.scale.sin())?,
+            wgsl_float(obj.scale.cos())?
+        ),
+        4 => format!("sdfMandel(rp, {shape_var}).distance"),
+        _ => "100000.0".to_string(),
+    };
+    out.push_str(&format!("  return {distance};\n"));
+    out.push_str("}\n");
+    return Some(());
+}
+
+// Debug always prints a decimal point (or an exponent), so wgsl reads it as a float
+// but it also prints NaN and inf, which wgsl has no literal for
+fn wgsl_float(value: f32) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+    return Some(format!("{value:?}"));
+}
+
+fn wgsl_vec3(value: Vec3) -> Option<String> {
+    return Some(format!(
+        "vec3<f32>({}, {}, {})",
+        wgsl_float(value.x)?,
+        wgsl_float(value.y)?,
+        wgsl_float(value.z)?
+    ));
+}
+
+#[derive(Resource, Clone)]
+struct GeneratedSdfState {
+    generation: u32,
+    ready: Arc<AtomicU32>,
+}
+
+impl ExtractResource for GeneratedSdfState {
+    type Source = GeneratedSdf;
+
+    fn extract_resource(source: &Self::Source) -> Self {
+        return GeneratedSdfState {
+            generation: source.generation,
+            ready: source.ready.clone(),
+        };
+    }
+}
+
+// ready once every material pipeline with GENERATED_SDF is compiled (and there is at least one)
+// the shader is extracted in the same frame as the generation, so a changed shader
+// already invalidated those pipelines by the time we get here
+fn check_generated_sdf(state: Option<Res<GeneratedSdfState>>, pipeline_cache: Res<PipelineCache>) {
+    let Some(state) = state else {
+        return;
+    };
+    let mut any_pipeline = false;
+    for pipeline in pipeline_cache.pipelines() {
+        let PipelineDescriptor::RenderPipelineDescriptor(descriptor) = &pipeline.descriptor else {
+            continue;
+        };
+        let generated = descriptor.fragment.as_ref().is_some_and(|fragment| {
+            fragment
+                .shader_defs
+                .iter()
+                .any(|def| *def == ShaderDefVal::Bool("GENERATED_SDF".into(), true))
+        });
+        if !generated {
+            continue;
+        }
+        match pipeline.state {
+            CachedPipelineState::Ok(_) => any_pipeline = true,
+            // an error stays on the interpreter, bevy already logged the shader error
+            _ => return,
+        }
+    }
+    if any_pipeline {
+        state.ready.store(state.generation, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_stay_floats() {
+        assert_eq!(wgsl_float(1.0).unwrap(), "1.0");
+        assert_eq!(wgsl_float(-0.25).unwrap(), "-0.25");
+        assert_eq!(wgsl_float(1e-10).unwrap(), "1e-10");
+        assert_eq!(wgsl_float(f32::NAN), None);
+        assert_eq!(wgsl_float(f32::NEG_INFINITY), None);
+    }
+
+    #[test]
+    fn generates_the_scene() {
+        let mut material = RaymarchMaterial::default();
+        material.material1.shape_type_id = 1;
+        material.material1.shape_var = 0.5;
+        material.material1.world_position = Vec3::new(1.0, 2.0, 3.0);
+        material.material2.shape_type_id = 2;
+        material.raymarch_global_settings.intersection_method = 1;
+        material.raymarch_global_settings.intersection_smooth_amount = 0.25;
+        let wgsl = generate_sdf_wgsl(&material).unwrap();
+        assert!(wgsl.contains("#define_import_path raymarch::generated_sdf\n"));
+        assert!(wgsl.contains("fn generated_object1(p: vec3<f32>, time: f32) -> f32 {\n"));
+        assert!(wgsl.contains("fn generated_object2(p: vec3<f32>, time: f32) -> f32 {\n"));
+        assert!(wgsl.contains("var rp = p - vec3<f32>(1.0, 2.0, 3.0);"));
+        assert!(wgsl.contains("return sdf_circle(rp, 0.5);"));
+        assert!(wgsl.contains("return sdBox(rp, vec3<f32>("));
+        assert!(wgsl.contains("return opSmoothIntersect(a, b, 0.25);"));
+    }
+
+    #[test]
+    fn volumes_have_no_surface() {
+        let mut material = RaymarchMaterial::default();
+        material.material2.volume_type_id = 1;
+        let wgsl = generate_sdf_wgsl(&material).unwrap();
+        assert!(wgsl.contains("fn generated_my_min(a: f32, b: f32) -> f32 {\n  return a;\n}"));
+        assert!(wgsl.contains(
+            "fn generated_object2(p: vec3<f32>, time: f32) -> f32 {\n  return 100000.0;\n}"
+        ));
+    }
+
+    #[test]
+    fn non_finite_values_are_not_generated() {
+        let mut material = RaymarchMaterial::default();
+        material.material1.world_position.y = f32::NAN;
+        assert_eq!(generate_sdf_wgsl(&material), None);
+
+        let mut material = RaymarchMaterial::default();
+        material.raymarch_global_settings.intersection_smooth_amount = f32::INFINITY;
+        assert_eq!(generate_sdf_wgsl(&material), None);
+    }
+}
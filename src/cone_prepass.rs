@@ -0,0 +1,378 @@
+// low resolution cone marching prepass
+// before the main pass, a compute shader marches one cone per TILE_SIZE x TILE_SIZE tile
+// and writes the distance it got to into a texture
+// perform_march then starts every pixel of that tile from there, instead of from the camera
+// this saves a lot of steps on complex sdfs (fractals), where each step is expensive
+
+use bevy::{
+    asset::RenderAssetUsages,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        graph::CameraDriverLabel,
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{
+            binding_types::{texture_storage_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::GpuImage,
+        view::ExtractedView,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    RaymarchGlobalSettings, RaymarchMaterial, RaymarchMaterialHandle, RaymarchMaterialKey,
+    RaymarchObjectDescriptor,
+};
+
+/// size of a tile in pixels, one cone is marched per tile
+/// has to match CONE_PREPASS_TILE_SIZE in basic_raymarch.wgsl
+const TILE_SIZE: u32 = 8;
+/// has to match the workgroup_size in cone_prepass.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+pub struct ConePrepassPlugin;
+impl Plugin for ConePrepassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractResourcePlugin::<ConePrepassSettings>::default());
+        app.add_systems(Startup, setup_cone_prepass_texture);
+        app.add_systems(
+            PostUpdate,
+            (resize_cone_prepass_texture, update_cone_prepass_settings).chain(),
+        );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            prepare_cone_prepass_bind_groups.in_set(RenderSet::PrepareBindGroups),
+        );
+        let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        render_graph.add_node(ConePrepassLabel, ConePrepassNode);
+        // run before any camera renders, so the texture is ready for the main pass
+        render_graph.add_node_edge(ConePrepassLabel, CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ConePrepassPipeline>();
+        render_app.init_resource::<SpecializedComputePipelines<ConePrepassPipeline>>();
+        render_app.init_resource::<ConePrepassBuffers>();
+    }
+}
+
+/// the texture the prepass writes to, and the material reads from
+#[derive(Resource)]
+struct ConePrepassTexture(Handle<Image>);
+
+/// everything the prepass needs from the main world
+#[derive(Resource, Clone, ExtractResource)]
+struct ConePrepassSettings {
+    enabled: bool,
+    texture: Handle<Image>,
+    material1: RaymarchObjectDescriptor,
+    material2: RaymarchObjectDescriptor,
+    raymarch_global_settings: RaymarchGlobalSettings,
+    key: RaymarchMaterialKey,
+}
+
+fn setup_cone_prepass_texture(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &0.0f32.to_ne_bytes(),
+        TextureFormat::R32Float,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    commands.insert_resource(ConePrepassTexture(images.add(image)));
+}
+
+// one texel per tile of the camera
+// with dynamic resolution the raymarch is drawn by the low resolution camera, which renders first
+fn resize_cone_prepass_texture(
+    cameras: Query<&Camera, With<Camera3d>>,
+    prepass_texture: Res<ConePrepassTexture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(viewport_size) = cameras
+        .iter()
+        .filter(|camera| camera.is_active)
+        .min_by_key(|camera| camera.order)
+        .and_then(|camera| camera.physical_viewport_size())
+    else {
+        return;
+    };
+    let tiles = viewport_size.div_ceil(UVec2::splat(TILE_SIZE)).max(UVec2::ONE);
+    let size = Extent3d {
+        width: tiles.x,
+        height: tiles.y,
+        depth_or_array_layers: 1,
+    };
+    // only touch the image if we have to, get_mut makes bevy upload it again
+    let needs_resize = images
+        .get(&prepass_texture.0)
+        .is_some_and(|image| image.texture_descriptor.size != size);
+    if needs_resize {
+        if let Some(image) = images.get_mut(&prepass_texture.0) {
+            image.resize(size);
+        }
+    }
+}
+
+fn update_cone_prepass_settings(
+    mut commands: Commands,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    prepass_texture: Res<ConePrepassTexture>,
+) {
+    let maybe_mat = rm_materials.get_mut(&rm_material_handle.0);
+    if let Some(mat) = maybe_mat {
+        let ext = &mut mat.extension;
+        // the demo configs replace the whole material, so set it again every frame
+        if ext.cone_prepass_texture.as_ref() != Some(&prepass_texture.0) {
+            ext.cone_prepass_texture = Some(prepass_texture.0.clone());
+        }
+        commands.insert_resource(ConePrepassSettings {
+            // the cones start at the camera, rays from all over the lens don't fit in there
+            enabled: ext.raymarch_global_settings.cone_prepass != 0
+                && ext.raymarch_global_settings.aperture <= 0.0,
+            texture: prepass_texture.0.clone(),
+            material1: ext.material1.clone(),
+            material2: ext.material2.clone(),
+            raymarch_global_settings: ext.raymarch_global_settings.clone(),
+            key: RaymarchMaterialKey::from(&*ext),
+        });
+    }
+}
+
+// has to reflect ConePrepassCamera in cone_prepass.wgsl
+#[derive(Debug, Clone, Default, ShaderType)]
+struct ConePrepassCamera {
+    world_from_clip: Mat4,
+    world_position: Vec3,
+    pixel_size: f32,
+    viewport_size: Vec2,
+    tile_size: f32,
+}
+
+#[derive(Resource)]
+struct ConePrepassPipeline {
+    prepass_layout: BindGroupLayout,
+    empty_layout: BindGroupLayout,
+    sdf_layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl FromWorld for ConePrepassPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let prepass_layout = render_device.create_bind_group_layout(
+            "cone_prepass_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    texture_storage_2d(TextureFormat::R32Float, StorageTextureAccess::WriteOnly),
+                    uniform_buffer::<ConePrepassCamera>(false),
+                ),
+            ),
+        );
+        // sdf.wgsl lives in the material bind group (2), so we have to put it there too
+        let empty_layout = render_device.create_bind_group_layout("cone_prepass_empty_layout", &[]);
+        let sdf_layout = render_device.create_bind_group_layout(
+            "cone_prepass_sdf_layout",
+            &BindGroupLayoutEntries::with_indices(
+                ShaderStages::COMPUTE,
+                (
+                    (100, uniform_buffer::<RaymarchObjectDescriptor>(false)),
+                    (101, uniform_buffer::<RaymarchObjectDescriptor>(false)),
+                    (102, uniform_buffer::<RaymarchGlobalSettings>(false)),
+                ),
+            ),
+        );
+        let shader = world.load_asset("shaders/cone_prepass.wgsl");
+        return ConePrepassPipeline {
+            prepass_layout,
+            empty_layout,
+            sdf_layout,
+            shader,
+        };
+    }
+}
+
+// same shader defs as the material, see RaymarchMaterial::specialize
+impl SpecializedComputePipeline for ConePrepassPipeline {
+    type Key = RaymarchMaterialKey;
+
+    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
+        return ComputePipelineDescriptor {
+            label: Some("cone_prepass_pipeline".into()),
+            layout: vec![
+                self.prepass_layout.clone(),
+                self.empty_layout.clone(),
+                self.sdf_layout.clone(),
+            ],
+            push_constant_ranges: Vec::new(),
+            shader: self.shader.clone(),
+            shader_defs: key.shader_defs(),
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        };
+    }
+}
+
+/// the uniforms live on between frames, only their contents get written again
+#[derive(Resource, Default)]
+struct ConePrepassBuffers {
+    camera: UniformBuffer<ConePrepassCamera>,
+    material1: UniformBuffer<RaymarchObjectDescriptor>,
+    material2: UniformBuffer<RaymarchObjectDescriptor>,
+    global_settings: UniformBuffer<RaymarchGlobalSettings>,
+}
+
+#[derive(Resource)]
+struct ConePrepassBindGroups {
+    prepass: BindGroup,
+    empty: BindGroup,
+    sdf: BindGroup,
+    tiles: UVec2,
+    pipeline: CachedComputePipelineId,
+}
+
+fn prepare_cone_prepass_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<ConePrepassPipeline>,
+    mut pipelines: ResMut<SpecializedComputePipelines<ConePrepassPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    settings: Option<Res<ConePrepassSettings>>,
+    mut buffers: ResMut<ConePrepassBuffers>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    views: Query<(&ExtractedView, &ExtractedCamera)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    commands.remove_resource::<ConePrepassBindGroups>();
+    let Some(settings) = settings.filter(|settings| settings.enabled) else {
+        return;
+    };
+    let Some(gpu_image) = gpu_images.get(&settings.texture) else {
+        return;
+    };
+    // same camera as in resize_cone_prepass_texture
+    let Some((view, _)) = views.iter().min_by_key(|(_, camera)| camera.order) else {
+        return;
+    };
+    // parallel rays, basic_raymarch.wgsl ignores the prepass then
+    if view.clip_from_view.w_axis.w == 1.0 {
+        return;
+    }
+
+    let viewport_size = view.viewport.zw().as_vec2();
+    let world_from_view = view.world_from_view.compute_matrix();
+    let buffers = buffers.into_inner();
+    buffers.camera.set(ConePrepassCamera {
+        world_from_clip: world_from_view * view.clip_from_view.inverse(),
+        world_position: view.world_from_view.translation(),
+        // clip_from_view[1][1] is 1 / tan(fov_y / 2)
+        pixel_size: 2.0 / (view.clip_from_view.y_axis.y * viewport_size.y),
+        viewport_size,
+        tile_size: TILE_SIZE as f32,
+    });
+    buffers.material1.set(settings.material1.clone());
+    buffers.material2.set(settings.material2.clone());
+    buffers
+        .global_settings
+        .set(settings.raymarch_global_settings.clone());
+    // the buffers are only created the first time, after that it's just a queue write
+    buffers.camera.write_buffer(&render_device, &render_queue);
+    buffers
+        .material1
+        .write_buffer(&render_device, &render_queue);
+    buffers
+        .material2
+        .write_buffer(&render_device, &render_queue);
+    buffers
+        .global_settings
+        .write_buffer(&render_device, &render_queue);
+
+    let (Some(camera), Some(material1), Some(material2), Some(global_settings)) = (
+        buffers.camera.binding(),
+        buffers.material1.binding(),
+        buffers.material2.binding(),
+        buffers.global_settings.binding(),
+    ) else {
+        return;
+    };
+    let prepass = render_device.create_bind_group(
+        "cone_prepass_bind_group",
+        &pipeline.prepass_layout,
+        &BindGroupEntries::sequential((&gpu_image.texture_view, camera)),
+    );
+    let empty = render_device.create_bind_group(
+        "cone_prepass_empty_bind_group",
+        &pipeline.empty_layout,
+        &[],
+    );
+    let sdf = render_device.create_bind_group(
+        "cone_prepass_sdf_bind_group",
+        &pipeline.sdf_layout,
+        &BindGroupEntries::with_indices((
+            (100, material1),
+            (101, material2),
+            (102, global_settings),
+        )),
+    );
+    commands.insert_resource(ConePrepassBindGroups {
+        prepass,
+        empty,
+        sdf,
+        tiles: UVec2::new(gpu_image.texture.width(), gpu_image.texture.height()),
+        pipeline: pipelines.specialize(&pipeline_cache, &pipeline, settings.key.clone()),
+    });
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct ConePrepassLabel;
+
+struct ConePrepassNode;
+impl render_graph::Node for ConePrepassNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        // turned off, or nothing to do yet
+        let Some(bind_groups) = world.get_resource::<ConePrepassBindGroups>() else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(compute_pipeline) = pipeline_cache.get_compute_pipeline(bind_groups.pipeline)
+        else {
+            return Ok(());
+        };
+
+        let mut pass = render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some("cone_prepass"),
+                ..default()
+            });
+        pass.set_bind_group(0, &bind_groups.prepass, &[]);
+        pass.set_bind_group(1, &bind_groups.empty, &[]);
+        pass.set_bind_group(2, &bind_groups.sdf, &[]);
+        pass.set_pipeline(compute_pipeline);
+        let workgroups = bind_groups.tiles.div_ceil(UVec2::splat(WORKGROUP_SIZE));
+        pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+        return Ok(());
+    }
+}
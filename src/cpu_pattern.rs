@@ -0,0 +1,115 @@
+// the procedural patterns on the cpu, for the cpu renderer (see cpu_render.rs)
+// has to do the same as apply_pattern, fbm and get_curvature in basic_raymarch.wgsl
+// the float math isn't bit for bit the one of the gpu, so the noise looks a bit different
+
+use bevy::prelude::*;
+
+use crate::{cpu_sdf, RaymarchMaterial, RaymarchObjectDescriptor};
+
+/// the color of obj at world_pos with its pattern on it, like apply_pattern
+pub fn apply_pattern(
+    material: &RaymarchMaterial,
+    obj: &RaymarchObjectDescriptor,
+    world_pos: Vec3,
+) -> Vec4 {
+    if obj.pattern_type_id == 0 {
+        return obj.base_color;
+    }
+    let time = material.raymarch_global_settings.time;
+    let object_pos = cpu_sdf::translate_ray(world_pos, obj, time);
+    let p = object_pos * obj.pattern_scale;
+    let t = match obj.pattern_type_id {
+        // checker
+        1 => step(0.5, fract((p.x.floor() + p.y.floor() + p.z.floor()) * 0.5)),
+        // stripes
+        2 => step(0.5, fract(p.x)),
+        // noise
+        3 => fbm(p),
+        // marble
+        4 => (p.x + fbm(p) * 6.0).sin() * 0.5 + 0.5,
+        // gradient by height, -1 to 1 in object space (before scaling)
+        5 => (p.y / obj.pattern_scale * 0.5 + 0.5).clamp(0.0, 1.0),
+        // gradient by curvature, 0.5 is flat
+        6 => (0.5 + curvature(material, world_pos) * 0.01 * obj.pattern_scale).clamp(0.0, 1.0),
+        // orbit trap, uses the palette instead of base_color -> pattern_color
+        7 => {
+            if obj.shape_type_id != 4 {
+                return obj.base_color;
+            }
+            let trap = cpu_sdf::sdf_mandel(object_pos, obj.shape_var).trap;
+            let trap_value = match obj.orbit_trap_type_id {
+                1 => trap.y,
+                2 => trap.z,
+                _ => trap.x,
+            };
+            return sample_palette(
+                &obj.palette,
+                (trap_value * obj.pattern_scale).clamp(0.0, 1.0),
+            );
+        }
+        _ => 0.0,
+    };
+    return obj.base_color.lerp(obj.pattern_color, t);
+}
+
+// 4 colors, evenly spaced from 0.0 to 1.0
+fn sample_palette(palette: &[Vec4; 4], t: f32) -> Vec4 {
+    let scaled = t * 3.0;
+    let i = scaled.floor().min(2.0) as usize;
+    return palette[i].lerp(palette[i + 1], scaled - i as f32);
+}
+
+// laplacian of the sdf, positive on convex and negative on concave surfaces
+fn curvature(material: &RaymarchMaterial, p: Vec3) -> f32 {
+    let e = 0.01;
+    let distance = |offset: Vec3| cpu_sdf::sdf_world_min(material, p + offset);
+    let sum = distance(Vec3::X * e)
+        + distance(Vec3::NEG_X * e)
+        + distance(Vec3::Y * e)
+        + distance(Vec3::NEG_Y * e)
+        + distance(Vec3::Z * e)
+        + distance(Vec3::NEG_Z * e);
+    return (sum - 6.0 * distance(Vec3::ZERO)) / (e * e);
+}
+
+// wgsl's fract, f32::fract rounds towards zero
+fn fract(x: f32) -> f32 {
+    return x - x.floor();
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+fn step(edge: f32, x: f32) -> f32 {
+    return if x >= edge { 1.0 } else { 0.0 };
+}
+
+fn hash3(p: Vec3) -> f32 {
+    return fract(p.dot(Vec3::new(127.1, 311.7, 74.7)).sin() * 43758.5453);
+}
+
+// value noise, 0.0-1.0
+fn noise3(p: Vec3) -> f32 {
+    let i = p.floor();
+    let f = p - i;
+    let u = f * f * (3.0 - 2.0 * f);
+    let corner = |x: f32, y: f32, z: f32| hash3(i + Vec3::new(x, y, z));
+    let x00 = mix(corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), u.x);
+    let x10 = mix(corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), u.x);
+    let x01 = mix(corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), u.x);
+    let x11 = mix(corner(0.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+fn fbm(p: Vec3) -> f32 {
+    let mut out = 0.0;
+    let mut amplitude = 0.5;
+    let mut q = p;
+    for _ in 0..4 {
+        out += noise3(q) * amplitude;
+        q *= 2.0;
+        amplitude *= 0.5;
+    }
+    return out / 0.9375; // sum of the amplitudes
+}
@@ -0,0 +1,100 @@
+// renders the scene without the gpu, for the offline render (see offline_render.rs)
+// every pixel marches cpu_sdf and the hit gets one point light, lambert + blinn phong
+// the procedural patterns are there (see cpu_pattern.rs), but otherwise it's
+// much simpler than basic_raymarch.wgsl: no textures, bounces, transmission, volumes or glow,
+// and the hit takes the color of the closer object instead of blending the materials
+
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+use image::RgbaImage;
+
+use crate::{
+    cpu_pattern,
+    cpu_sdf::{self, SdfHit},
+    RaymarchMaterial,
+};
+
+/// how bright the side facing away from the light is
+const AMBIENT: f32 = 0.1;
+/// offset for the normals, in world units
+const NORMAL_EPSILON: f32 = 0.0005;
+
+/// everything the renderer needs besides the scene
+pub struct CpuView {
+    pub camera: GlobalTransform,
+    /// vertical field of view, in radians
+    pub fov: f32,
+    pub light_position: Vec3,
+    /// what the rays that miss see
+    pub background: LinearRgba,
+}
+
+pub fn render_frame(material: &RaymarchMaterial, view: &CpuView, size: UVec2) -> RgbaImage {
+    // one task per row, the rows come back in order
+    let rows: Vec<Vec<u8>> = ComputeTaskPool::get().scope(|scope| {
+        for y in 0..size.y {
+            scope.spawn(async move {
+                return (0..size.x)
+                    .flat_map(|x| render_pixel(material, view, size, UVec2::new(x, y)))
+                    .collect();
+            });
+        }
+    });
+    return RgbaImage::from_raw(size.x, size.y, rows.concat())
+        .expect("every row has size.x pixels");
+}
+
+fn render_pixel(material: &RaymarchMaterial, view: &CpuView, size: UVec2, pixel: UVec2) -> [u8; 4] {
+    let ray = camera_ray(view, size, pixel);
+    let color = match cpu_sdf::raycast(material, ray) {
+        Some(hit) => shade(material, view, ray, hit),
+        None => view.background,
+    };
+    // the png is srgb
+    return Srgba::from(color).to_u8_array();
+}
+
+/// the ray through the middle of the pixel, y goes down like in the image
+pub fn camera_ray(view: &CpuView, size: UVec2, pixel: UVec2) -> Ray3d {
+    let ndc = (pixel.as_vec2() + 0.5) / size.as_vec2() * 2.0 - 1.0;
+    let half_height = (view.fov * 0.5).tan();
+    let aspect = size.x as f32 / size.y.max(1) as f32;
+    let direction = view.camera.affine().transform_vector3(Vec3::new(
+        ndc.x * half_height * aspect,
+        -ndc.y * half_height,
+        -1.0,
+    ));
+    return Ray3d::new(
+        view.camera.translation(),
+        Dir3::new(direction).unwrap_or(Dir3::NEG_Z),
+    );
+}
+
+fn shade(material: &RaymarchMaterial, view: &CpuView, ray: Ray3d, hit: SdfHit) -> LinearRgba {
+    let obj = hit.object.descriptor(material);
+    let normal = normal_at(material, hit.position);
+    let to_light = (view.light_position - hit.position).normalize_or_zero();
+    let diffuse = normal.dot(to_light).max(0.0);
+    let halfway = (to_light - *ray.direction).normalize_or_zero();
+    let smoothness = 1.0 - obj.perceptual_roughness.clamp(0.0, 1.0);
+    let specular = normal
+        .dot(halfway)
+        .max(0.0)
+        .powf(1.0 + smoothness * smoothness * 256.0)
+        * smoothness;
+    let base_color = cpu_pattern::apply_pattern(material, obj, hit.position).truncate();
+    let color = base_color * (AMBIENT + diffuse * (1.0 - obj.metallic))
+        + Vec3::splat(specular).lerp(base_color * specular, obj.metallic)
+        + obj.emissive.truncate();
+    return LinearRgba::new(color.x, color.y, color.z, 1.0);
+}
+
+// central differences
+fn normal_at(material: &RaymarchMaterial, position: Vec3) -> Vec3 {
+    let distance = |offset: Vec3| cpu_sdf::sdf_world_min(material, position + offset);
+    return Vec3::new(
+        distance(Vec3::X * NORMAL_EPSILON) - distance(Vec3::NEG_X * NORMAL_EPSILON),
+        distance(Vec3::Y * NORMAL_EPSILON) - distance(Vec3::NEG_Y * NORMAL_EPSILON),
+        distance(Vec3::Z * NORMAL_EPSILON) - distance(Vec3::NEG_Z * NORMAL_EPSILON),
+    )
+    .normalize_or_zero();
+}
@@ -0,0 +1,291 @@
+// the scene sdf on the cpu, for ray queries like picking
+// has to do the same as sdf_world and my_min in sdf.wgsl (and the shapes in sdf_primitives.wgsl)
+// the only difference: volumes count as solid here, so they can be clicked on too
+
+use bevy::prelude::*;
+
+use crate::{RaymarchGlobalSettings, RaymarchMaterial, RaymarchObjectDescriptor};
+
+/// same as in sdf_primitives.wgsl
+const BAILOUT: f32 = 3.0;
+const MISS_DISTANCE: f32 = 100000.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SdfObject {
+    Object1,
+    Object2,
+}
+
+impl SdfObject {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SdfObject::Object1 => "Object 1",
+            SdfObject::Object2 => "Object 2",
+        }
+    }
+
+    pub fn descriptor<'a>(&self, material: &'a RaymarchMaterial) -> &'a RaymarchObjectDescriptor {
+        match self {
+            SdfObject::Object1 => &material.material1,
+            SdfObject::Object2 => &material.material2,
+        }
+    }
+
+    pub fn descriptor_mut<'a>(
+        &self,
+        material: &'a mut RaymarchMaterial,
+    ) -> &'a mut RaymarchObjectDescriptor {
+        match self {
+            SdfObject::Object1 => &mut material.material1,
+            SdfObject::Object2 => &mut material.material2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SdfHit {
+    pub object: SdfObject,
+    pub position: Vec3,
+    /// along the ray
+    pub distance: f32,
+}
+
+/// plain sphere tracing, like march_ray in basic_raymarch.wgsl
+pub fn raycast(material: &RaymarchMaterial, ray: Ray3d) -> Option<SdfHit> {
+    let settings = &material.raymarch_global_settings;
+    let termination_distance = settings.termination_distance.max(0.0001);
+    let mut t = 0.0;
+    for _ in 0..settings.max_iterations {
+        if t >= settings.far_clip {
+            break;
+        }
+        let position = ray.get_point(t);
+        let [distance1, distance2] = sdf_world(material, position);
+        let distance = my_min(settings, distance1, distance2);
+        if distance < termination_distance {
+            // the surface belongs to whichever object is closer
+            // (abs, with a NOT intersection we are inside of the second one)
+            let object = if distance1.abs() <= distance2.abs() {
+                SdfObject::Object1
+            } else {
+                SdfObject::Object2
+            };
+            return Some(SdfHit {
+                object,
+                position,
+                distance: t,
+            });
+        }
+        t += distance;
+    }
+    return None;
+}
+
+/// distance to both objects
+pub fn sdf_world(material: &RaymarchMaterial, position: Vec3) -> [f32; 2] {
+    let time = material.raymarch_global_settings.time;
+    return [&material.material1, &material.material2]
+        .map(|obj| sdf_object(translate_ray(position, obj, time), obj));
+}
+
+/// distance to the whole scene, like sdf_world_min in sdf.wgsl
+pub fn sdf_world_min(material: &RaymarchMaterial, position: Vec3) -> f32 {
+    let [distance1, distance2] = sdf_world(material, position);
+    return my_min(&material.raymarch_global_settings, distance1, distance2);
+}
+
+pub fn my_min(settings: &RaymarchGlobalSettings, a: f32, b: f32) -> f32 {
+    // the gpu gets away with dividing by 0, we would get NaNs
+    let k = settings.intersection_smooth_amount.max(0.000001);
+    match settings.intersection_method {
+        0 => op_smooth_union(a, b, k),
+        1 => op_smooth_intersect(a, b, k),
+        2 => op_smooth_subtract(a, b, k),
+        _ => MISS_DISTANCE,
+    }
+}
+
+fn sdf_object(position: Vec3, obj: &RaymarchObjectDescriptor) -> f32 {
+    match obj.shape_type_id {
+        1 => position.length() - obj.shape_var,
+        2 => sd_box(position, Vec3::splat(obj.shape_var)),
+        // same offset as in sdf_object
+        3 => sd_cone_bound(
+            position - Vec3::new(0.0, 0.25, 0.0),
+            obj.shape_var,
+            Vec2::new(obj.scale.sin(), obj.scale.cos()),
+        ),
+        4 => sdf_mandel(position, obj.shape_var).distance,
+        _ => MISS_DISTANCE,
+    }
+}
+
+/// world position -> object space, like translate_ray in sdf.wgsl
+pub fn translate_ray(position: Vec3, obj: &RaymarchObjectDescriptor, time: f32) -> Vec3 {
+    let out = position - object_center(obj, time);
+    // the shader multiplies the row vector from the left, which is the transposed matrix
+    return Mat3::from_rotation_x(rotation_x(obj, time)).transpose() * out;
+}
+
+/// where the object is right now, with the hardcoded movement
+pub fn object_center(obj: &RaymarchObjectDescriptor, time: f32) -> Vec3 {
+    let added_translation = Vec3::new(
+        (time * 0.5).sin() * obj.move_amout,
+        time.cos() * obj.move_amout,
+        time.cos() * obj.move_amout * 0.2,
+    );
+    return obj.world_position - added_translation;
+}
+
+/// like get_rotation_x in sdf.wgsl
+pub fn rotation_x(obj: &RaymarchObjectDescriptor, time: f32) -> f32 {
+    return obj.rotation.x + obj.rotation_amount * time;
+}
+
+fn sd_box(p: Vec3, b: Vec3) -> f32 {
+    let q = p.abs() - b;
+    return q.max(Vec3::ZERO).length() + q.max_element().min(0.0);
+}
+
+fn sd_cone_bound(p: Vec3, h: f32, sincos: Vec2) -> f32 {
+    let q = Vec2::new(Vec2::new(p.x, p.z).length(), p.y);
+    return Vec2::new(sincos.y, sincos.x).dot(q).max(-h - p.y);
+}
+
+fn op_smooth_union(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+    return d2 + (d1 - d2) * h - k * h * (1.0 - h);
+}
+
+fn op_smooth_subtract(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 - 0.5 * (d1 + d2) / k).clamp(0.0, 1.0);
+    return d1 + (-d2 - d1) * h + k * h * (1.0 - h);
+}
+
+fn op_smooth_intersect(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = (0.5 - 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+    return d2 + (d1 - d2) * h + k * h * (1.0 - h);
+}
+
+pub struct FractalOutput {
+    pub distance: f32,
+    /// min distance to the origin, min distance to the axis planes, iterations / 16
+    pub trap: Vec3,
+}
+
+/// like sdfMandel in sdf_primitives.wgsl
+pub fn sdf_mandel(point: Vec3, power: f32) -> FractalOutput {
+    let mut z = point;
+    let mut dr = 1.0;
+    let mut dist = 0.0;
+    let mut trap = Vec3::new(1000000.0, 1000000.0, 0.0);
+    for _ in 0..16 {
+        dist = z.length();
+        if dist > BAILOUT {
+            break;
+        }
+        trap.x = trap.x.min(dist);
+        trap.y = trap.y.min(z.abs().min_element());
+        trap.z += 1.0 / 16.0;
+        // to polar coordinates
+        let theta = (z.z / dist).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        // scale and rotate
+        let dist_pow_minus_one = dist.powf(power - 1.0);
+        let zr = dist_pow_minus_one * dist;
+        dr = dist_pow_minus_one * power * dr + 1.0;
+        // back to cartesian coordinates
+        let sin_theta = theta.sin();
+        z = zr * Vec3::new(sin_theta * phi.cos(), phi.sin() * sin_theta, theta.cos());
+        z += point;
+    }
+    return FractalOutput {
+        distance: 0.5 * dist.ln() * dist / dr,
+        trap,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn shape(shape_type_id: u32, shape_var: f32) -> RaymarchObjectDescriptor {
+        return RaymarchObjectDescriptor {
+            world_position: Vec3::ZERO,
+            shape_type_id,
+            shape_var,
+            ..default()
+        };
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.0001, "{a} != {b}");
+    }
+
+    #[test]
+    fn sphere_distance() {
+        let sphere = shape(1, 0.5);
+        assert_close(sdf_object(Vec3::new(2.0, 0.0, 0.0), &sphere), 1.5);
+        assert_close(sdf_object(Vec3::new(0.0, -0.5, 0.0), &sphere), 0.0);
+        assert_close(sdf_object(Vec3::ZERO, &sphere), -0.5);
+    }
+
+    #[test]
+    fn box_distance() {
+        let cube = shape(2, 1.0);
+        // to a face, an edge and from the inside
+        assert_close(sdf_object(Vec3::new(3.0, 0.0, 0.0), &cube), 2.0);
+        assert_close(sdf_object(Vec3::new(2.0, 2.0, 0.5), &cube), 2.0_f32.sqrt());
+        assert_close(sdf_object(Vec3::new(0.5, 0.0, 0.0), &cube), -0.5);
+    }
+
+    #[test]
+    fn unknown_shape_misses() {
+        assert_eq!(sdf_object(Vec3::ZERO, &shape(0, 1.0)), MISS_DISTANCE);
+    }
+
+    #[test]
+    fn translate_ray_moves_and_rotates() {
+        let mut obj = shape(1, 1.0);
+        obj.world_position = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!(
+            translate_ray(Vec3::new(1.0, 3.0, 3.0), &obj, 0.0),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+
+        // a quarter turn, once from the rotation and once from the animated rotation
+        obj.rotation.x = FRAC_PI_2;
+        let rotated = translate_ray(Vec3::new(1.0, 3.0, 3.0), &obj, 0.0);
+        assert!(
+            rotated.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), 0.0001),
+            "{rotated}"
+        );
+        obj.rotation.x = 0.0;
+        obj.rotation_amount = FRAC_PI_2;
+        let animated = translate_ray(Vec3::new(1.0, 3.0, 3.0), &obj, 1.0);
+        assert!(animated.abs_diff_eq(rotated, 0.0001), "{animated}");
+    }
+
+    #[test]
+    fn object_center_moves_with_time() {
+        let mut obj = shape(1, 1.0);
+        obj.move_amout = 2.0;
+        assert!(object_center(&obj, 0.0).abs_diff_eq(Vec3::new(0.0, -2.0, -0.4), 0.0001));
+    }
+
+    #[test]
+    fn raycast_hits_the_sphere() {
+        let mut material = RaymarchMaterial::default();
+        material.material1 = shape(1, 1.0);
+        material.material2 = shape(0, 1.0);
+        let ray = Ray3d::new(Vec3::new(0.0, 0.0, 5.0), Dir3::NEG_Z);
+        let hit = raycast(&material, ray).unwrap();
+        assert_eq!(hit.object, SdfObject::Object1);
+        assert!((hit.distance - 4.0).abs() < 0.01, "{}", hit.distance);
+
+        let away = Ray3d::new(Vec3::new(0.0, 0.0, 5.0), Dir3::Z);
+        assert!(raycast(&material, away).is_none());
+    }
+}
@@ -0,0 +1,242 @@
+// dynamic resolution
+// the raymarch is the expensive part of the frame, so it can be rendered at a fraction of the
+// window resolution by a second camera into an offscreen image
+// a copy of the raymarched cube with the upsampling material then puts it back into the main view
+// the cubes switch RenderLayers, so every camera only sees the one it should draw
+// limitations: hit surfaces count as opaque, and volumes don't see the rest of the scene
+
+use bevy::{
+    asset::RenderAssetUsages,
+    core_pipeline::tonemapping::Tonemapping,
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    pbr::{ExtendedMaterial, NotShadowCaster},
+    prelude::*,
+    render::{
+        camera::{CameraUpdateSystem, RenderTarget},
+        render_resource::{
+            AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat, TextureUsages,
+        },
+        view::RenderLayers,
+    },
+};
+
+use crate::{
+    accumulation::is_accumulating, RaymarchCube, RaymarchMaterial, RaymarchMaterialHandle,
+};
+
+/// only the low resolution camera sees this layer
+pub const LOW_RES_LAYER: usize = 1;
+
+pub struct DynamicResolutionPlugin;
+impl Plugin for DynamicResolutionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<RaymarchUpscaleMaterial>::default());
+        app.add_systems(PostStartup, setup_dynamic_resolution);
+        app.add_systems(
+            Update,
+            (update_resolution_scale, update_low_res_target).chain(),
+        );
+        app.add_systems(
+            PostUpdate,
+            follow_main_camera
+                .before(TransformSystem::TransformPropagate)
+                .before(CameraUpdateSystem),
+        );
+    }
+}
+
+#[derive(Component)]
+struct LowResCamera;
+
+/// the copy of the raymarched cube that draws the upsampled image in the main view
+#[derive(Component)]
+struct LowResComposite;
+
+#[derive(Resource)]
+struct LowResTarget(Handle<Image>);
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+struct RaymarchUpscaleMaterial {
+    // rgb -> premultiplied color, a -> hit distance or -alpha (see encode_low_res)
+    #[texture(0)]
+    low_res_texture: Handle<Image>,
+}
+
+impl Material for RaymarchUpscaleMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/dynamic_resolution.wgsl".into()
+    }
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+// runs after setup, so the raymarched cube exists
+fn setup_dynamic_resolution(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut upscale_materials: ResMut<Assets<RaymarchUpscaleMaterial>>,
+    cubes: Query<(Entity, &Mesh3d), With<RaymarchCube>>,
+) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 8],
+        // has to be float, the alpha channel holds distances
+        TextureFormat::Rgba16Float,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_DST
+        | TextureUsages::RENDER_ATTACHMENT;
+    let image_handle = images.add(image);
+
+    commands.spawn((
+        Camera3d::default(),
+        Camera {
+            // before the main camera
+            order: -1,
+            target: RenderTarget::Image(image_handle.clone().into()),
+            clear_color: ClearColorConfig::Custom(Color::NONE),
+            // linear colors, the main camera does the tonemapping
+            hdr: true,
+            is_active: false,
+            ..default()
+        },
+        Tonemapping::None,
+        Msaa::Off,
+        // no DepthPrepass: the material is opaque in this mode, so the prepass would march
+        // every pixel a second time, just to find the depth of the cube itself
+        RenderLayers::layer(LOW_RES_LAYER),
+        LowResCamera,
+    ));
+
+    let upscale_material = upscale_materials.add(RaymarchUpscaleMaterial {
+        low_res_texture: image_handle.clone(),
+    });
+    for (cube, mesh) in cubes.iter() {
+        commands.entity(cube).with_child((
+            Mesh3d(mesh.0.clone()),
+            MeshMaterial3d(upscale_material.clone()),
+            Visibility::Hidden,
+            NotShadowCaster,
+            LowResComposite,
+        ));
+    }
+    commands.insert_resource(LowResTarget(image_handle));
+}
+
+// adaptive mode: nudge the resolution until the frame time is close to the target
+fn update_resolution_scale(
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    diagnostics: Res<DiagnosticsStore>,
+) {
+    let maybe_mat = rm_materials.get_mut(&rm_material_handle.0);
+    if let Some(mat) = maybe_mat {
+        let settings = &mut mat.extension.raymarch_global_settings;
+        // every change starts the accumulation over, it would never get anywhere
+        if settings.dynamic_resolution != 2 || is_accumulating(settings) {
+            return;
+        }
+        let Some(frame_time) = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+            .and_then(|frame_time| frame_time.smoothed())
+        else {
+            return;
+        };
+        let frame_time = frame_time as f32;
+        // leave some room around the target, otherwise it never settles
+        if frame_time > settings.target_frame_time * 1.05 {
+            settings.resolution_scale *= 0.98;
+        } else if frame_time < settings.target_frame_time * 0.9 {
+            settings.resolution_scale *= 1.01;
+        }
+        settings.resolution_scale = settings.resolution_scale.clamp(0.1, 1.0);
+    }
+}
+
+fn update_low_res_target(
+    mut commands: Commands,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    low_res_target: Res<LowResTarget>,
+    mut images: ResMut<Assets<Image>>,
+    main_cameras: Query<&Camera, (With<Camera3d>, Without<LowResCamera>)>,
+    mut low_res_cameras: Query<&mut Camera, With<LowResCamera>>,
+    cubes: Query<(Entity, Option<&RenderLayers>), With<RaymarchCube>>,
+    mut composites: Query<&mut Visibility, With<LowResComposite>>,
+) {
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let settings = &mat.extension.raymarch_global_settings;
+    let enabled = settings.dynamic_resolution != 0;
+
+    for mut camera in low_res_cameras.iter_mut() {
+        camera.is_active = enabled;
+    }
+    let cube_layers = if enabled {
+        RenderLayers::layer(LOW_RES_LAYER)
+    } else {
+        RenderLayers::default()
+    };
+    for (cube, layers) in cubes.iter() {
+        if layers != Some(&cube_layers) {
+            commands.entity(cube).insert(cube_layers.clone());
+        }
+    }
+    for mut visibility in composites.iter_mut() {
+        visibility.set_if_neq(if enabled {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+
+    if !enabled {
+        return;
+    }
+    let Some(viewport_size) = main_cameras
+        .iter()
+        .find_map(|camera| camera.physical_viewport_size())
+    else {
+        return;
+    };
+    let low_res_size = (viewport_size.as_vec2() * settings.resolution_scale)
+        .ceil()
+        .as_uvec2()
+        .max(UVec2::ONE);
+    let size = Extent3d {
+        width: low_res_size.x,
+        height: low_res_size.y,
+        depth_or_array_layers: 1,
+    };
+    // only touch the image if we have to, get_mut makes bevy upload it again
+    let needs_resize = images
+        .get(&low_res_target.0)
+        .is_some_and(|image| image.texture_descriptor.size != size);
+    if needs_resize {
+        if let Some(image) = images.get_mut(&low_res_target.0) {
+            image.resize(size);
+        }
+    }
+}
+
+fn follow_main_camera(
+    main_cameras: Query<(&Transform, &Projection), (With<Camera3d>, Without<LowResCamera>)>,
+    mut low_res_cameras: Query<(&mut Transform, &mut Projection), With<LowResCamera>>,
+) {
+    let Ok((main_transform, main_projection)) = main_cameras.single() else {
+        return;
+    };
+    for (mut transform, mut projection) in low_res_cameras.iter_mut() {
+        *transform = *main_transform;
+        // the aspect ratio gets fixed up by bevy afterwards
+        *projection = main_projection.clone();
+    }
+}
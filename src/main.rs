@@ -1,22 +1,96 @@
+mod accumulation;
+mod benchmark;
+mod camera_controller;
+mod camera_path;
+mod codegen;
+#[cfg(not(target_arch = "wasm32"))]
+mod cone_prepass;
+#[cfg(not(target_arch = "wasm32"))]
+mod cpu_pattern;
+#[cfg(not(target_arch = "wasm32"))]
+mod cpu_render;
+mod cpu_sdf;
+mod dynamic_resolution;
+#[cfg(not(target_arch = "wasm32"))]
+mod offline_render;
+mod picking;
+mod playback;
+#[cfg(not(target_arch = "wasm32"))]
+mod scene_file;
+#[cfg(not(target_arch = "wasm32"))]
+mod screenshot;
+mod shadertoy;
+mod timeline;
+mod transform_gizmo;
 mod ui;
+use accumulation::{Accumulation, AccumulationPlugin};
+use benchmark::MarcherBenchmarkPlugin;
+use camera_controller::{CameraController, CameraControllerPlugin, CameraMode};
+use camera_path::CameraPathPlugin;
+use codegen::SceneCodegenPlugin;
+#[cfg(not(target_arch = "wasm32"))]
+use cone_prepass::ConePrepassPlugin;
+use dynamic_resolution::{DynamicResolutionPlugin, LOW_RES_LAYER};
+#[cfg(not(target_arch = "wasm32"))]
+use offline_render::OfflineRenderPlugin;
+use picking::SdfPickingPlugin;
+use playback::{Playback, PlaybackPlugin};
+#[cfg(not(target_arch = "wasm32"))]
+use screenshot::ScreenshotPlugin;
+use timeline::TimelinePlugin;
+use transform_gizmo::TransformGizmoPlugin;
 use ui::MyRaymarchUi;
 
 use bevy::{
     core_pipeline::prepass::DepthPrepass,
-    pbr::{ExtendedMaterial, MaterialExtension},
+    diagnostic::FrameTimeDiagnosticsPlugin,
+    image::ImageLoaderSettings,
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline},
     prelude::*,
-    render::render_resource::{AsBindGroup, ShaderType},
+    render::{
+        mesh::MeshVertexBufferLayoutRef,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderDefVal, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+        view::RenderLayers,
+    },
 };
 
 fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins,
-            MyRaymarchUi,
-            MaterialPlugin::<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>::default(),
-        ))
-        .add_systems(Startup, setup)
-        .add_systems(Update, (spin_camera, update_raymarch_settings_time))
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins,
+        FrameTimeDiagnosticsPlugin::default(),
+        MyRaymarchUi,
+        MaterialPlugin::<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>::default(),
+        DynamicResolutionPlugin,
+        SceneCodegenPlugin,
+        TimelinePlugin,
+        PlaybackPlugin,
+        CameraControllerPlugin,
+        CameraPathPlugin,
+        AccumulationPlugin,
+        SdfPickingPlugin,
+        TransformGizmoPlugin,
+        MarcherBenchmarkPlugin,
+    ));
+    // no compute shaders on webgl2
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugins(ConePrepassPlugin);
+    // nowhere to write the frames to on web
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_plugins((OfflineRenderPlugin, ScreenshotPlugin));
+    app.add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                spin_camera,
+                update_raymarch_settings_time,
+                update_raymarch_texture_flags,
+                update_raymarch_pbr_features,
+            ),
+        )
         .run();
 }
 
@@ -29,14 +103,22 @@ struct SpinningCam {
     look_at: Vec3,
 }
 
-fn spin_camera(mut cams: Query<(&mut Transform, &SpinningCam)>, time: Res<Time>) {
+fn spin_camera(
+    mut cams: Query<(&mut Transform, &SpinningCam)>,
+    playback: Res<Playback>,
+    controller: Res<CameraController>,
+) {
+    // the orbit and fly cameras move it themselves
+    if controller.mode() != CameraMode::Spinning {
+        return;
+    }
     cams.iter_mut()
         .for_each(|(mut transform, spinning_cam_vars)| {
             let new_z =
-                (time.elapsed_secs() * spinning_cam_vars.speed).cos() * spinning_cam_vars.distance;
+                (playback.time * spinning_cam_vars.speed).cos() * spinning_cam_vars.distance;
             let new_x =
-                (time.elapsed_secs() * spinning_cam_vars.speed).sin() * spinning_cam_vars.distance;
-            let sway_y = (time.elapsed_secs() * spinning_cam_vars.speed / 0.35).sin()
+                (playback.time * spinning_cam_vars.speed).sin() * spinning_cam_vars.distance;
+            let sway_y = (playback.time * spinning_cam_vars.speed / 0.35).sin()
                 * spinning_cam_vars.sway_amount;
             let new_transform =
                 Transform::from_xyz(new_x, spinning_cam_vars.height + sway_y, new_z)
@@ -48,7 +130,7 @@ fn spin_camera(mut cams: Query<(&mut Transform, &SpinningCam)>, time: Res<Time>)
 // info to pass to the shader
 // VEEERY carefull with the order of these params
 // they HAVE to reflect the state of the same named struct in the shader
-#[derive(Debug, AsBindGroup, Clone, ShaderType)]
+#[derive(Debug, AsBindGroup, Clone, PartialEq, ShaderType)]
 #[repr(C)]
 struct RaymarchObjectDescriptor {
     // translation
@@ -90,7 +172,61 @@ struct RaymarchObjectDescriptor {
     clearcoat: f32,
     clearcoat_perceptual_roughness: f32,
     anisotropy_strength: f32,
+    /// (cos, sin) of the rotation angle, same as bevy does it
     anisotropy_rotation: Vec2,
+
+    // textures
+    /// how often the textures repeat per unit in object space
+    texture_scale: f32,
+    /// higher -> harder transition between the 3 projections
+    texture_blend_sharpness: f32,
+    /// which textures are set, updated by update_raymarch_texture_flags
+    /// bit 0 -> base color
+    /// bit 1 -> metallic roughness
+    /// bit 2 -> normal map
+    texture_flags: u32,
+
+    // procedural pattern
+    /// none = 0
+    /// checker = 1
+    /// stripes = 2
+    /// noise = 3
+    /// marble = 4
+    /// gradient by height = 5
+    /// gradient by curvature = 6
+    /// orbit trap = 7 (only does something on fractals)
+    pattern_type_id: u32,
+    /// the pattern goes from base_color to this
+    pattern_color: Vec4,
+    pattern_scale: f32,
+    /// what the orbit trap measures
+    /// distance to origin = 0
+    /// distance to the xyz planes = 1
+    /// iteration count = 2
+    orbit_trap_type_id: u32,
+    /// the orbit trap pattern goes through these colors (instead of base_color/pattern_color)
+    palette: [Vec4; 4],
+
+    // volume
+    /// solid surface = 0
+    /// constant density = 1
+    /// noise density = 2
+    /// density falling off towards the sdf surface = 3
+    /// volumes use base_color as the scattering color and emissive as emission
+    volume_type_id: u32,
+    volume_density: f32,
+    volume_absorption: f32,
+    volume_scattering: f32,
+    /// henyey-greenstein g, -1 -> scatters back, 0 -> everywhere, 1 -> scatters forward
+    volume_phase_g: f32,
+    volume_noise_scale: f32,
+    /// how deep inside the sdf the density reaches its maximum (type 3)
+    volume_falloff: f32,
+
+    // glow, on top of the global glow
+    /// alpha is used as the strength
+    glow_color: Vec4,
+    glow_intensity: f32,
 }
 
 impl Default for RaymarchObjectDescriptor {
@@ -117,12 +253,34 @@ impl Default for RaymarchObjectDescriptor {
             clearcoat: 0.0,
             clearcoat_perceptual_roughness: 0.0,
             anisotropy_strength: 0.0,
-            anisotropy_rotation: Vec2::ZERO,
+            anisotropy_rotation: Vec2::new(1.0, 0.0),
+            texture_scale: 1.0,
+            texture_blend_sharpness: 4.0,
+            texture_flags: 0,
+            pattern_type_id: 0,
+            pattern_color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            pattern_scale: 4.0,
+            orbit_trap_type_id: 0,
+            palette: [
+                Vec4::new(0.05, 0.02, 0.15, 1.0),
+                Vec4::new(0.6, 0.1, 0.4, 1.0),
+                Vec4::new(1.0, 0.55, 0.1, 1.0),
+                Vec4::new(1.0, 0.95, 0.7, 1.0),
+            ],
+            volume_type_id: 0,
+            volume_density: 5.0,
+            volume_absorption: 0.2,
+            volume_scattering: 1.0,
+            volume_phase_g: 0.3,
+            volume_noise_scale: 3.0,
+            volume_falloff: 0.2,
+            glow_color: Vec4::ZERO,
+            glow_intensity: 1.0,
         };
     }
 }
 
-#[derive(Debug, AsBindGroup, Clone, ShaderType)]
+#[derive(Debug, AsBindGroup, Clone, PartialEq, ShaderType)]
 #[repr(C)]
 struct RaymarchGlobalSettings {
     /// 0 -> a OR b intersection
@@ -130,10 +288,64 @@ struct RaymarchGlobalSettings {
     /// 2 -> a NOT b intersection
     intersection_method: u32,
     intersection_smooth_amount: f32,
+    /// 0 -> lerp by normalized distances
+    /// 1 -> nearest object
+    /// 2 -> weight of the smooth intersection
+    /// 3 -> like 2, but with material_blend_width instead of the smooth amount
+    material_blend_mode: u32,
+    material_blend_width: f32,
+    /// 0 -> closest approach, only for rays that miss
+    /// 1 -> accumulated along the whole ray, also in front of hits
+    /// the global glow_color always uses the closest approach
+    glow_mode: u32,
     glow_range: f32,
     glow_color: Vec4,
     far_clip: f32,
     termination_distance: f32,
+    /// rays that take more steps than this count as a miss
+    max_iterations: u32,
+    /// 1.0 -> plain sphere tracing, ~1.2-1.6 -> over-relaxed (falls back if it overshoots)
+    over_relaxation: f32,
+    /// 0.0 -> always stop at termination_distance
+    /// otherwise also stop when the sdf is smaller than this many pixels
+    termination_cone_scale: f32,
+    /// extra sphere tracing steps after a hit
+    hit_refinement_steps: u32,
+    /// 0 -> tetrahedron (4 samples)
+    /// 1 -> central differences (6 samples)
+    /// 2 -> forward differences (4 samples)
+    normal_method: u32,
+    normal_epsilon: f32,
+    /// the epsilon grows to this many pixels, if that is bigger than normal_epsilon
+    normal_epsilon_scale: f32,
+    /// how many reflections/refractions get marched through the sdf world
+    /// 0 -> off, only bevys pbr
+    bounce_limit: u32,
+    /// step length inside of volumes, smaller -> prettier but slower
+    volume_step_size: f32,
+    /// 0 -> every pixel marches from the camera
+    /// 1 -> start from the distance the low resolution cone prepass found (see cone_prepass.rs)
+    cone_prepass: u32,
+    /// 0 -> off, render at the window resolution
+    /// 1 -> fixed, render at resolution_scale
+    /// 2 -> adaptive, change resolution_scale to stay around target_frame_time
+    /// see dynamic_resolution.rs
+    dynamic_resolution: u32,
+    /// 0.0-1.0, fraction of the window resolution
+    resolution_scale: f32,
+    /// in milliseconds
+    target_frame_time: f32,
+    /// diameter of the lens, 0.0 -> pinhole camera, everything is sharp
+    /// otherwise every frame marches from a different point on the lens (see get_camera_ray)
+    aperture: f32,
+    /// distance from the camera to the plane that is in focus, along the view direction
+    focus_distance: f32,
+    /// 0 -> frames only get averaged for the lens
+    /// 1 -> progressive, also jitter the position in the pixel, the shadows and the volume steps
+    /// either way, it starts over on any change (see accumulation.rs)
+    accumulation: u32,
+    /// frames to average until the image is done
+    max_samples: u32,
     time: f32,
 }
 
@@ -142,29 +354,156 @@ impl Default for RaymarchGlobalSettings {
         return RaymarchGlobalSettings {
             intersection_method: 0,
             intersection_smooth_amount: 0.0,
+            material_blend_mode: 2,
+            material_blend_width: 0.2,
+            glow_mode: 0,
             glow_range: 0.0,
             glow_color: Vec4::ZERO,
             far_clip: 10.0,
             termination_distance: 0.001,
+            max_iterations: 256,
+            over_relaxation: 1.0,
+            termination_cone_scale: 0.0,
+            hit_refinement_steps: 0,
+            normal_method: 0,
+            normal_epsilon: 0.001,
+            normal_epsilon_scale: 1.0,
+            bounce_limit: 0,
+            volume_step_size: 0.02,
+            cone_prepass: 0,
+            dynamic_resolution: 0,
+            resolution_scale: 0.5,
+            target_frame_time: 16.6,
+            aperture: 0.0,
+            focus_distance: 4.0,
+            accumulation: 0,
+            max_samples: 256,
             time: 0.0,
         };
     }
 }
 
-// update the elapsed time that I pass to the shader
+// update the playback time that I pass to the shader
 fn update_raymarch_settings_time(
     mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
     rm_material_handle: Res<RaymarchMaterialHandle>,
-    time: Res<Time>,
+    playback: Res<Playback>,
 ) {
     let maybe_mat = rm_materials.get_mut(&rm_material_handle.0);
     if let Some(mat) = maybe_mat {
-        mat.extension.raymarch_global_settings.time = time.elapsed_secs();
+        mat.extension.raymarch_global_settings.time = playback.time;
+    }
+}
+
+// the shader can't check if a texture is set, so we tell it via the flags
+fn update_raymarch_texture_flags(
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let ext = &mat.extension;
+    let flags1 = get_texture_flags(
+        &ext.object1_base_color_texture,
+        &ext.object1_metallic_roughness_texture,
+        &ext.object1_normal_map_texture,
+    );
+    let flags2 = get_texture_flags(
+        &ext.object2_base_color_texture,
+        &ext.object2_metallic_roughness_texture,
+        &ext.object2_normal_map_texture,
+    );
+    if ext.material1.texture_flags == flags1 && ext.material2.texture_flags == flags2 {
+        return;
+    }
+    // get_mut marks the material as modified, which prepares the bind group again
+    let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+        return;
+    };
+    mat.extension.material1.texture_flags = flags1;
+    mat.extension.material2.texture_flags = flags2;
+}
+
+// bevy only compiles transmission, clearcoat and anisotropy into the shader if the base
+// StandardMaterial uses them. So we turn them on there if one of our objects needs them
+fn update_raymarch_pbr_features(
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let obj1 = &mat.extension.material1;
+    let obj2 = &mat.extension.material2;
+    let specular_transmission = obj1.specular_transmission.max(obj2.specular_transmission);
+    let diffuse_transmission = obj1.diffuse_transmission.max(obj2.diffuse_transmission);
+    let clearcoat = obj1.clearcoat.max(obj2.clearcoat);
+    let anisotropy_strength = obj1.anisotropy_strength.max(obj2.anisotropy_strength);
+
+    // the transmission texture is only available in the transmissive pass
+    // and blended materials never end up there
+    // the shader discards the missed rays itself in that case
+    // the low resolution target stores distances in the alpha channel, so no blending there
+    let alpha_mode = if mat.extension.raymarch_global_settings.dynamic_resolution != 0 {
+        AlphaMode::Opaque
+    } else if specular_transmission > 0.0 {
+        AlphaMode::Mask(0.5)
+    } else {
+        AlphaMode::Blend
+    };
+
+    let base = &mat.base;
+    if base.specular_transmission == specular_transmission
+        && base.diffuse_transmission == diffuse_transmission
+        && base.clearcoat == clearcoat
+        && base.anisotropy_strength == anisotropy_strength
+        && base.alpha_mode == alpha_mode
+    {
+        return;
+    }
+    // same as in update_raymarch_texture_flags, only touch the material if something changed
+    let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+        return;
+    };
+    mat.base.specular_transmission = specular_transmission;
+    mat.base.diffuse_transmission = diffuse_transmission;
+    mat.base.clearcoat = clearcoat;
+    mat.base.anisotropy_strength = anisotropy_strength;
+    mat.base.alpha_mode = alpha_mode;
+}
+
+/// only the base color is an actual color, the rest is data
+fn load_texture(asset_server: &AssetServer, path: String, is_color: bool) -> Handle<Image> {
+    if is_color {
+        return asset_server.load(path);
+    }
+    return asset_server.load_with_settings(path, |settings: &mut ImageLoaderSettings| {
+        settings.is_srgb = false
+    });
+}
+
+fn get_texture_flags(
+    base_color: &Option<Handle<Image>>,
+    metallic_roughness: &Option<Handle<Image>>,
+    normal_map: &Option<Handle<Image>>,
+) -> u32 {
+    let mut flags = 0;
+    if base_color.is_some() {
+        flags |= 1;
+    }
+    if metallic_roughness.is_some() {
+        flags |= 1 << 1;
     }
+    if normal_map.is_some() {
+        flags |= 1 << 2;
+    }
+    return flags;
 }
 
 // my RayMarch Material
-#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone, Default, PartialEq)]
+#[bind_group_data(RaymarchMaterialKey)]
 struct RaymarchMaterial {
     #[uniform(100)]
     material1: RaymarchObjectDescriptor,
@@ -172,6 +511,35 @@ struct RaymarchMaterial {
     material2: RaymarchObjectDescriptor,
     #[uniform(102)]
     raymarch_global_settings: RaymarchGlobalSettings,
+
+    // textures, projected triplanar in object space
+    // metallic_roughness: green -> roughness, blue -> metallic (same as the StandardMaterial)
+    // normal maps have to be loaded as linear (not srgb)!
+    #[texture(103)]
+    #[sampler(104)]
+    object1_base_color_texture: Option<Handle<Image>>,
+    #[texture(105)]
+    #[sampler(106)]
+    object1_metallic_roughness_texture: Option<Handle<Image>>,
+    #[texture(107)]
+    #[sampler(108)]
+    object1_normal_map_texture: Option<Handle<Image>>,
+    #[texture(109)]
+    #[sampler(110)]
+    object2_base_color_texture: Option<Handle<Image>>,
+    #[texture(111)]
+    #[sampler(112)]
+    object2_metallic_roughness_texture: Option<Handle<Image>>,
+    #[texture(113)]
+    #[sampler(114)]
+    object2_normal_map_texture: Option<Handle<Image>>,
+
+    // one safe starting distance per tile, written by the cone prepass
+    #[texture(115, sample_type = "float", filterable = false)]
+    cone_prepass_texture: Option<Handle<Image>>,
+
+    // not sent to the gpu, switches to the sdf from codegen.rs (see RaymarchMaterialKey)
+    use_generated_sdf: bool,
 }
 
 impl MaterialExtension for RaymarchMaterial {
@@ -181,15 +549,63 @@ impl MaterialExtension for RaymarchMaterial {
     fn prepass_fragment_shader() -> bevy::render::render_resource::ShaderRef {
         "shaders/basic_raymarch_prepass.wgsl".into()
     }
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader_defs.extend(key.bind_group_data.shader_defs());
+            // the generated sdf has its own copy of the shaders, see codegen.rs
+            if key.bind_group_data.generated_sdf {
+                fragment.shader = codegen::generated_fragment_shader(&fragment.shader_defs);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the parts of the scene that change which branches of the sdf are needed
+/// bevy caches one pipeline per key, so switching back and forth doesn't recompile
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RaymarchMaterialKey {
+    shape_type_ids: [u32; 2],
+    intersection_method: u32,
+    generated_sdf: bool,
+}
+
+impl From<&RaymarchMaterial> for RaymarchMaterialKey {
+    fn from(material: &RaymarchMaterial) -> Self {
+        return RaymarchMaterialKey {
+            shape_type_ids: [
+                material.material1.shape_type_id,
+                material.material2.shape_type_id,
+            ],
+            intersection_method: material.raymarch_global_settings.intersection_method,
+            generated_sdf: material.use_generated_sdf,
+        };
+    }
+}
+
+impl RaymarchMaterialKey {
+    /// see sdf_object1, sdf_object2 and my_min in sdf.wgsl
+    fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        let mut shader_defs = vec![
+            ShaderDefVal::UInt("OBJECT1_SHAPE".into(), self.shape_type_ids[0]),
+            ShaderDefVal::UInt("OBJECT2_SHAPE".into(), self.shape_type_ids[1]),
+            ShaderDefVal::UInt("INTERSECTION_METHOD".into(), self.intersection_method),
+        ];
+        if self.generated_sdf {
+            shader_defs.push("GENERATED_SDF".into());
+        }
+        return shader_defs;
+    }
 }
 
 impl RaymarchMaterial {
     fn get_basic_config() -> Self {
-        let mut out = RaymarchMaterial {
-            material1: RaymarchObjectDescriptor::default(),
-            material2: RaymarchObjectDescriptor::default(),
-            raymarch_global_settings: RaymarchGlobalSettings::default(),
-        };
+        let mut out = RaymarchMaterial::default();
         out.material2.world_position = Vec3::new(-0.5, 0.75, 0.4);
         out.material2.base_color = Vec4::new(0.0, 1.0, 0.0, 1.0);
         out.material2.rotation_amount = 0.5;
@@ -198,11 +614,7 @@ impl RaymarchMaterial {
     }
 
     fn get_smooth_config() -> Self {
-        let mut out = RaymarchMaterial {
-            material1: RaymarchObjectDescriptor::default(),
-            material2: RaymarchObjectDescriptor::default(),
-            raymarch_global_settings: RaymarchGlobalSettings::default(),
-        };
+        let mut out = RaymarchMaterial::default();
         out.material2.world_position = Vec3::new(-0.5, 0.75, 0.4);
         out.material2.base_color = Vec4::new(0.0, 1.0, 0.0, 1.0);
         out.material2.shape_type_id = 2;
@@ -213,11 +625,7 @@ impl RaymarchMaterial {
     }
 
     fn get_intersection_config() -> Self {
-        let mut out = RaymarchMaterial {
-            material1: RaymarchObjectDescriptor::default(),
-            material2: RaymarchObjectDescriptor::default(),
-            raymarch_global_settings: RaymarchGlobalSettings::default(),
-        };
+        let mut out = RaymarchMaterial::default();
         out.material2.world_position = Vec3::new(-0.5, 0.75, 0.4);
         out.material2.base_color = Vec4::new(0.0, 1.0, 0.0, 1.0);
         out.material2.shape_type_id = 2;
@@ -228,20 +636,36 @@ impl RaymarchMaterial {
     }
 
     fn get_mandelbulb_config() -> Self {
-        let mut out = RaymarchMaterial {
-            material1: RaymarchObjectDescriptor::default(),
-            material2: RaymarchObjectDescriptor::default(),
-            raymarch_global_settings: RaymarchGlobalSettings::default(),
-        };
+        let mut out = RaymarchMaterial::default();
         out.material1.world_position = Vec3::new(0.0, 0.8, 0.0);
         out.material1.rotation.x = 0.8;
         out.material1.shape_type_id = 4;
         out.material1.shape_var = 1.85;
+        out.material1.pattern_type_id = 7;
+        out.material1.pattern_scale = 1.0;
         out.material2.shape_var = 0.0;
         return out;
     }
+
+    fn get_cloud_config() -> Self {
+        let mut out = RaymarchMaterial::default();
+        out.material1.world_position = Vec3::new(0.0, 0.9, 0.0);
+        out.material1.shape_var = 0.7;
+        out.material1.base_color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        out.material1.volume_type_id = 2;
+        out.material2.world_position = Vec3::new(-0.5, 0.75, 0.4);
+        out.material2.base_color = Vec4::new(0.0, 1.0, 0.0, 1.0);
+        out.material2.shape_type_id = 2;
+        out.material2.shape_var = 0.2;
+        out.material2.rotation_amount = 0.5;
+        return out;
+    }
 }
 
+/// the mesh that the raymarch material is drawn on
+#[derive(Component)]
+struct RaymarchCube;
+
 /// this holds the current Material Handle (like a pointer) as a Resource
 #[derive(Resource)]
 struct RaymarchMaterialHandle(Handle<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>);
@@ -266,6 +690,7 @@ fn setup(
             look_at: Vec3::new(0.0, 0.5, 0.0),
         },
         DepthPrepass,
+        Accumulation::default(),
     ));
 
     // circular base
@@ -288,6 +713,7 @@ fn setup(
         Mesh3d(meshes.add(Cuboid::new(4.0, 4.0, 4.0))),
         MeshMaterial3d(rm_material_handle.clone()),
         Transform::from_xyz(0.0, 0.5, 0.0),
+        RaymarchCube,
     ));
 
     // // cylinder
@@ -308,5 +734,7 @@ fn setup(
             ..default()
         },
         Transform::from_xyz(4.0, 4.0, 4.0),
+        // the low resolution raymarch has to be lit too
+        RenderLayers::from_layers(&[0, LOW_RES_LAYER]),
     ));
 }
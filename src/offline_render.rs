@@ -0,0 +1,436 @@
+// offline rendering of animations into a png sequence (and optionally a gif)
+// the main camera renders into a big offscreen image for the duration, one frame of fixed
+// simulated time after the other, and only moves on once the frame has been read back
+// everything that follows the main camera (dynamic resolution, cone prepass) just sees a bigger
+// target. supersampling renders at a multiple of the resolution and box filters it down
+// with accumulation on, every frame waits until its image is done (see accumulation.rs)
+// with the cpu renderer (cpu_render.rs) the camera keeps drawing to the window and every frame
+// is marched on the cpu from the camera's view instead, slower and with much simpler shading
+
+use std::{fs::File, path::PathBuf};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        renderer::RenderDevice,
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+    },
+};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::{
+    accumulation::frames_to_converge,
+    camera_path::follow_camera_path,
+    cpu_render::{render_frame, CpuView},
+    playback::{advance_playback, Playback},
+    screenshot::ScreenshotSettings,
+    spin_camera, update_raymarch_settings_time, RaymarchMaterial, RaymarchMaterialHandle,
+};
+
+/// frames to wait after switching the target, the pipelines for it compile in the background
+pub const WARMUP_FRAMES: u32 = 10;
+
+pub struct OfflineRenderPlugin;
+impl Plugin for OfflineRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OfflineRender>();
+        app.add_systems(
+            Update,
+            // the cameras (and the camera controller after spin_camera) have to see the
+            // time of the frame we are rendering
+            run_offline_render
+                .after(advance_playback)
+                .before(update_raymarch_settings_time)
+                .before(spin_camera)
+                .before(follow_camera_path),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct OfflineRender {
+    pub width: u32,
+    pub height: u32,
+    /// renders at this multiple of the resolution and averages it down
+    pub supersample: u32,
+    pub fps: u32,
+    /// simulated time of the first frame, in seconds
+    pub start_time: f32,
+    pub frame_count: u32,
+    pub output_dir: String,
+    /// also write all frames into output_dir/animation.gif
+    pub gif: bool,
+    /// render with cpu_render.rs instead of the gpu
+    pub cpu: bool,
+    /// set to start a render, the render system picks it up
+    pub requested: bool,
+    job: Option<RenderJob>,
+}
+
+impl Default for OfflineRender {
+    fn default() -> Self {
+        return OfflineRender {
+            width: 640,
+            height: 360,
+            supersample: 2,
+            fps: 30,
+            start_time: 0.0,
+            frame_count: 90,
+            output_dir: "render".to_string(),
+            gif: false,
+            cpu: false,
+            requested: false,
+            job: None,
+        };
+    }
+}
+
+impl OfflineRender {
+    /// the frame that is being rendered right now, if there is a render
+    pub fn progress(&self) -> Option<u32> {
+        return self.job.as_ref().map(|job| job.frame);
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(job) = self.job.as_mut() {
+            job.cancelled = true;
+        }
+    }
+}
+
+struct RenderJob {
+    /// None with the cpu renderer, the camera keeps drawing to the window then
+    gpu: Option<GpuTarget>,
+    /// can be less than OfflineRender::supersample if the gpu can't make a texture that big
+    supersample: u32,
+    frame: u32,
+    warmup: u32,
+    /// frames the current one has been accumulating for
+    settle: u32,
+    /// a screenshot is on the way
+    waiting: bool,
+    cancelled: bool,
+    gif_frames: Vec<Frame>,
+}
+
+struct GpuTarget {
+    camera: Entity,
+    /// what the camera rendered to before, set back when we are done
+    previous_target: RenderTarget,
+    target: Handle<Image>,
+}
+
+fn run_offline_render(
+    mut commands: Commands,
+    mut offline_render: ResMut<OfflineRender>,
+    mut playback: ResMut<Playback>,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<(Entity, &mut Camera), With<Camera3d>>,
+    views: Query<(&GlobalTransform, &Projection), With<Camera3d>>,
+    lights: Query<&GlobalTransform, With<PointLight>>,
+    clear_color: Res<ClearColor>,
+    render_device: Res<RenderDevice>,
+    keys: Res<ButtonInput<KeyCode>>,
+    screenshot: Res<ScreenshotSettings>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    let offline_render = &mut *offline_render;
+    // a screenshot has the camera right now
+    if offline_render.requested && offline_render.job.is_none() && !screenshot.is_capturing() {
+        offline_render.requested = false;
+        offline_render.job = start_job(offline_render, &mut images, &mut cameras, &render_device);
+        if offline_render.job.is_some() {
+            info!("rendering {} frames", offline_render.frame_count);
+        }
+    }
+    let Some(job) = offline_render.job.as_mut() else {
+        return;
+    };
+    // the window doesn't show anything while we render, so the ui might not be there either
+    if keys.just_pressed(KeyCode::Escape) {
+        job.cancelled = true;
+    }
+    if job.waiting {
+        return;
+    }
+    if job.cancelled || job.frame >= offline_render.frame_count {
+        if let Some(job) = offline_render.job.take() {
+            finish_job(job, offline_render, &mut cameras);
+        }
+        return;
+    }
+
+    playback.playing = false;
+    playback.time = offline_render.start_time + job.frame as f32 / offline_render.fps as f32;
+    if job.warmup > 0 {
+        job.warmup -= 1;
+        return;
+    }
+    let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let Some(gpu) = &job.gpu else {
+        // we run before the material and the camera see the new time, so wait for one frame
+        if job.settle < 1 {
+            job.settle += 1;
+            return;
+        }
+        job.settle = 0;
+        let size = UVec2::new(offline_render.width, offline_render.height).max(UVec2::ONE)
+            * job.supersample;
+        let Some(view) = cpu_view(&cameras, &views, &lights, &clear_color) else {
+            error!("the cpu renderer needs a perspective camera that draws to the window");
+            job.cancelled = true;
+            return;
+        };
+        let rgba = render_frame(&mat.extension, &view, size);
+        store_frame(offline_render, &rgba);
+        return;
+    };
+    // the new time starts the accumulation over
+    let accumulation_frames = frames_to_converge(&mat.extension.raymarch_global_settings);
+    if job.settle < accumulation_frames {
+        job.settle += 1;
+        return;
+    }
+    job.settle = 0;
+    commands
+        .spawn(Screenshot::image(gpu.target.clone()))
+        .observe(save_offline_frame);
+    job.waiting = true;
+}
+
+/// where the camera that draws to the window looks from, for the cpu renderer
+fn cpu_view(
+    cameras: &Query<(Entity, &mut Camera), With<Camera3d>>,
+    views: &Query<(&GlobalTransform, &Projection), With<Camera3d>>,
+    lights: &Query<&GlobalTransform, With<PointLight>>,
+    clear_color: &ClearColor,
+) -> Option<CpuView> {
+    let (camera, _) = cameras
+        .iter()
+        .find(|(_, camera)| matches!(camera.target, RenderTarget::Window(_)))?;
+    let Ok((transform, Projection::Perspective(perspective))) = views.get(camera) else {
+        return None;
+    };
+    return Some(CpuView {
+        camera: *transform,
+        fov: perspective.fov,
+        // same as the light in setup
+        light_position: lights
+            .iter()
+            .next()
+            .map_or(Vec3::splat(4.0), |light| light.translation()),
+        background: clear_color.0.to_linear(),
+    });
+}
+
+fn start_job(
+    offline_render: &OfflineRender,
+    images: &mut Assets<Image>,
+    cameras: &mut Query<(Entity, &mut Camera), With<Camera3d>>,
+    render_device: &RenderDevice,
+) -> Option<RenderJob> {
+    if let Err(err) = std::fs::create_dir_all(&offline_render.output_dir) {
+        error!("could not create {}: {err}", offline_render.output_dir);
+        return None;
+    }
+    if offline_render.cpu {
+        return Some(RenderJob {
+            gpu: None,
+            supersample: offline_render.supersample.max(1),
+            frame: 0,
+            warmup: 0,
+            settle: 0,
+            waiting: false,
+            cancelled: false,
+            gif_frames: Vec::new(),
+        });
+    }
+    let resolution = UVec2::new(offline_render.width, offline_render.height).max(UVec2::ONE);
+    // less supersampling instead of a texture the gpu can't make
+    let max_size = render_device.limits().max_texture_dimension_2d;
+    let supersample = offline_render
+        .supersample
+        .min(max_size / resolution.max_element())
+        .max(1);
+    if supersample < offline_render.supersample {
+        warn!(
+            "{}x supersampling of {}x{} is too big for the gpu (at most {max_size} pixels), \
+             using {supersample}x",
+            offline_render.supersample, resolution.x, resolution.y
+        );
+    }
+    let size = resolution * supersample;
+    let (camera, previous_target, target) = retarget_window_camera(
+        images,
+        cameras,
+        render_device,
+        size,
+        TextureFormat::Rgba8UnormSrgb,
+    )?;
+    return Some(RenderJob {
+        gpu: Some(GpuTarget {
+            camera,
+            previous_target,
+            target,
+        }),
+        supersample,
+        frame: 0,
+        warmup: WARMUP_FRAMES,
+        settle: 0,
+        waiting: false,
+        cancelled: false,
+        gif_frames: Vec::new(),
+    });
+}
+
+/// points the camera that draws to the window at a new offscreen image of this size and format
+/// returns the camera, what it rendered to before and the image
+/// None if there is no such camera or the gpu can't make an image that big
+pub fn retarget_window_camera(
+    images: &mut Assets<Image>,
+    cameras: &mut Query<(Entity, &mut Camera), With<Camera3d>>,
+    render_device: &RenderDevice,
+    size: UVec2,
+    format: TextureFormat,
+) -> Option<(Entity, RenderTarget, Handle<Image>)> {
+    let max_size = render_device.limits().max_texture_dimension_2d;
+    if size.max_element() > max_size {
+        error!(
+            "can't render at {}x{}, the gpu allows at most {max_size} pixels per side",
+            size.x, size.y
+        );
+        return None;
+    }
+    let Some((camera_entity, mut camera)) = cameras
+        .iter_mut()
+        .find(|(_, camera)| matches!(camera.target, RenderTarget::Window(_)))
+    else {
+        error!("no camera to render with");
+        return None;
+    };
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x.max(1),
+            height: size.y.max(1),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &vec![0; format.block_copy_size(None).unwrap_or(4) as usize],
+        format,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_SRC
+        | TextureUsages::COPY_DST
+        | TextureUsages::RENDER_ATTACHMENT;
+    let target = images.add(image);
+    let previous_target = std::mem::replace(
+        &mut camera.target,
+        RenderTarget::Image(target.clone().into()),
+    );
+    return Some((camera_entity, previous_target, target));
+}
+
+fn finish_job(
+    job: RenderJob,
+    offline_render: &OfflineRender,
+    cameras: &mut Query<(Entity, &mut Camera), With<Camera3d>>,
+) {
+    if let Some(gpu) = job.gpu {
+        if let Ok((_, mut camera)) = cameras.get_mut(gpu.camera) {
+            camera.target = gpu.previous_target;
+        }
+    }
+    if job.cancelled {
+        info!("render cancelled after {} frames", job.frame);
+        return;
+    }
+    info!(
+        "rendered {} frames to {}",
+        job.frame, offline_render.output_dir
+    );
+    if !offline_render.gif || job.gif_frames.is_empty() {
+        return;
+    }
+    let path = PathBuf::from(&offline_render.output_dir).join("animation.gif");
+    let result = File::create(&path)
+        .map_err(image::ImageError::IoError)
+        .and_then(|file| {
+            let mut encoder = GifEncoder::new_with_speed(file, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            return encoder.encode_frames(job.gif_frames);
+        });
+    match result {
+        Ok(()) => info!("wrote {}", path.display()),
+        Err(err) => error!("could not write {}: {err}", path.display()),
+    }
+}
+
+fn save_offline_frame(
+    trigger: Trigger<ScreenshotCaptured>,
+    mut offline_render: ResMut<OfflineRender>,
+) {
+    let Some(job) = offline_render.job.as_mut() else {
+        return;
+    };
+    job.waiting = false;
+    match trigger.event().0.clone().try_into_dynamic() {
+        Ok(dynamic) => store_frame(&mut offline_render, &dynamic.to_rgba8()),
+        Err(err) => {
+            error!("could not read back frame {}: {err}", job.frame);
+            job.cancelled = true;
+        }
+    }
+}
+
+// downsamples a finished frame, writes its png and keeps it for the gif
+fn store_frame(offline_render: &mut OfflineRender, rgba: &RgbaImage) {
+    let Some(job) = offline_render.job.as_mut() else {
+        return;
+    };
+    let rgba = downsample(rgba, job.supersample);
+    let path =
+        PathBuf::from(&offline_render.output_dir).join(format!("frame_{:05}.png", job.frame));
+    if let Err(err) = rgba.save(&path) {
+        error!("could not write {}: {err}", path.display());
+        job.cancelled = true;
+        return;
+    }
+    if offline_render.gif {
+        let delay = Delay::from_numer_denom_ms(1000, offline_render.fps.max(1));
+        job.gif_frames.push(Frame::from_parts(rgba, 0, 0, delay));
+    }
+    job.frame += 1;
+}
+
+// box filter, every output pixel is the average of factor x factor input pixels
+pub fn downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor <= 1 {
+        return image.clone();
+    }
+    let width = image.width() / factor;
+    let height = image.height() / factor;
+    return RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u32; 4];
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let pixel = image.get_pixel(x * factor + dx, y * factor + dy);
+                for (sum, value) in sum.iter_mut().zip(pixel.0) {
+                    *sum += value as u32;
+                }
+            }
+        }
+        let count = factor * factor;
+        return image::Rgba(sum.map(|channel| ((channel + count / 2) / count) as u8));
+    });
+}
@@ -0,0 +1,176 @@
+// clicking on the raymarched objects
+// the cursor ray is marched against the cpu version of the sdf (cpu_sdf.rs), so no gpu readback
+// only the sdf objects can be picked, the floor doesn't block the ray
+// a click is press and release close together, so dragging the orbit camera doesn't pick
+// neither does grabbing a handle of the transform gizmo
+// the points of a shown camera path are picked first, they are drawn on top of everything
+// the hits also go to bevy_picking as hits on the RaymarchCube, so Pointer<Click> & co work there
+
+use bevy::{
+    pbr::ExtendedMaterial,
+    picking::{
+        backend::{ray::RayMap, HitData, PointerHits},
+        PickSet,
+    },
+    prelude::*,
+    render::camera::RenderTarget,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+use crate::{
+    camera_controller::object_radius,
+    camera_path::CameraPath,
+    cpu_sdf::{self, SdfHit, SdfObject},
+    transform_gizmo::TransformGizmo,
+    RaymarchCube, RaymarchMaterial, RaymarchMaterialHandle, SpinningCam,
+};
+
+/// how far the cursor may move between press and release, in pixels
+const CLICK_DISTANCE: f32 = 4.0;
+
+pub struct SdfPickingPlugin;
+impl Plugin for SdfPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SdfPicked>();
+        app.init_resource::<Selection>();
+        app.add_systems(PreUpdate, sdf_picking_backend.in_set(PickSet::Backend));
+        app.add_systems(
+            Update,
+            ((pick_on_click, select_picked).chain(), draw_selection),
+        );
+    }
+}
+
+/// sent for every click into the scene, hit is None when nothing was under the cursor
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SdfPicked {
+    pub hit: Option<SdfHit>,
+}
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub object: Option<SdfObject>,
+}
+
+fn pick_on_click(
+    mut contexts: EguiContexts,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    gizmo: Res<TransformGizmo>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<SpinningCam>>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut camera_path: ResMut<CameraPath>,
+    mut selection: ResMut<Selection>,
+    mut pressed_at: Local<Option<Vec2>>,
+    mut picked: EventWriter<SdfPicked>,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let cursor = window.cursor_position();
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        let ctx = contexts.ctx_mut();
+        let use_mouse =
+            !ctx.wants_pointer_input() && !ctx.is_pointer_over_area() && !gizmo.is_dragging();
+        *pressed_at = cursor.filter(|_| use_mouse);
+    }
+    if !mouse_buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let (Some(pressed), Some(cursor)) = (pressed_at.take(), cursor) else {
+        return;
+    };
+    if pressed.distance(cursor) > CLICK_DISTANCE {
+        return;
+    }
+    let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    for (camera, camera_transform) in cameras.iter() {
+        if let Some(point) = camera_path.point_at(camera, camera_transform, cursor) {
+            camera_path.selected = Some(point);
+            selection.object = None;
+            continue;
+        }
+        let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+            continue;
+        };
+        picked.write(SdfPicked {
+            hit: cpu_sdf::raycast(&rm_material.extension, ray),
+        });
+    }
+}
+
+// clicking into the void deselects, the camera path too
+fn select_picked(
+    mut picked: EventReader<SdfPicked>,
+    mut selection: ResMut<Selection>,
+    mut camera_path: ResMut<CameraPath>,
+) {
+    for event in picked.read() {
+        selection.object = event.hit.map(|hit| hit.object);
+        camera_path.selected = None;
+    }
+}
+
+fn sdf_picking_backend(
+    ray_map: Res<RayMap>,
+    cameras: Query<&Camera, With<SpinningCam>>,
+    cubes: Query<Entity, With<RaymarchCube>>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut output: EventWriter<PointerHits>,
+) {
+    let Ok(cube) = cubes.single() else {
+        return;
+    };
+    let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    for (&ray_id, &ray) in ray_map.iter() {
+        let Ok(camera) = cameras.get(ray_id.camera) else {
+            continue;
+        };
+        let Some(hit) = cpu_sdf::raycast(&rm_material.extension, ray) else {
+            continue;
+        };
+        let hit_data = HitData::new(ray_id.camera, hit.distance, Some(hit.position), None);
+        output.write(PointerHits::new(
+            ray_id.pointer,
+            vec![(cube, hit_data)],
+            camera.order as f32,
+        ));
+    }
+}
+
+fn draw_selection(
+    selection: Res<Selection>,
+    cameras: Query<&Camera, With<SpinningCam>>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut gizmos: Gizmos,
+) {
+    let Some(object) = selection.object else {
+        return;
+    };
+    // not in screenshots and offline renders
+    let to_window = cameras
+        .iter()
+        .all(|camera| matches!(camera.target, RenderTarget::Window(_)));
+    if !to_window {
+        return;
+    }
+    let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let material = &rm_material.extension;
+    let desc = object.descriptor(material);
+    let center = cpu_sdf::object_center(desc, material.raymarch_global_settings.time);
+    gizmos.sphere(
+        Isometry3d::from_translation(center),
+        object_radius(desc),
+        Color::srgb(1.0, 0.8, 0.0),
+    );
+}
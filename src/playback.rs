@@ -0,0 +1,107 @@
+// playback of the animation time
+// the shader time, the timeline and the spinning camera all run on Playback::time
+// instead of the real elapsed time, so they can be paused, slowed down and scrubbed together
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::{spin_camera, update_raymarch_settings_time};
+
+/// one frame at 60fps, for stepping with the arrow keys
+const FRAME_STEP: f32 = 1.0 / 60.0;
+
+pub struct PlaybackPlugin;
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playback>();
+        app.add_systems(
+            Update,
+            (playback_shortcuts, advance_playback)
+                .chain()
+                .before(update_raymarch_settings_time)
+                .before(spin_camera),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct Playback {
+    pub playing: bool,
+    /// 1.0 -> real time, negative plays backwards
+    pub speed: f32,
+    /// in seconds
+    pub time: f32,
+    /// jump back to loop_start when loop_end is reached
+    pub looping: bool,
+    pub loop_start: f32,
+    pub loop_end: f32,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        return Playback {
+            playing: true,
+            speed: 1.0,
+            time: 0.0,
+            looping: false,
+            loop_start: 0.0,
+            loop_end: 10.0,
+        };
+    }
+}
+
+impl Playback {
+    /// sets the time and stops there, for scrubbing
+    pub fn scrub_to(&mut self, time: f32) {
+        self.playing = false;
+        self.time = time.max(0.0);
+    }
+
+    fn wrap_loop(&mut self) {
+        let length = self.loop_end - self.loop_start;
+        if !self.looping || length <= 0.0 {
+            return;
+        }
+        if self.time < self.loop_start || self.time >= self.loop_end {
+            self.time = self.loop_start + (self.time - self.loop_start).rem_euclid(length);
+        }
+    }
+}
+
+pub fn advance_playback(mut playback: ResMut<Playback>, time: Res<Time>) {
+    if playback.playing {
+        playback.time = (playback.time + time.delta_secs() * playback.speed).max(0.0);
+    }
+    playback.wrap_loop();
+}
+
+// space -> play/pause, left/right -> one frame back/forward, home -> back to the start
+fn playback_shortcuts(
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<Playback>,
+) {
+    // typing into a text field shouldn't pause the animation
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        playback.playing = !playback.playing;
+    }
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        let time = playback.time - FRAME_STEP;
+        playback.scrub_to(time);
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        let time = playback.time + FRAME_STEP;
+        playback.scrub_to(time);
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        let time = if playback.looping {
+            playback.loop_start
+        } else {
+            0.0
+        };
+        playback.time = time;
+    }
+}
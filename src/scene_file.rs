@@ -0,0 +1,259 @@
+// the scene on disk: both objects, the global settings, the textures, the timeline and the
+// camera path, as plain text
+// the object and global values are the fields the timeline can animate (see timeline.rs), by
+// name, so old files still load after fields were added or moved around
+// textures are saved as their asset path, one that wasn't loaded from a file can't be saved
+
+use std::io::{Error, ErrorKind};
+
+use bevy::prelude::*;
+
+use crate::{
+    camera_path::CameraPath,
+    load_texture,
+    timeline::{Easing, Keyframe, Timeline, Track, TrackTarget},
+    RaymarchMaterial,
+};
+
+pub const SCENE_FILE: &str = "scene.txt";
+
+const TEXTURES_SECTION: &str = "Textures";
+const TIMELINE_SECTION: &str = "Timeline";
+/// followed by the track name, one section per track
+const TRACK_SECTION: &str = "Track ";
+const CAMERA_PATH_SECTION: &str = "Camera Path";
+
+fn invalid_data(message: String) -> Error {
+    return Error::new(ErrorKind::InvalidData, message);
+}
+
+/// the texture fields with the names they have in the file
+fn textures(material: &mut RaymarchMaterial) -> [(&'static str, &mut Option<Handle<Image>>); 6] {
+    return [
+        (
+            "object 1 base color",
+            &mut material.object1_base_color_texture,
+        ),
+        (
+            "object 1 metallic roughness",
+            &mut material.object1_metallic_roughness_texture,
+        ),
+        (
+            "object 1 normal map",
+            &mut material.object1_normal_map_texture,
+        ),
+        (
+            "object 2 base color",
+            &mut material.object2_base_color_texture,
+        ),
+        (
+            "object 2 metallic roughness",
+            &mut material.object2_metallic_roughness_texture,
+        ),
+        (
+            "object 2 normal map",
+            &mut material.object2_normal_map_texture,
+        ),
+    ];
+}
+
+pub fn save_scene(
+    material: &RaymarchMaterial,
+    timeline: &Timeline,
+    camera_path: &CameraPath,
+) -> std::io::Result<()> {
+    // the accessors write too, so they want a mutable material
+    let mut material = material.clone();
+    let mut out = String::new();
+    for target in TrackTarget::ALL {
+        out.push_str(&format!("[{}]\n", target.name()));
+        for (param, name) in target.param_names().iter().enumerate() {
+            let value = target.access(param, &mut material, None);
+            out.push_str(&format!("{name} = {value}\n"));
+        }
+        out.push('\n');
+    }
+
+    out.push_str(&format!("[{TEXTURES_SECTION}]\n"));
+    for (name, texture) in textures(&mut material) {
+        let Some(texture) = texture else {
+            continue;
+        };
+        let Some(path) = texture.path() else {
+            return Err(invalid_data(format!(
+                "the {name} texture wasn't loaded from a file"
+            )));
+        };
+        out.push_str(&format!("{name} = {path}\n"));
+    }
+    out.push('\n');
+
+    out.push_str(&format!("[{TIMELINE_SECTION}]\n"));
+    out.push_str(&format!("length = {}\n\n", timeline.length));
+    for track in timeline.tracks.iter() {
+        out.push_str(&format!("[{TRACK_SECTION}{}]\n", track.name()));
+        // time value easing
+        for key in track.keys.iter() {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                key.time,
+                key.value,
+                key.easing.name()
+            ));
+        }
+        out.push('\n');
+    }
+
+    out.push_str(&format!("[{CAMERA_PATH_SECTION}]\n"));
+    camera_path.write_keys(&mut out);
+    return std::fs::write(SCENE_FILE, out);
+}
+
+// nothing changes if the file can't be read
+pub fn load_scene(
+    material: &mut RaymarchMaterial,
+    timeline: &mut Timeline,
+    camera_path: &mut CameraPath,
+    asset_server: &AssetServer,
+) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(SCENE_FILE)?;
+    let mut loaded = material.clone();
+    // a file without textures has none
+    for (_, texture) in textures(&mut loaded) {
+        *texture = None;
+    }
+    let mut length = timeline.length;
+    let mut tracks: Vec<Track> = Vec::new();
+    let mut keys = Vec::new();
+    let mut section = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = Some(name);
+            if let Some(track) = name.strip_prefix(TRACK_SECTION) {
+                tracks.push(parse_track(track)?);
+            }
+            continue;
+        }
+        if section == Some(CAMERA_PATH_SECTION) {
+            keys.push(CameraPath::parse_key(line)?);
+            continue;
+        }
+        if section.is_some_and(|section| section.starts_with(TRACK_SECTION)) {
+            let track = tracks.last_mut().expect("pushed with the section");
+            track.keys.push(parse_keyframe(line)?);
+            continue;
+        }
+
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(invalid_data(format!("expected name = value, got {line:?}")));
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if section == Some(TEXTURES_SECTION) {
+            let Some((_, texture)) = textures(&mut loaded)
+                .into_iter()
+                .find(|(texture, _)| *texture == name)
+            else {
+                return Err(invalid_data(format!("there is no {name:?} texture")));
+            };
+            let is_color = name.ends_with("base color");
+            *texture = Some(load_texture(asset_server, value.to_string(), is_color));
+            continue;
+        }
+        let value = value
+            .parse::<f32>()
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        if section == Some(TIMELINE_SECTION) {
+            if name != "length" {
+                return Err(invalid_data(format!("the timeline has no {name:?}")));
+            }
+            length = value;
+            continue;
+        }
+        let Some(target) = TrackTarget::ALL
+            .into_iter()
+            .find(|target| Some(target.name()) == section)
+        else {
+            return Err(invalid_data(format!("{line:?} is not in a known section")));
+        };
+        // probably from a newer version, the rest of the scene is still worth having
+        let Some(param) = target.param_names().iter().position(|param| *param == name) else {
+            warn!("{SCENE_FILE}: {} has no {name:?}, skipped", target.name());
+            continue;
+        };
+        target.access(param, &mut loaded, Some(value));
+    }
+    *material = loaded;
+    for track in tracks.iter_mut() {
+        track.sort_keys();
+    }
+    timeline.tracks = tracks;
+    timeline.length = length;
+    camera_path.keys = keys;
+    camera_path.selected = None;
+    camera_path.sort_keys();
+    return Ok(());
+}
+
+// "Object 1: position x", like Track::name
+fn parse_track(name: &str) -> std::io::Result<Track> {
+    let Some((target, param)) = name.split_once(": ") else {
+        return Err(invalid_data(format!("{name:?} is not a track name")));
+    };
+    let Some(target) = TrackTarget::ALL
+        .into_iter()
+        .find(|known| known.name() == target)
+    else {
+        return Err(invalid_data(format!(
+            "there is nothing called {target:?} to animate"
+        )));
+    };
+    // unlike a single value, a track for something that doesn't exist can't be skipped quietly
+    let Some(param) = target
+        .param_names()
+        .iter()
+        .position(|known| *known == param)
+    else {
+        return Err(invalid_data(format!(
+            "{} has no {param:?} to animate",
+            target.name()
+        )));
+    };
+    return Ok(Track {
+        target,
+        param,
+        keys: Vec::new(),
+    });
+}
+
+// "time value easing", the easing name can have spaces
+fn parse_keyframe(line: &str) -> std::io::Result<Keyframe> {
+    let mut parts = line.splitn(3, ' ');
+    let mut number = || -> std::io::Result<f32> {
+        return parts
+            .next()
+            .unwrap_or_default()
+            .parse::<f32>()
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err));
+    };
+    let time = number()?;
+    let value = number()?;
+    let easing_name = parts.next().unwrap_or_default();
+    let Some(easing) = Easing::ALL
+        .into_iter()
+        .find(|easing| easing.name() == easing_name)
+    else {
+        return Err(invalid_data(format!("there is no {easing_name:?} easing")));
+    };
+    return Ok(Keyframe {
+        time,
+        value,
+        easing,
+    });
+}
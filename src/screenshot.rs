@@ -0,0 +1,338 @@
+// screenshots of the current view at a multiple of the window resolution
+// works like the offline render (see offline_render.rs), just for one frame at the current time
+// with a transparent background, rays that miss keep the alpha of the glow
+// exr renders in hdr without tonemapping for the capture, so it keeps the values above 1
+// with accumulation on, it waits until the image is done (see accumulation.rs)
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::{
+    core_pipeline::tonemapping::Tonemapping,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::TextureFormat,
+        renderer::RenderDevice,
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+    },
+};
+use bevy_egui::EguiContexts;
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::{
+    accumulation::frames_to_converge,
+    offline_render::{retarget_window_camera, OfflineRender, WARMUP_FRAMES},
+    RaymarchMaterial, RaymarchMaterialHandle,
+};
+
+pub struct ScreenshotPlugin;
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenshotSettings>();
+        app.add_systems(Update, (screenshot_shortcut, run_screenshot).chain());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScreenshotFormat {
+    Png,
+    /// linear colors straight from the hdr render, without tonemapping
+    Exr,
+}
+
+#[derive(Resource)]
+pub struct ScreenshotSettings {
+    /// multiple of the window resolution, doesn't have to be a whole number
+    pub scale: f32,
+    pub transparent: bool,
+    pub format: ScreenshotFormat,
+    /// set to take a screenshot, the screenshot system picks it up
+    pub requested: bool,
+    capture: Option<Capture>,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        return ScreenshotSettings {
+            scale: 2.0,
+            transparent: false,
+            format: ScreenshotFormat::Png,
+            requested: false,
+            capture: None,
+        };
+    }
+}
+
+impl ScreenshotSettings {
+    pub fn is_capturing(&self) -> bool {
+        return self.capture.is_some();
+    }
+}
+
+struct Capture {
+    camera: Entity,
+    previous_target: RenderTarget,
+    previous_clear_color: ClearColorConfig,
+    previous_hdr: bool,
+    previous_tonemapping: Tonemapping,
+    format: ScreenshotFormat,
+    target: Handle<Image>,
+    warmup: u32,
+    /// the screenshot is on the way
+    waiting: bool,
+    done: bool,
+}
+
+// F12 -> screenshot
+fn screenshot_shortcut(
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<ScreenshotSettings>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::F12) {
+        settings.requested = true;
+    }
+}
+
+fn run_screenshot(
+    mut commands: Commands,
+    mut settings: ResMut<ScreenshotSettings>,
+    offline_render: Res<OfflineRender>,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<(Entity, &mut Camera), With<Camera3d>>,
+    mut tonemappings: Query<&mut Tonemapping, With<Camera3d>>,
+    render_device: Res<RenderDevice>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    let settings = &mut *settings;
+    // the offline render already has the camera
+    if settings.requested && settings.capture.is_none() && offline_render.progress().is_none() {
+        settings.requested = false;
+        // the new target size starts the accumulation over
+        let accumulation_frames = rm_materials.get(&rm_material_handle.0).map_or(0, |mat| {
+            frames_to_converge(&mat.extension.raymarch_global_settings)
+        });
+        settings.capture = start_capture(
+            settings,
+            &mut images,
+            &mut cameras,
+            &mut tonemappings,
+            &render_device,
+            accumulation_frames,
+        );
+    }
+    let Some(capture) = settings.capture.as_mut() else {
+        return;
+    };
+    if capture.done {
+        if let Some(capture) = settings.capture.take() {
+            if let Ok((_, mut camera)) = cameras.get_mut(capture.camera) {
+                camera.target = capture.previous_target;
+                camera.clear_color = capture.previous_clear_color;
+                camera.hdr = capture.previous_hdr;
+            }
+            if let Ok(mut tonemapping) = tonemappings.get_mut(capture.camera) {
+                *tonemapping = capture.previous_tonemapping;
+            }
+        }
+        return;
+    }
+    if capture.waiting {
+        return;
+    }
+    if capture.warmup > 0 {
+        capture.warmup -= 1;
+        return;
+    }
+    capture.waiting = true;
+    commands
+        .spawn(Screenshot::image(capture.target.clone()))
+        .observe(save_screenshot);
+}
+
+fn start_capture(
+    settings: &ScreenshotSettings,
+    images: &mut Assets<Image>,
+    cameras: &mut Query<(Entity, &mut Camera), With<Camera3d>>,
+    tonemappings: &mut Query<&mut Tonemapping, With<Camera3d>>,
+    render_device: &RenderDevice,
+    accumulation_frames: u32,
+) -> Option<Capture> {
+    let window_size = cameras
+        .iter()
+        .filter(|(_, camera)| matches!(camera.target, RenderTarget::Window(_)))
+        .find_map(|(_, camera)| camera.physical_viewport_size())?;
+    // a smaller screenshot instead of a texture the gpu can't make
+    let max_size = render_device.limits().max_texture_dimension_2d;
+    let scale = settings
+        .scale
+        .min(max_size as f32 / window_size.max_element().max(1) as f32);
+    if scale < settings.scale {
+        warn!(
+            "{}x the window is too big for the gpu (at most {max_size} pixels), using {scale:.2}x",
+            settings.scale
+        );
+    }
+    let size = (window_size.as_vec2() * scale)
+        .round()
+        .as_uvec2()
+        .min(UVec2::splat(max_size));
+    let hdr = settings.format == ScreenshotFormat::Exr;
+    let texture_format = if hdr {
+        TextureFormat::Rgba16Float
+    } else {
+        TextureFormat::Rgba8UnormSrgb
+    };
+    let (camera_entity, previous_target, target) =
+        retarget_window_camera(images, cameras, render_device, size, texture_format)?;
+    // the camera was just found, it can't be gone
+    let Ok((_, mut camera)) = cameras.get_mut(camera_entity) else {
+        return None;
+    };
+    // but from here on the window stays black if we don't point it back
+    let Ok(mut tonemapping) = tonemappings.get_mut(camera_entity) else {
+        error!("the camera has no tonemapping, can't take a screenshot");
+        camera.target = previous_target;
+        return None;
+    };
+    let previous_clear_color = camera.clear_color.clone();
+    if settings.transparent {
+        camera.clear_color = ClearColorConfig::Custom(Color::NONE);
+    }
+    let previous_hdr = camera.hdr;
+    let previous_tonemapping = *tonemapping;
+    if hdr {
+        camera.hdr = true;
+        *tonemapping = Tonemapping::None;
+    }
+    return Some(Capture {
+        camera: camera_entity,
+        previous_target,
+        previous_clear_color,
+        previous_hdr,
+        previous_tonemapping,
+        format: settings.format,
+        target,
+        warmup: WARMUP_FRAMES + accumulation_frames,
+        waiting: false,
+        done: false,
+    });
+}
+
+fn save_screenshot(trigger: Trigger<ScreenshotCaptured>, mut settings: ResMut<ScreenshotSettings>) {
+    let Some(capture) = settings.capture.as_mut() else {
+        return;
+    };
+    capture.done = true;
+    let (extension, image) = match capture.format {
+        ScreenshotFormat::Png => match trigger.event().0.clone().try_into_dynamic() {
+            Ok(dynamic) => ("png", DynamicImage::ImageRgba8(dynamic.to_rgba8())),
+            Err(err) => {
+                error!("could not read back the screenshot: {err}");
+                return;
+            }
+        },
+        ScreenshotFormat::Exr => match read_rgba16_float(&trigger.event().0) {
+            Some(linear) => ("exr", DynamicImage::ImageRgba32F(linear)),
+            None => {
+                error!("could not read back the screenshot");
+                return;
+            }
+        },
+    };
+    let path = format!("screenshot_{}.{extension}", timestamp());
+    match image.save(&path) {
+        Ok(()) => info!("saved {path}"),
+        Err(err) => error!("could not write {path}: {err}"),
+    }
+}
+
+// the capture of the Rgba16Float target, as 32 bit floats for the exr encoder
+fn read_rgba16_float(image: &Image) -> Option<Rgba32FImage> {
+    if image.texture_descriptor.format != TextureFormat::Rgba16Float {
+        return None;
+    }
+    let values = image
+        .data
+        .as_ref()?
+        .chunks_exact(2)
+        .map(|bytes| f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])))
+        .collect();
+    return Rgba32FImage::from_raw(image.width(), image.height(), values);
+}
+
+// ieee half float, 1 sign bit, 5 exponent bits, 10 mantissa bits
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    let value = match exponent {
+        // subnormal
+        0 => mantissa * 2.0f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+    };
+    return sign * value;
+}
+
+/// utc, like 2024-05-01_13-45-10
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    return format_timestamp(seconds);
+}
+
+/// seconds since 1970
+fn format_timestamp(seconds: u64) -> String {
+    let (days, time_of_day) = (seconds / 86400, seconds % 86400);
+    // days since 1970 to a date, from Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    return format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_values() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 1365.0 / 4096.0);
+        // the smallest and the largest subnormal
+        assert_eq!(f16_to_f32(0x0001), 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01_00-00-00");
+        assert_eq!(format_timestamp(1714571110), "2024-05-01_13-45-10");
+        assert_eq!(format_timestamp(951868799), "2000-02-29_23-59-59");
+    }
+}
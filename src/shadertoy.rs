@@ -0,0 +1,373 @@
+// exports the current scene as a standalone glsl shader, in shadertoy's mainImage format
+// the sdf part does the same as sdf.wgsl (like codegen.rs, with the constants inlined)
+// the lighting is a lot simpler than bevys pbr: one point light, lambert + blinn-phong
+// volumes, textures and patterns are left out
+
+use bevy::prelude::*;
+
+use crate::{RaymarchMaterial, RaymarchObjectDescriptor, SpinningCam};
+
+const SDF_FUNCTIONS: &str = r#"// from sdf_primitives.wgsl
+float sdf_circle(vec3 p, float rad) {
+  return length(p) - rad;
+}
+
+float sdBox(vec3 p, vec3 b) {
+  vec3 q = abs(p) - b;
+  return length(max(q, vec3(0.))) + min(max(q.x, max(q.y, q.z)), 0.);
+}
+
+float sdConeBound(vec3 p, float h, vec2 sincos) {
+  return max(dot(sincos.yx, vec2(length(p.xz), p.y)), -h - p.y);
+}
+
+float opSmoothUnion(float d1, float d2, float k) {
+  float h = clamp(0.5 + 0.5 * (d2 - d1) / k, 0., 1.);
+  return mix(d2, d1, h) - k * h * (1. - h);
+}
+
+float opSmoothSubtract(float d1, float d2, float k) {
+  float h = clamp(0.5 - 0.5 * (d1 + d2) / k, 0., 1.);
+  return mix(d1, -d2, h) + k * h * (1. - h);
+}
+
+float opSmoothIntersect(float d1, float d2, float k) {
+  float h = clamp(0.5 - 0.5 * (d2 - d1) / k, 0., 1.);
+  return mix(d2, d1, h) + k * h * (1. - h);
+}
+
+mat4 rotation_mat_x(float angle_x) {
+  return mat4(vec4(1.0, 0.0, 0.0, 0.0),
+              vec4(0.0, cos(angle_x), sin(angle_x), 0.0),
+              vec4(0.0, -sin(angle_x), cos(angle_x), 0.0),
+              vec4(0.0, 0.0, 0.0, 1.0));
+}
+
+float sdfMandel(vec3 point, float power) {
+  vec3 z = point;
+  float dr = 1.0;
+  float dist = 0.0;
+  for (int i = 0; i < 16; i++) {
+    dist = length(z);
+    if (dist > 3.0) { break; }
+    float theta = acos(z.z / dist) * power;
+    float phi = atan(z.y, z.x) * power;
+    float distPowMinusOne = pow(dist, power - 1.0);
+    float zr = distPowMinusOne * dist;
+    dr = distPowMinusOne * power * dr + 1.0;
+    float sinTheta = sin(theta);
+    z = zr * vec3(sinTheta * cos(phi), sin(phi) * sinTheta, cos(theta));
+    z += point;
+  }
+  return 0.5 * log(dist) * dist / dr;
+}
+"#;
+
+const MAIN_IMAGE: &str = r#"float sdf_world_min(vec3 p) {
+  vec2 d = sdf_world(p);
+  return my_min(d.x, d.y);
+}
+
+// tetrahedron technique
+vec3 get_normal(vec3 p) {
+  vec2 k = vec2(1.0, -1.0);
+  float e = 0.001;
+  return normalize(k.xyy * sdf_world_min(p + k.xyy * e)
+                   + k.yyx * sdf_world_min(p + k.yyx * e)
+                   + k.yxy * sdf_world_min(p + k.yxy * e)
+                   + k.xxx * sdf_world_min(p + k.xxx * e));
+}
+
+vec3 shade(vec3 p, vec3 ray_dir) {
+  vec2 d = sdf_world(p);
+  float w = material_weight(d.x, d.y);
+  vec3 base_color = mix(BASE_COLOR1, BASE_COLOR2, w);
+  vec3 emissive = mix(EMISSIVE1, EMISSIVE2, w);
+  float metallic = mix(METALLIC1, METALLIC2, w);
+  float roughness = mix(ROUGHNESS1, ROUGHNESS2, w);
+
+  vec3 n = get_normal(p);
+  vec3 l = normalize(LIGHT_POSITION - p);
+  vec3 h = normalize(l - ray_dir);
+  float diffuse = max(dot(n, l), 0.0);
+  float shininess = 2.0 / max(roughness * roughness * roughness * roughness, 0.001) - 2.0;
+  vec3 f0 = mix(vec3(0.04), base_color, metallic);
+  vec3 specular = f0 * pow(max(dot(n, h), 0.0), shininess) * diffuse;
+  return base_color * (1.0 - metallic) * (diffuse + 0.1) + specular + emissive;
+}
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+  vec3 cam_pos = camera_position(iTime);
+  vec3 forward = normalize(camera_target(iTime) - cam_pos);
+  vec3 right = normalize(cross(forward, vec3(0.0, 1.0, 0.0)));
+  vec3 up = cross(right, forward);
+  vec2 uv = (fragCoord / iResolution.xy) * 2.0 - 1.0;
+  uv.x *= iResolution.x / iResolution.y;
+  vec3 ray_dir = normalize(forward + (right * uv.x + up * uv.y) * TAN_HALF_FOV);
+
+  float dist_marched = 0.0;
+  float min_dist = 1000.0;
+  vec2 min_dist_per_object = vec2(1000.0);
+  bool has_hit = false;
+  for (int i = 0; i < MAX_ITERATIONS; i++) {
+    if (dist_marched >= FAR_CLIP) { break; }
+    vec2 d = sdf_world(cam_pos + ray_dir * dist_marched);
+    float signed_distance = my_min(d.x, d.y);
+    min_dist_per_object = min(min_dist_per_object, d);
+    if (signed_distance < TERMINATION_DISTANCE) {
+      has_hit = true;
+      break;
+    }
+    min_dist = min(min_dist, signed_distance);
+    dist_marched += signed_distance;
+  }
+
+  vec3 color = BACKGROUND;
+  if (has_hit) {
+    color = shade(cam_pos + ray_dir * dist_marched, ray_dir);
+  } else {
+    // closest approach glow, like the global and the per object glow in the sandbox
+    float glow_range = max(GLOW_RANGE, 0.0001);
+    float glow = (1.0 - clamp(min_dist / glow_range, 0.0, 1.0)) * GLOW_COLOR.a;
+    color = mix(color, GLOW_COLOR.rgb, glow);
+    vec2 amounts = 1.0 - clamp(min_dist_per_object / glow_range, vec2(0.0), vec2(1.0));
+    color += OBJECT_GLOW1.rgb * OBJECT_GLOW1.a * amounts.x;
+    color += OBJECT_GLOW2.rgb * OBJECT_GLOW2.a * amounts.y;
+  }
+  // the sandbox colors are linear
+  fragColor = vec4(pow(color, vec3(1.0 / 2.2)), 1.0);
+}
+"#;
+
+/// the camera at the time of the export
+/// if it is a SpinningCam, the shader spins it with iTime instead
+pub struct ShadertoyCamera<'a> {
+    pub transform: &'a GlobalTransform,
+    pub fov: f32,
+    pub spinning_cam: Option<&'a SpinningCam>,
+}
+
+pub fn export_shadertoy(
+    material: &RaymarchMaterial,
+    camera: ShadertoyCamera,
+    light_position: Vec3,
+) -> String {
+    let settings = &material.raymarch_global_settings;
+    let obj1 = &material.material1;
+    let obj2 = &material.material2;
+    let mut out = String::new();
+    out.push_str("// exported from basic_raymarching\n");
+    out.push_str("// paste this into the Image tab on https://www.shadertoy.com\n\n");
+
+    out.push_str(&format!(
+        "#define FAR_CLIP {}\n",
+        glsl_float(settings.far_clip)
+    ));
+    out.push_str(&format!(
+        "#define TERMINATION_DISTANCE {}\n",
+        glsl_float(settings.termination_distance)
+    ));
+    out.push_str(&format!(
+        "#define MAX_ITERATIONS {}\n",
+        settings.max_iterations
+    ));
+    out.push_str(&format!(
+        "#define SMOOTH_AMOUNT {}\n",
+        glsl_float(settings.intersection_smooth_amount)
+    ));
+    out.push_str(&format!(
+        "#define MATERIAL_BLEND_WIDTH {}\n",
+        glsl_float(settings.material_blend_width)
+    ));
+    out.push_str(&format!(
+        "#define GLOW_RANGE {}\n",
+        glsl_float(settings.glow_range)
+    ));
+    out.push_str(&format!(
+        "#define GLOW_COLOR {}\n",
+        glsl_vec4(settings.glow_color)
+    ));
+    out.push_str("#define BACKGROUND vec3(0.0)\n");
+    out.push_str(&format!(
+        "#define LIGHT_POSITION {}\n",
+        glsl_vec3(light_position)
+    ));
+    out.push_str(&format!(
+        "#define TAN_HALF_FOV {}\n",
+        glsl_float((camera.fov * 0.5).tan())
+    ));
+    for (index, obj) in [(1, obj1), (2, obj2)] {
+        out.push_str(&format!(
+            "#define BASE_COLOR{index} {}\n",
+            glsl_vec3(obj.base_color.xyz())
+        ));
+        out.push_str(&format!(
+            "#define EMISSIVE{index} {}\n",
+            glsl_vec3(obj.emissive.xyz())
+        ));
+        out.push_str(&format!(
+            "#define METALLIC{index} {}\n",
+            glsl_float(obj.metallic)
+        ));
+        out.push_str(&format!(
+            "#define ROUGHNESS{index} {}\n",
+            glsl_float(obj.perceptual_roughness)
+        ));
+        out.push_str(&format!(
+            "#define OBJECT_GLOW{index} {}\n",
+            glsl_vec4(obj.glow_color * Vec4::new(1.0, 1.0, 1.0, obj.glow_intensity))
+        ));
+    }
+    out.push('\n');
+
+    write_camera_glsl(&mut out, &camera);
+    out.push('\n');
+    out.push_str(SDF_FUNCTIONS);
+    out.push('\n');
+    write_object_glsl(&mut out, "object1", obj1);
+    out.push('\n');
+    write_object_glsl(&mut out, "object2", obj2);
+    out.push('\n');
+
+    let intersection = match settings.intersection_method {
+        // same as my_min in sdf.wgsl, a volume leaves the solid object on its own
+        _ if obj1.volume_type_id != 0 => "b",
+        _ if obj2.volume_type_id != 0 => "a",
+        0 => "opSmoothUnion(a, b, SMOOTH_AMOUNT)",
+        1 => "opSmoothIntersect(a, b, SMOOTH_AMOUNT)",
+        2 => "opSmoothSubtract(a, b, SMOOTH_AMOUNT)",
+        _ => "100000.0",
+    };
+    out.push_str("float my_min(float a, float b) {\n");
+    out.push_str(&format!("  return {intersection};\n"));
+    out.push_str("}\n\n");
+    // how much of object2's material to use, like material_blend_weight and
+    // get_material_lerp_amount in basic_raymarch.wgsl
+    let weight = match settings.intersection_method {
+        _ if obj1.volume_type_id != 0 => "1.0",
+        _ if obj2.volume_type_id != 0 => "0.0",
+        0 => "1.0 - clamp(0.5 + 0.5 * (d2 - d1) / k, 0., 1.)",
+        1 => "1.0 - clamp(0.5 - 0.5 * (d2 - d1) / k, 0., 1.)",
+        2 => "clamp(0.5 - 0.5 * (d1 + d2) / k, 0., 1.)",
+        _ => "0.0",
+    };
+    out.push_str("float material_blend_weight(float d1, float d2, float k) {\n");
+    out.push_str("  k = max(k, 0.00001);\n");
+    out.push_str(&format!("  return {weight};\n"));
+    out.push_str("}\n\n");
+    let lerp_amount = match settings.material_blend_mode {
+        // the old way: smears even when the objects are far apart
+        0 => {
+            "vec2 distances = normalize(vec2(d1, d2));\n  \
+             return ((distances.x - distances.y) + 1.0) * 0.5"
+        }
+        1 => "return material_blend_weight(d1, d2, 0.0)",
+        2 => "return material_blend_weight(d1, d2, SMOOTH_AMOUNT)",
+        3 => "return material_blend_weight(d1, d2, MATERIAL_BLEND_WIDTH)",
+        _ => "return 0.0",
+    };
+    out.push_str("float material_weight(float d1, float d2) {\n");
+    out.push_str(&format!("  {lerp_amount};\n"));
+    out.push_str("}\n\n");
+    out.push_str("vec2 sdf_world(vec3 p) {\n");
+    out.push_str("  return vec2(object1(p), object2(p));\n");
+    out.push_str("}\n\n");
+    out.push_str(MAIN_IMAGE);
+    return out;
+}
+
+// same movement as spin_camera in main.rs
+fn write_camera_glsl(out: &mut String, camera: &ShadertoyCamera) {
+    if let Some(cam) = camera.spinning_cam {
+        out.push_str("vec3 camera_position(float time) {\n");
+        out.push_str(&format!("  float t = time * {};\n", glsl_float(cam.speed)));
+        out.push_str(&format!(
+            "  return vec3(sin(t) * {distance}, {height} + sin(t / 0.35) * {sway}, \
+             cos(t) * {distance});\n",
+            distance = glsl_float(cam.distance),
+            height = glsl_float(cam.height),
+            sway = glsl_float(cam.sway_amount)
+        ));
+        out.push_str("}\n\n");
+        out.push_str("vec3 camera_target(float time) {\n");
+        out.push_str(&format!("  return {};\n", glsl_vec3(cam.look_at)));
+        out.push_str("}\n");
+    } else {
+        let position = camera.transform.translation();
+        out.push_str("vec3 camera_position(float time) {\n");
+        out.push_str(&format!("  return {};\n", glsl_vec3(position)));
+        out.push_str("}\n\n");
+        out.push_str("vec3 camera_target(float time) {\n");
+        out.push_str(&format!(
+            "  return {};\n",
+            glsl_vec3(position + camera.transform.forward().as_vec3())
+        ));
+        out.push_str("}\n");
+    }
+}
+
+// translate_ray + sdf_object, like in codegen.rs
+fn write_object_glsl(out: &mut String, name: &str, obj: &RaymarchObjectDescriptor) {
+    out.push_str(&format!("float {name}(vec3 p) {{\n"));
+    // volumes don't have a surface
+    if obj.volume_type_id != 0 {
+        out.push_str("  return 100000.0;\n}\n");
+        return;
+    }
+    out.push_str(&format!(
+        "  vec3 rp = p - {};\n",
+        glsl_vec3(obj.world_position)
+    ));
+    if obj.move_amout != 0.0 {
+        out.push_str(&format!(
+            "  rp += vec3(sin(iTime * 0.5), cos(iTime), cos(iTime) * 0.2) * {};\n",
+            glsl_float(obj.move_amout)
+        ));
+    }
+    if obj.rotation_amount != 0.0 || obj.rotation.x != 0.0 {
+        out.push_str(&format!(
+            "  rp = (vec4(rp, 1.0) * rotation_mat_x({} + {} * iTime)).xyz;\n",
+            glsl_float(obj.rotation.x),
+            glsl_float(obj.rotation_amount)
+        ));
+    }
+    let shape_var = glsl_float(obj.shape_var);
+    let distance = match obj.shape_type_id {
+        1 => format!("sdf_circle(rp, {shape_var})"),
+        2 => format!("sdBox(rp, vec3({shape_var}))"),
+        // same offset as in sdf_object
+        3 => format!(
+            "sdConeBound(rp - vec3(0.0, 0.25, 0.0), {shape_var}, vec2({}, {}))",
+            glsl_float(obj.scale.sin()),
+            glsl_float(obj.scale.cos())
+        ),
+        4 => format!("sdfMandel(rp, {shape_var})"),
+        _ => "100000.0".to_string(),
+    };
+    out.push_str(&format!("  return {distance};\n"));
+    out.push_str("}\n");
+}
+
+// Debug always prints a decimal point (or an exponent), so glsl reads it as a float
+fn glsl_float(value: f32) -> String {
+    return format!("{value:?}");
+}
+
+fn glsl_vec3(value: Vec3) -> String {
+    return format!(
+        "vec3({}, {}, {})",
+        glsl_float(value.x),
+        glsl_float(value.y),
+        glsl_float(value.z)
+    );
+}
+
+fn glsl_vec4(value: Vec4) -> String {
+    return format!(
+        "vec4({}, {}, {}, {})",
+        glsl_float(value.x),
+        glsl_float(value.y),
+        glsl_float(value.z),
+        glsl_float(value.w)
+    );
+}
@@ -0,0 +1,638 @@
+// keyframe animation
+// every track animates one field of an object or of the global settings
+// the tracks are evaluated on the cpu every frame and written into the material,
+// so the shader doesn't know anything about it (and the hardcoded move/rotation still works)
+// note: codegen.rs bakes the shape, position and movement into the generated sdf, so while a
+// track animates one of those it turns itself off and the interpreter draws (see Param::baked)
+
+use bevy::{pbr::ExtendedMaterial, prelude::*};
+
+use crate::{
+    update_raymarch_settings_time, RaymarchGlobalSettings, RaymarchMaterial,
+    RaymarchMaterialHandle, RaymarchObjectDescriptor,
+};
+
+pub struct TimelinePlugin;
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Timeline>();
+        app.add_systems(Update, apply_timeline.after(update_raymarch_settings_time));
+    }
+}
+
+#[derive(Resource)]
+pub struct Timeline {
+    pub tracks: Vec<Track>,
+    /// the range the timeline panel shows, in seconds
+    pub length: f32,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        return Timeline {
+            tracks: Vec::new(),
+            length: 10.0,
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrackTarget {
+    Object1,
+    Object2,
+    Global,
+}
+
+impl TrackTarget {
+    pub const ALL: [TrackTarget; 3] = [
+        TrackTarget::Object1,
+        TrackTarget::Object2,
+        TrackTarget::Global,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrackTarget::Object1 => "Object 1",
+            TrackTarget::Object2 => "Object 2",
+            TrackTarget::Global => "Global",
+        }
+    }
+
+    /// reads (value None) or writes the field, param is an index into param_names
+    pub fn access(&self, param: usize, material: &mut RaymarchMaterial, value: Option<f32>) -> f32 {
+        match self {
+            TrackTarget::Object1 => (OBJECT_PARAMS[param].access)(&mut material.material1, value),
+            TrackTarget::Object2 => (OBJECT_PARAMS[param].access)(&mut material.material2, value),
+            TrackTarget::Global => {
+                (GLOBAL_PARAMS[param].access)(&mut material.raymarch_global_settings, value)
+            }
+        }
+    }
+
+    /// names of the fields that can be keyframed
+    pub fn param_names(&self) -> Vec<&'static str> {
+        match self {
+            TrackTarget::Global => GLOBAL_PARAMS.iter().map(|param| param.name).collect(),
+            _ => OBJECT_PARAMS.iter().map(|param| param.name).collect(),
+        }
+    }
+
+    /// whether the field is a constant in the generated sdf from codegen.rs
+    pub fn bakes_into_sdf(&self, param: usize) -> bool {
+        match self {
+            TrackTarget::Global => GLOBAL_PARAMS[param].bakes_into_sdf,
+            _ => OBJECT_PARAMS[param].bakes_into_sdf,
+        }
+    }
+}
+
+pub struct Track {
+    pub target: TrackTarget,
+    /// index into OBJECT_PARAMS or GLOBAL_PARAMS
+    pub param: usize,
+    /// sorted by time
+    pub keys: Vec<Keyframe>,
+}
+
+impl Track {
+    pub fn name(&self) -> String {
+        return format!(
+            "{}: {}",
+            self.target.name(),
+            self.target.param_names()[self.param]
+        );
+    }
+
+    /// whether the animated field is a constant in the generated sdf from codegen.rs
+    /// a single key doesn't animate anything
+    pub fn bakes_into_sdf(&self) -> bool {
+        return self.keys.len() >= 2 && self.target.bakes_into_sdf(self.param);
+    }
+
+    pub fn sort_keys(&mut self) {
+        self.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    /// holds the first and last value outside of the keys
+    pub fn evaluate(&self, time: f32) -> Option<f32> {
+        let first = self.keys.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        for pair in self.keys.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            if time < to.time {
+                let t = (time - from.time) / (to.time - from.time).max(0.0001);
+                return Some(from.value + (to.value - from.value) * from.easing.apply(t));
+            }
+        }
+        return self.keys.last().map(|key| key.value);
+    }
+
+    /// reads (value None) or writes the animated field
+    pub fn access(&self, material: &mut RaymarchMaterial, value: Option<f32>) -> f32 {
+        return self.target.access(self.param, material, value);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: f32,
+    /// how to get from this key to the next one
+    pub easing: Easing,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Easing {
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 5] = [
+        Easing::Step,
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Step => "step",
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease in",
+            Easing::EaseOut => "ease out",
+            Easing::EaseInOut => "ease in out",
+        }
+    }
+
+    fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+// all values are animated as f32, the ids get rounded
+trait KeyframeValue {
+    fn to_keyframe(&self) -> f32;
+    fn from_keyframe(value: f32) -> Self;
+}
+
+impl KeyframeValue for f32 {
+    fn to_keyframe(&self) -> f32 {
+        return *self;
+    }
+    fn from_keyframe(value: f32) -> Self {
+        return value;
+    }
+}
+
+impl KeyframeValue for u32 {
+    fn to_keyframe(&self) -> f32 {
+        return *self as f32;
+    }
+    fn from_keyframe(value: f32) -> Self {
+        return value.round().max(0.0) as u32;
+    }
+}
+
+type Accessor<T> = fn(&mut T, Option<f32>) -> f32;
+
+struct Param<T> {
+    name: &'static str,
+    access: Accessor<T>,
+    /// a constant in the generated sdf from codegen.rs
+    bakes_into_sdf: bool,
+}
+
+impl<T> Param<T> {
+    const fn baked(mut self) -> Self {
+        self.bakes_into_sdf = true;
+        return self;
+    }
+}
+
+macro_rules! param {
+    ($ty:ty, $name:literal, $($field:tt)+) => {
+        Param {
+            name: $name,
+            access: {
+                fn access(v: &mut $ty, value: Option<f32>) -> f32 {
+                    if let Some(value) = value {
+                        v.$($field)+ = KeyframeValue::from_keyframe(value);
+                    }
+                    return v.$($field)+.to_keyframe();
+                }
+                access as Accessor<$ty>
+            },
+            bakes_into_sdf: false,
+        }
+    };
+}
+
+// stored as (cos, sin), animated as the angle like in the object settings window
+fn anisotropy_rotation(obj: &mut RaymarchObjectDescriptor, value: Option<f32>) -> f32 {
+    if let Some(angle) = value {
+        obj.anisotropy_rotation = Vec2::new(angle.cos(), angle.sin());
+    }
+    return obj.anisotropy_rotation.y.atan2(obj.anisotropy_rotation.x);
+}
+
+// texture_flags is left out, update_raymarch_texture_flags sets it from the textures
+const OBJECT_PARAMS: &[Param<RaymarchObjectDescriptor>] = &[
+    param!(RaymarchObjectDescriptor, "position x", world_position.x).baked(),
+    param!(RaymarchObjectDescriptor, "position y", world_position.y).baked(),
+    param!(RaymarchObjectDescriptor, "position z", world_position.z).baked(),
+    param!(RaymarchObjectDescriptor, "rotation x", rotation.x).baked(),
+    param!(RaymarchObjectDescriptor, "rotation y", rotation.y),
+    param!(RaymarchObjectDescriptor, "rotation z", rotation.z),
+    param!(RaymarchObjectDescriptor, "move amount", move_amout).baked(),
+    param!(RaymarchObjectDescriptor, "rotation amount", rotation_amount).baked(),
+    param!(RaymarchObjectDescriptor, "shape", shape_type_id).baked(),
+    param!(RaymarchObjectDescriptor, "shape variable", shape_var).baked(),
+    param!(RaymarchObjectDescriptor, "scale", scale).baked(),
+    param!(RaymarchObjectDescriptor, "base color r", base_color.x),
+    param!(RaymarchObjectDescriptor, "base color g", base_color.y),
+    param!(RaymarchObjectDescriptor, "base color b", base_color.z),
+    param!(RaymarchObjectDescriptor, "base color a", base_color.w),
+    param!(RaymarchObjectDescriptor, "emissive r", emissive.x),
+    param!(RaymarchObjectDescriptor, "emissive g", emissive.y),
+    param!(RaymarchObjectDescriptor, "emissive b", emissive.z),
+    param!(RaymarchObjectDescriptor, "emissive a", emissive.w),
+    param!(RaymarchObjectDescriptor, "reflectance r", reflectance.x),
+    param!(RaymarchObjectDescriptor, "reflectance g", reflectance.y),
+    param!(RaymarchObjectDescriptor, "reflectance b", reflectance.z),
+    param!(RaymarchObjectDescriptor, "roughness", perceptual_roughness),
+    param!(RaymarchObjectDescriptor, "metallic", metallic),
+    param!(
+        RaymarchObjectDescriptor,
+        "diffuse transmission",
+        diffuse_transmission
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "specular transmission",
+        specular_transmission
+    ),
+    param!(RaymarchObjectDescriptor, "thickness", thickness),
+    param!(RaymarchObjectDescriptor, "ior", ior),
+    param!(
+        RaymarchObjectDescriptor,
+        "attenuation distance",
+        attenuation_distance
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "attenuation color r",
+        attenuation_color.x
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "attenuation color g",
+        attenuation_color.y
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "attenuation color b",
+        attenuation_color.z
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "attenuation color a",
+        attenuation_color.w
+    ),
+    param!(RaymarchObjectDescriptor, "clearcoat", clearcoat),
+    param!(
+        RaymarchObjectDescriptor,
+        "clearcoat roughness",
+        clearcoat_perceptual_roughness
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "anisotropy strength",
+        anisotropy_strength
+    ),
+    Param {
+        name: "anisotropy rotation",
+        access: anisotropy_rotation,
+        bakes_into_sdf: false,
+    },
+    param!(RaymarchObjectDescriptor, "texture scale", texture_scale),
+    param!(
+        RaymarchObjectDescriptor,
+        "texture blend sharpness",
+        texture_blend_sharpness
+    ),
+    param!(RaymarchObjectDescriptor, "pattern", pattern_type_id),
+    param!(RaymarchObjectDescriptor, "pattern color r", pattern_color.x),
+    param!(RaymarchObjectDescriptor, "pattern color g", pattern_color.y),
+    param!(RaymarchObjectDescriptor, "pattern color b", pattern_color.z),
+    param!(RaymarchObjectDescriptor, "pattern color a", pattern_color.w),
+    param!(RaymarchObjectDescriptor, "pattern scale", pattern_scale),
+    param!(RaymarchObjectDescriptor, "orbit trap", orbit_trap_type_id),
+    param!(RaymarchObjectDescriptor, "palette 1 r", palette[0].x),
+    param!(RaymarchObjectDescriptor, "palette 1 g", palette[0].y),
+    param!(RaymarchObjectDescriptor, "palette 1 b", palette[0].z),
+    param!(RaymarchObjectDescriptor, "palette 1 a", palette[0].w),
+    param!(RaymarchObjectDescriptor, "palette 2 r", palette[1].x),
+    param!(RaymarchObjectDescriptor, "palette 2 g", palette[1].y),
+    param!(RaymarchObjectDescriptor, "palette 2 b", palette[1].z),
+    param!(RaymarchObjectDescriptor, "palette 2 a", palette[1].w),
+    param!(RaymarchObjectDescriptor, "palette 3 r", palette[2].x),
+    param!(RaymarchObjectDescriptor, "palette 3 g", palette[2].y),
+    param!(RaymarchObjectDescriptor, "palette 3 b", palette[2].z),
+    param!(RaymarchObjectDescriptor, "palette 3 a", palette[2].w),
+    param!(RaymarchObjectDescriptor, "palette 4 r", palette[3].x),
+    param!(RaymarchObjectDescriptor, "palette 4 g", palette[3].y),
+    param!(RaymarchObjectDescriptor, "palette 4 b", palette[3].z),
+    param!(RaymarchObjectDescriptor, "palette 4 a", palette[3].w),
+    param!(RaymarchObjectDescriptor, "volume", volume_type_id).baked(),
+    param!(RaymarchObjectDescriptor, "volume density", volume_density),
+    param!(
+        RaymarchObjectDescriptor,
+        "volume absorption",
+        volume_absorption
+    ),
+    param!(
+        RaymarchObjectDescriptor,
+        "volume scattering",
+        volume_scattering
+    ),
+    param!(RaymarchObjectDescriptor, "volume phase g", volume_phase_g),
+    param!(
+        RaymarchObjectDescriptor,
+        "volume noise scale",
+        volume_noise_scale
+    ),
+    param!(RaymarchObjectDescriptor, "volume falloff", volume_falloff),
+    param!(RaymarchObjectDescriptor, "glow color r", glow_color.x),
+    param!(RaymarchObjectDescriptor, "glow color g", glow_color.y),
+    param!(RaymarchObjectDescriptor, "glow color b", glow_color.z),
+    param!(RaymarchObjectDescriptor, "glow color a", glow_color.w),
+    param!(RaymarchObjectDescriptor, "glow intensity", glow_intensity),
+];
+
+// time is what drives the timeline, so it can't be animated itself
+const GLOBAL_PARAMS: &[Param<RaymarchGlobalSettings>] = &[
+    param!(
+        RaymarchGlobalSettings,
+        "intersection method",
+        intersection_method
+    )
+    .baked(),
+    param!(
+        RaymarchGlobalSettings,
+        "smooth amount",
+        intersection_smooth_amount
+    )
+    .baked(),
+    param!(
+        RaymarchGlobalSettings,
+        "material blend mode",
+        material_blend_mode
+    ),
+    param!(
+        RaymarchGlobalSettings,
+        "material blend width",
+        material_blend_width
+    ),
+    param!(RaymarchGlobalSettings, "glow mode", glow_mode),
+    param!(RaymarchGlobalSettings, "glow range", glow_range),
+    param!(RaymarchGlobalSettings, "glow color r", glow_color.x),
+    param!(RaymarchGlobalSettings, "glow color g", glow_color.y),
+    param!(RaymarchGlobalSettings, "glow color b", glow_color.z),
+    param!(RaymarchGlobalSettings, "glow color a", glow_color.w),
+    param!(RaymarchGlobalSettings, "far clip", far_clip),
+    param!(
+        RaymarchGlobalSettings,
+        "termination distance",
+        termination_distance
+    ),
+    param!(RaymarchGlobalSettings, "max iterations", max_iterations),
+    param!(RaymarchGlobalSettings, "over-relaxation", over_relaxation),
+    param!(
+        RaymarchGlobalSettings,
+        "termination in pixels",
+        termination_cone_scale
+    ),
+    param!(
+        RaymarchGlobalSettings,
+        "hit refinement steps",
+        hit_refinement_steps
+    ),
+    param!(RaymarchGlobalSettings, "normal method", normal_method),
+    param!(RaymarchGlobalSettings, "normal epsilon", normal_epsilon),
+    param!(
+        RaymarchGlobalSettings,
+        "normal epsilon in pixels",
+        normal_epsilon_scale
+    ),
+    param!(RaymarchGlobalSettings, "bounces", bounce_limit),
+    param!(RaymarchGlobalSettings, "volume step size", volume_step_size),
+    param!(RaymarchGlobalSettings, "cone prepass", cone_prepass),
+    param!(
+        RaymarchGlobalSettings,
+        "dynamic resolution",
+        dynamic_resolution
+    ),
+    param!(RaymarchGlobalSettings, "resolution scale", resolution_scale),
+    param!(
+        RaymarchGlobalSettings,
+        "target frame time",
+        target_frame_time
+    ),
+    param!(RaymarchGlobalSettings, "aperture", aperture),
+    param!(RaymarchGlobalSettings, "focus distance", focus_distance),
+    param!(RaymarchGlobalSettings, "accumulation", accumulation),
+    param!(RaymarchGlobalSettings, "max samples", max_samples),
+];
+
+// doesn't compile when a field is added to the descriptors without thinking about the tables
+// (the scene file saves exactly what is in there too)
+const _: fn(&RaymarchObjectDescriptor, &RaymarchGlobalSettings) = |obj, settings| {
+    let RaymarchObjectDescriptor {
+        world_position: _,
+        rotation: _,
+        move_amout: _,
+        rotation_amount: _,
+        shape_type_id: _,
+        shape_var: _,
+        scale: _,
+        base_color: _,
+        emissive: _,
+        reflectance: _,
+        perceptual_roughness: _,
+        metallic: _,
+        diffuse_transmission: _,
+        specular_transmission: _,
+        thickness: _,
+        ior: _,
+        attenuation_distance: _,
+        attenuation_color: _,
+        clearcoat: _,
+        clearcoat_perceptual_roughness: _,
+        anisotropy_strength: _,
+        anisotropy_rotation: _,
+        texture_scale: _,
+        texture_blend_sharpness: _,
+        texture_flags: _,
+        pattern_type_id: _,
+        pattern_color: _,
+        pattern_scale: _,
+        orbit_trap_type_id: _,
+        palette: _,
+        volume_type_id: _,
+        volume_density: _,
+        volume_absorption: _,
+        volume_scattering: _,
+        volume_phase_g: _,
+        volume_noise_scale: _,
+        volume_falloff: _,
+        glow_color: _,
+        glow_intensity: _,
+    } = obj;
+    let RaymarchGlobalSettings {
+        intersection_method: _,
+        intersection_smooth_amount: _,
+        material_blend_mode: _,
+        material_blend_width: _,
+        glow_mode: _,
+        glow_range: _,
+        glow_color: _,
+        far_clip: _,
+        termination_distance: _,
+        max_iterations: _,
+        over_relaxation: _,
+        termination_cone_scale: _,
+        hit_refinement_steps: _,
+        normal_method: _,
+        normal_epsilon: _,
+        normal_epsilon_scale: _,
+        bounce_limit: _,
+        volume_step_size: _,
+        cone_prepass: _,
+        dynamic_resolution: _,
+        resolution_scale: _,
+        target_frame_time: _,
+        aperture: _,
+        focus_distance: _,
+        accumulation: _,
+        max_samples: _,
+        time: _,
+    } = settings;
+};
+
+fn apply_timeline(
+    timeline: Res<Timeline>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    if timeline.tracks.is_empty() {
+        return;
+    }
+    let maybe_mat = rm_materials.get_mut(&rm_material_handle.0);
+    if let Some(mat) = maybe_mat {
+        let time = mat.extension.raymarch_global_settings.time;
+        for track in timeline.tracks.iter() {
+            if let Some(value) = track.evaluate(time) {
+                track.access(&mut mat.extension, Some(value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, value: f32, easing: Easing) -> Keyframe {
+        return Keyframe {
+            time,
+            value,
+            easing,
+        };
+    }
+
+    fn track(keys: Vec<Keyframe>) -> Track {
+        return Track {
+            target: TrackTarget::Object1,
+            param: 0,
+            keys,
+        };
+    }
+
+    #[test]
+    fn no_keys_no_value() {
+        assert_eq!(track(Vec::new()).evaluate(1.0), None);
+    }
+
+    #[test]
+    fn evaluate_at_keys() {
+        let track = track(vec![
+            key(1.0, 10.0, Easing::Linear),
+            key(2.0, 20.0, Easing::EaseIn),
+            key(4.0, -5.0, Easing::Linear),
+        ]);
+        assert_eq!(track.evaluate(1.0), Some(10.0));
+        assert_eq!(track.evaluate(2.0), Some(20.0));
+        assert_eq!(track.evaluate(4.0), Some(-5.0));
+        // held outside of the keys
+        assert_eq!(track.evaluate(0.0), Some(10.0));
+        assert_eq!(track.evaluate(9.0), Some(-5.0));
+    }
+
+    #[test]
+    fn evaluate_between_keys() {
+        let track = track(vec![
+            key(0.0, 0.0, Easing::Linear),
+            key(2.0, 10.0, Easing::Step),
+            key(4.0, 20.0, Easing::EaseIn),
+            key(6.0, 30.0, Easing::Linear),
+        ]);
+        assert_eq!(track.evaluate(0.5), Some(2.5));
+        // the easing of the key before counts
+        assert_eq!(track.evaluate(3.9), Some(10.0));
+        assert_eq!(track.evaluate(5.0), Some(21.25));
+    }
+
+    #[test]
+    fn easing_endpoints() {
+        for easing in Easing::ALL {
+            assert_eq!(easing.apply(0.0), 0.0, "{}", easing.name());
+            if easing != Easing::Step {
+                assert_eq!(easing.apply(1.0), 1.0, "{}", easing.name());
+            }
+        }
+    }
+
+    #[test]
+    fn easing_midpoint() {
+        assert_eq!(Easing::Step.apply(0.5), 0.0);
+        assert_eq!(Easing::Linear.apply(0.5), 0.5);
+        assert_eq!(Easing::EaseIn.apply(0.5), 0.125);
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.875);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        // clamped
+        assert_eq!(Easing::Linear.apply(2.0), 1.0);
+    }
+
+    #[test]
+    fn single_key_bakes_nothing() {
+        let mut track = track(vec![key(0.0, 1.0, Easing::Linear)]);
+        // object position x is baked
+        assert!(!track.bakes_into_sdf());
+        track.keys.push(key(1.0, 2.0, Easing::Linear));
+        assert!(track.bakes_into_sdf());
+    }
+}
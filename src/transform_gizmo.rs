@@ -0,0 +1,509 @@
+// moving, rotating and scaling the selected object (see picking.rs) in the viewport
+// the handles write straight into its RaymarchObjectDescriptor, same as the sliders
+// rotate only has the x ring, the shader only knows rotation around x (see translate_ray)
+// scale changes shape_var, which is the size of the sphere, cube and cone. the mandelbulb has
+// its power in there, so it can't be scaled
+// the gizmo sits where the object is drawn right now (with the movement over time), dragging
+// moves the position it moves around
+// a selected point of the camera path (see camera_path.rs) gets the move handles in world space
+
+use std::f32::consts::PI;
+
+use bevy::{
+    input::InputSystem, pbr::ExtendedMaterial, prelude::*, render::camera::RenderTarget,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+use crate::{
+    camera_controller::object_radius,
+    camera_path::{CameraKeyPoint, CameraPath},
+    cpu_sdf::{self, SdfObject},
+    picking::Selection,
+    RaymarchMaterial, RaymarchMaterialHandle, RaymarchObjectDescriptor, SpinningCam,
+};
+
+/// how close the cursor has to be to a handle to grab it, in pixels
+const GRAB_DISTANCE: f32 = 8.0;
+/// segments of the rotation ring
+const RING_SEGMENTS: usize = 48;
+
+pub struct TransformGizmoPlugin;
+impl Plugin for TransformGizmoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransformGizmo>();
+        // before the cameras and picking look at the mouse, so they can leave a drag alone
+        app.add_systems(PreUpdate, drag_transform_gizmo.after(InputSystem));
+        app.add_systems(Update, draw_transform_gizmo);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GizmoSpace {
+    World,
+    /// follows the rotation of the object
+    Local,
+}
+
+#[derive(Resource)]
+pub struct TransformGizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    /// holding ctrl flips this while dragging
+    pub snap: bool,
+    pub translate_step: f32,
+    /// in radians
+    pub rotate_step: f32,
+    pub scale_step: f32,
+    /// index of the axis under the cursor
+    hovered: Option<usize>,
+    drag: Option<GizmoDrag>,
+}
+
+impl Default for TransformGizmo {
+    fn default() -> Self {
+        return TransformGizmo {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            snap: false,
+            translate_step: 0.1,
+            rotate_step: 15.0_f32.to_radians(),
+            scale_step: 0.05,
+            hovered: None,
+            drag: None,
+        };
+    }
+}
+
+impl TransformGizmo {
+    pub fn is_dragging(&self) -> bool {
+        return self.drag.is_some();
+    }
+}
+
+/// what the gizmo works on, the selected object or the selected point of the camera path
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum GizmoTarget {
+    Object(SdfObject),
+    /// can only be moved, whatever the mode is
+    CameraKey(CameraKeyPoint),
+}
+
+impl GizmoTarget {
+    fn mode(&self, mode: GizmoMode) -> GizmoMode {
+        match self {
+            GizmoTarget::Object(_) => mode,
+            GizmoTarget::CameraKey(_) => GizmoMode::Translate,
+        }
+    }
+}
+
+struct GizmoDrag {
+    start: DragStart,
+    axis: usize,
+    /// where the handle was grabbed, along the axis or the angle on the ring
+    grab: f32,
+    /// the gizmo stays where it was when the drag started
+    frame: GizmoFrame,
+}
+
+/// the target and what it was when the drag started
+enum DragStart {
+    Object(SdfObject, RaymarchObjectDescriptor),
+    CameraKey(CameraKeyPoint, Vec3),
+}
+
+#[derive(Clone, Copy)]
+struct GizmoFrame {
+    center: Vec3,
+    axes: [Vec3; 3],
+    /// length of the handles and radius of the ring
+    size: f32,
+}
+
+impl GizmoFrame {
+    fn new(desc: &RaymarchObjectDescriptor, time: f32, space: GizmoSpace) -> Self {
+        // same rotation as translate_ray, object space -> world space
+        let rotation = match space {
+            GizmoSpace::World => Quat::IDENTITY,
+            GizmoSpace::Local => Quat::from_rotation_x(cpu_sdf::rotation_x(desc, time)),
+        };
+        return GizmoFrame {
+            center: cpu_sdf::object_center(desc, time),
+            axes: [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| rotation * axis),
+            size: (object_radius(desc) * 1.5).max(0.3),
+        };
+    }
+
+    // camera path points are tiny, the gizmo gets a fixed size there
+    fn at_point(center: Vec3) -> Self {
+        return GizmoFrame {
+            center,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            size: 0.3,
+        };
+    }
+
+    fn tip(&self, axis: usize) -> Vec3 {
+        return self.center + self.axes[axis] * self.size;
+    }
+
+    // the ring around x
+    fn ring_points(&self) -> impl Iterator<Item = Vec3> + '_ {
+        return (0..=RING_SEGMENTS).map(|segment| {
+            let angle = segment as f32 / RING_SEGMENTS as f32 * 2.0 * PI;
+            return self.center
+                + (self.axes[1] * angle.cos() + self.axes[2] * angle.sin()) * self.size;
+        });
+    }
+
+    /// where on the axis line the ray gets closest
+    fn axis_parameter(&self, ray: Ray3d, axis: usize) -> Option<f32> {
+        let direction = *ray.direction;
+        let axis = self.axes[axis];
+        let offset = ray.origin - self.center;
+        let b = direction.dot(axis);
+        let denominator = 1.0 - b * b;
+        // looking straight down the axis
+        if denominator < 0.0001 {
+            return None;
+        }
+        return Some((axis.dot(offset) - b * direction.dot(offset)) / denominator);
+    }
+
+    /// angle around x where the ray hits the plane of the ring
+    fn ring_angle(&self, ray: Ray3d) -> Option<f32> {
+        let distance = ray.intersect_plane(self.center, InfinitePlane3d::new(self.axes[0]))?;
+        let point = ray.get_point(distance) - self.center;
+        return Some(point.dot(self.axes[2]).atan2(point.dot(self.axes[1])));
+    }
+}
+
+/// the mandelbulb has its power in shape_var, not its size
+pub fn can_scale(desc: &RaymarchObjectDescriptor) -> bool {
+    return matches!(desc.shape_type_id, 1..=3);
+}
+
+fn snap_to(value: f32, step: f32) -> f32 {
+    if step <= 0.0 {
+        return value;
+    }
+    return (value / step).round() * step;
+}
+
+/// moves start along an axis of the frame, by the distance the cursor moved along it
+fn translate(
+    gizmo: &TransformGizmo,
+    frame: &GizmoFrame,
+    axis: usize,
+    start: Vec3,
+    delta: f32,
+    snap: bool,
+) -> Vec3 {
+    let mut delta = delta;
+    if snap && gizmo.space == GizmoSpace::Local {
+        delta = snap_to(delta, gizmo.translate_step);
+    }
+    let mut position = start + frame.axes[axis] * delta;
+    // in world space, snap to the grid instead of the distance moved
+    if snap && gizmo.space == GizmoSpace::World {
+        position[axis] = snap_to(position[axis], gizmo.translate_step);
+    }
+    return position;
+}
+
+fn gizmo_target(selection: &Selection, camera_path: &CameraPath) -> Option<GizmoTarget> {
+    if let Some(point) = camera_path.selected {
+        if camera_path.show && camera_path.point(point).is_some() {
+            return Some(GizmoTarget::CameraKey(point));
+        }
+    }
+    return selection.object.map(GizmoTarget::Object);
+}
+
+/// where the gizmo is for the target right now, None if there is nothing to show
+fn target_frame(
+    gizmo: &TransformGizmo,
+    target: GizmoTarget,
+    camera_path: &CameraPath,
+    material: &RaymarchMaterial,
+) -> Option<GizmoFrame> {
+    match target {
+        GizmoTarget::CameraKey(point) => {
+            return camera_path.point(point).map(GizmoFrame::at_point);
+        }
+        GizmoTarget::Object(object) => {
+            let desc = object.descriptor(material);
+            if gizmo.mode == GizmoMode::Scale && !can_scale(desc) {
+                return None;
+            }
+            let time = material.raymarch_global_settings.time;
+            return Some(GizmoFrame::new(desc, time, gizmo.space));
+        }
+    }
+}
+
+fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let segment = end - start;
+    let t = ((point - start).dot(segment) / segment.length_squared().max(0.0001)).clamp(0.0, 1.0);
+    return point.distance(start + segment * t);
+}
+
+fn hovered_axis(
+    frame: &GizmoFrame,
+    mode: GizmoMode,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    cursor: Vec2,
+) -> Option<usize> {
+    let to_screen = |point: Vec3| camera.world_to_viewport(camera_transform, point).ok();
+    let axes = match mode {
+        GizmoMode::Rotate => 0..1,
+        GizmoMode::Translate | GizmoMode::Scale => 0..3,
+    };
+    let mut closest = None;
+    let mut closest_distance = GRAB_DISTANCE;
+    for axis in axes {
+        let points: Vec<Vec2> = match mode {
+            GizmoMode::Rotate => frame.ring_points().filter_map(to_screen).collect(),
+            GizmoMode::Translate | GizmoMode::Scale => [frame.center, frame.tip(axis)]
+                .into_iter()
+                .filter_map(to_screen)
+                .collect(),
+        };
+        let distance = points
+            .windows(2)
+            .map(|segment| distance_to_segment(cursor, segment[0], segment[1]))
+            .fold(f32::INFINITY, f32::min);
+        if distance < closest_distance {
+            closest = Some(axis);
+            closest_distance = distance;
+        }
+    }
+    return closest;
+}
+
+fn drag_transform_gizmo(
+    mut contexts: EguiContexts,
+    mut gizmo: ResMut<TransformGizmo>,
+    selection: Res<Selection>,
+    mut camera_path: ResMut<CameraPath>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<SpinningCam>>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    if !mouse_buttons.pressed(MouseButton::Left) {
+        gizmo.drag = None;
+    }
+    let Some(target) = gizmo_target(&selection, &camera_path) else {
+        gizmo.drag = None;
+        gizmo.hovered = None;
+        return;
+    };
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.single(), cameras.single()) else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        gizmo.hovered = None;
+        return;
+    };
+    let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+
+    if let Some(drag) = &gizmo.drag {
+        let snap = gizmo.snap != keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let frame = &drag.frame;
+        let (object, start) = match &drag.start {
+            DragStart::CameraKey(point, start) => {
+                let Some(along) = frame.axis_parameter(ray, drag.axis) else {
+                    return;
+                };
+                let position = translate(&gizmo, frame, drag.axis, *start, along - drag.grab, snap);
+                if camera_path.point(*point) != Some(position) {
+                    camera_path.set_point(*point, position);
+                }
+                return;
+            }
+            DragStart::Object(object, start) => (*object, start),
+        };
+        let mut desc = start.clone();
+        match gizmo.mode {
+            GizmoMode::Translate => {
+                let Some(along) = frame.axis_parameter(ray, drag.axis) else {
+                    return;
+                };
+                desc.world_position = translate(
+                    &gizmo,
+                    frame,
+                    drag.axis,
+                    start.world_position,
+                    along - drag.grab,
+                    snap,
+                );
+            }
+            GizmoMode::Rotate => {
+                let Some(angle) = frame.ring_angle(ray) else {
+                    return;
+                };
+                let mut rotation = start.rotation.x + angle - drag.grab;
+                if snap {
+                    rotation = snap_to(rotation, gizmo.rotate_step);
+                }
+                // same range as the slider
+                desc.rotation.x = (rotation + PI).rem_euclid(2.0 * PI) - PI;
+            }
+            GizmoMode::Scale => {
+                let Some(along) = frame.axis_parameter(ray, drag.axis) else {
+                    return;
+                };
+                // dragging by the length of the handle doubles the size
+                let factor = 1.0 + (along - drag.grab) / frame.size;
+                let mut size = start.shape_var * factor;
+                if snap {
+                    size = snap_to(size, gizmo.scale_step);
+                }
+                desc.shape_var = size.max(0.01);
+            }
+        }
+        let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+            return;
+        };
+        let current = object.descriptor(&rm_material.extension);
+        // only touch the asset when something changes, get_mut sends a modified event
+        let (position, rotation, size) = (desc.world_position, desc.rotation, desc.shape_var);
+        if current.world_position == position
+            && current.rotation == rotation
+            && current.shape_var == size
+        {
+            return;
+        }
+        // just the fields the gizmo edits, the timeline might animate the rest
+        if let Some(rm_material) = rm_materials.get_mut(&rm_material_handle.0) {
+            let current = object.descriptor_mut(&mut rm_material.extension);
+            current.world_position = position;
+            current.rotation = rotation;
+            current.shape_var = size;
+        }
+        return;
+    }
+
+    let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let material = &rm_material.extension;
+    let Some(frame) = target_frame(&gizmo, target, &camera_path, material) else {
+        gizmo.hovered = None;
+        return;
+    };
+    let mode = target.mode(gizmo.mode);
+    let ctx = contexts.ctx_mut();
+    let use_mouse = !ctx.wants_pointer_input() && !ctx.is_pointer_over_area();
+    gizmo.hovered =
+        hovered_axis(&frame, mode, camera, camera_transform, cursor).filter(|_| use_mouse);
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(axis) = gizmo.hovered else {
+        return;
+    };
+    let grab = match mode {
+        GizmoMode::Rotate => frame.ring_angle(ray),
+        GizmoMode::Translate | GizmoMode::Scale => frame.axis_parameter(ray, axis),
+    };
+    let start = match target {
+        GizmoTarget::Object(object) => {
+            DragStart::Object(object, object.descriptor(material).clone())
+        }
+        GizmoTarget::CameraKey(point) => DragStart::CameraKey(point, frame.center),
+    };
+    if let Some(grab) = grab {
+        gizmo.drag = Some(GizmoDrag {
+            start,
+            axis,
+            grab,
+            frame,
+        });
+    }
+}
+
+fn draw_transform_gizmo(
+    gizmo: Res<TransformGizmo>,
+    selection: Res<Selection>,
+    camera_path: Res<CameraPath>,
+    cameras: Query<&Camera, With<SpinningCam>>,
+    rm_materials: Res<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut gizmos: Gizmos,
+) {
+    let Some(target) = gizmo_target(&selection, &camera_path) else {
+        return;
+    };
+    // not in screenshots and offline renders
+    let to_window = cameras
+        .iter()
+        .all(|camera| matches!(camera.target, RenderTarget::Window(_)));
+    if !to_window {
+        return;
+    }
+    let Some(rm_material) = rm_materials.get(&rm_material_handle.0) else {
+        return;
+    };
+    let frame = match &gizmo.drag {
+        Some(drag) => drag.frame,
+        None => {
+            let Some(frame) = target_frame(&gizmo, target, &camera_path, &rm_material.extension)
+            else {
+                return;
+            };
+            frame
+        }
+    };
+    let active = gizmo.drag.as_ref().map(|drag| drag.axis).or(gizmo.hovered);
+    let color = |axis: usize| {
+        if active == Some(axis) {
+            return Color::srgb(1.0, 1.0, 0.3);
+        }
+        return [
+            Color::srgb(1.0, 0.2, 0.2),
+            Color::srgb(0.2, 1.0, 0.2),
+            Color::srgb(0.2, 0.4, 1.0),
+        ][axis];
+    };
+    match target.mode(gizmo.mode) {
+        GizmoMode::Translate => {
+            for axis in 0..3 {
+                gizmos.arrow(frame.center, frame.tip(axis), color(axis));
+            }
+        }
+        GizmoMode::Rotate => {
+            gizmos.linestrip(frame.ring_points(), color(0));
+        }
+        GizmoMode::Scale => {
+            let rotation = Quat::from_mat3(&Mat3::from_cols(
+                frame.axes[0],
+                frame.axes[1],
+                frame.axes[2],
+            ));
+            for axis in 0..3 {
+                gizmos.line(frame.center, frame.tip(axis), color(axis));
+                gizmos.cuboid(
+                    Transform::from_translation(frame.tip(axis))
+                        .with_rotation(rotation)
+                        .with_scale(Vec3::splat(frame.size * 0.1)),
+                    color(axis),
+                );
+            }
+        }
+    }
+}
@@ -1,18 +1,46 @@
 use core::f32;
 
-use bevy::{pbr::ExtendedMaterial, prelude::*};
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::camera::ScalingMode,
+};
 use bevy_egui::{
     self,
     egui::{self, Color32},
     EguiContextPass, EguiContexts, EguiPlugin,
 };
 
-use crate::{RaymarchMaterial, RaymarchMaterialHandle, RaymarchObjectDescriptor, SpinningCam};
+use crate::{
+    accumulation::Accumulation,
+    benchmark::MarcherBenchmark,
+    camera_controller::{object_radius, CameraController, CameraMode},
+    camera_path::{CameraKey, CameraPath},
+    codegen::GeneratedSdf,
+    cpu_sdf::SdfObject,
+    load_texture,
+    picking::Selection,
+    playback::Playback,
+    shadertoy::{export_shadertoy, ShadertoyCamera},
+    timeline::{Easing, Keyframe, Timeline, Track, TrackTarget},
+    transform_gizmo::{can_scale, GizmoMode, GizmoSpace, TransformGizmo},
+    RaymarchGlobalSettings, RaymarchMaterial, RaymarchMaterialHandle, RaymarchObjectDescriptor,
+    SpinningCam,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    offline_render::OfflineRender,
+    scene_file::{load_scene, save_scene, SCENE_FILE},
+    screenshot::{ScreenshotFormat, ScreenshotSettings},
+};
 
 pub struct MyRaymarchUi;
 impl Plugin for MyRaymarchUi {
     fn build(&self, app: &mut App) {
         app.init_resource::<UiState>();
+        app.init_resource::<TexturePathInputs>();
+        app.init_resource::<NewTrackInputs>();
         app.add_plugins(EguiPlugin {
             enable_multipass_for_primary_context: false,
         });
@@ -21,10 +49,14 @@ impl Plugin for MyRaymarchUi {
             (
                 camera_settings_ui,
                 object_settings_ui,
+                transform_gizmo_ui,
                 global_settings_ui,
                 ui_settings_ui,
+                timeline_ui,
             ),
         );
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(EguiContextPass, offline_render_ui);
     }
 }
 
@@ -33,6 +65,10 @@ fn global_settings_ui(
     mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
     rm_material_handle: Res<RaymarchMaterialHandle>,
     ui_state: Res<UiState>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut generated_sdf: ResMut<GeneratedSdf>,
+    accumulations: Query<&Accumulation>,
+    mut benchmark: ResMut<MarcherBenchmark>,
 ) {
     if ui_state.into_inner() == &UiState::Minimal {
         return;
@@ -40,6 +76,32 @@ fn global_settings_ui(
     let maybe_mat = rm_materials.get_mut(&rm_material_handle.0);
     if let Some(mat) = maybe_mat {
         egui::Window::new("Global Settings").show(contexts.ctx_mut(), |ui| {
+            let frame_time = diagnostics
+                .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+                .and_then(|frame_time| frame_time.smoothed());
+            if let Some(frame_time) = frame_time {
+                ui.label(format!("frame time: {:.2} ms", frame_time));
+            }
+            ui.horizontal(|ui| {
+                if let Some((case, cases)) = benchmark.progress() {
+                    ui.label(format!("benchmarking {case} / {cases}..."));
+                } else if ui
+                    .button("benchmark marcher options")
+                    .on_hover_text(
+                        "measures each option on the current scene and two demos, without vsync",
+                    )
+                    .clicked()
+                {
+                    benchmark.start();
+                }
+            });
+            for result in benchmark.results.iter() {
+                let speedup = benchmark.speedup(result).unwrap_or(0.0);
+                ui.label(format!(
+                    "{}, {}: {:.2} ms ({speedup:+.1} %)",
+                    result.scene, result.case, result.frame_time
+                ));
+            }
             ui.horizontal(|ui| {
                 ui.label("far clip");
                 ui.add(egui::Slider::new(
@@ -54,6 +116,174 @@ fn global_settings_ui(
                     0.0001..=0.5,
                 ));
             });
+            ui.horizontal(|ui| {
+                ui.label("max iterations");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.max_iterations,
+                    16..=1024,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("over-relaxation");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.over_relaxation,
+                    1.0..=2.0,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("termination in pixels");
+                ui.add(egui::Slider::new(
+                    &mut mat
+                        .extension
+                        .raymarch_global_settings
+                        .termination_cone_scale,
+                    0.0..=4.0,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("hit refinement steps");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.hit_refinement_steps,
+                    0..=8,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("normals");
+                ui.vertical(|ui| {
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.normal_method,
+                        NormalMethod::Tetrahedron as u32,
+                        "tetrahedron",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.normal_method,
+                        NormalMethod::Central as u32,
+                        "central differences",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.normal_method,
+                        NormalMethod::Forward as u32,
+                        "forward differences",
+                    );
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.label("normal epsilon");
+                ui.add(
+                    egui::Slider::new(
+                        &mut mat.extension.raymarch_global_settings.normal_epsilon,
+                        0.00001..=0.01,
+                    )
+                    .logarithmic(true),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("normal epsilon in pixels");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.normal_epsilon_scale,
+                    0.0..=4.0,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut generated_sdf.enabled, "compile scene to wgsl");
+                if generated_sdf.enabled {
+                    if generated_sdf.is_paused_by_timeline() {
+                        ui.label("(off while the timeline animates the shape)");
+                    } else if generated_sdf.has_non_finite_values() {
+                        ui.label("(off, the scene has a NaN or infinite value)");
+                    } else if generated_sdf.is_ready() {
+                        ui.label("(generated)");
+                    } else {
+                        ui.label("(compiling, interpreter for now)");
+                    }
+                }
+            });
+            // needs compute shaders, so not on web
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.horizontal(|ui| {
+                let settings = &mut mat.extension.raymarch_global_settings;
+                let mut cone_prepass = settings.cone_prepass != 0;
+                ui.checkbox(&mut cone_prepass, "cone marching prepass");
+                settings.cone_prepass = cone_prepass as u32;
+            });
+            ui.horizontal(|ui| {
+                ui.label("resolution");
+                ui.vertical(|ui| {
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.dynamic_resolution,
+                        DynamicResolution::Off as u32,
+                        "full",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.dynamic_resolution,
+                        DynamicResolution::Fixed as u32,
+                        "fixed scale",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.dynamic_resolution,
+                        DynamicResolution::Adaptive as u32,
+                        "adaptive",
+                    );
+                });
+            });
+            let dynamic_resolution = mat.extension.raymarch_global_settings.dynamic_resolution;
+            if dynamic_resolution == DynamicResolution::Fixed as u32 {
+                ui.horizontal(|ui| {
+                    ui.label("resolution scale");
+                    ui.add(egui::Slider::new(
+                        &mut mat.extension.raymarch_global_settings.resolution_scale,
+                        0.1..=1.0,
+                    ));
+                });
+            } else if dynamic_resolution == DynamicResolution::Adaptive as u32 {
+                ui.horizontal(|ui| {
+                    ui.label("target frame time (ms)");
+                    ui.add(egui::Slider::new(
+                        &mut mat.extension.raymarch_global_settings.target_frame_time,
+                        4.0..=50.0,
+                    ));
+                });
+                ui.label(format!(
+                    "resolution scale: {:.2}",
+                    mat.extension.raymarch_global_settings.resolution_scale
+                ));
+            }
+            ui.horizontal(|ui| {
+                let settings = &mut mat.extension.raymarch_global_settings;
+                let mut accumulation = settings.accumulation != 0;
+                ui.checkbox(&mut accumulation, "progressive accumulation");
+                settings.accumulation = accumulation as u32;
+            });
+            ui.horizontal(|ui| {
+                ui.label("samples");
+                ui.add(
+                    egui::Slider::new(
+                        &mut mat.extension.raymarch_global_settings.max_samples,
+                        1..=4096,
+                    )
+                    .logarithmic(true),
+                );
+            });
+            if let Some((samples, max_samples)) = accumulations
+                .iter()
+                .find_map(|accumulation| accumulation.progress())
+            {
+                ui.label(format!("accumulated: {samples} / {max_samples}"));
+            }
+            ui.horizontal(|ui| {
+                ui.label("reflection/refraction bounces");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.bounce_limit,
+                    0..=4,
+                ));
+            });
+            ui.horizontal(|ui| {
+                ui.label("volume step size");
+                ui.add(egui::Slider::new(
+                    &mut mat.extension.raymarch_global_settings.volume_step_size,
+                    0.005..=0.2,
+                ));
+            });
             ui.horizontal(|ui| {
                 ui.label("glow_range");
                 ui.add(egui::Slider::new(
@@ -68,6 +298,21 @@ fn global_settings_ui(
                 ui.color_edit_button_srgba(&mut color32);
                 mat.extension.raymarch_global_settings.glow_color = color32_to_vec4(color32);
             });
+            ui.horizontal(|ui| {
+                ui.label("object glow");
+                ui.vertical(|ui| {
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.glow_mode,
+                        GlowMode::ClosestApproach as u32,
+                        "closest approach",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.glow_mode,
+                        GlowMode::Accumulated as u32,
+                        "accumulated",
+                    );
+                });
+            });
             ui.horizontal(|ui| {
                 ui.label("intersection method");
                 ui.vertical(|ui| {
@@ -98,38 +343,287 @@ fn global_settings_ui(
                     0.0..=1.0,
                 ));
             });
+            ui.horizontal(|ui| {
+                ui.label("material blending");
+                ui.vertical(|ui| {
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.material_blend_mode,
+                        MaterialBlendMode::Nearest as u32,
+                        "nearest object",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.material_blend_mode,
+                        MaterialBlendMode::SmoothWeight as u32,
+                        "smooth intersection weight",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.material_blend_mode,
+                        MaterialBlendMode::CustomWidth as u32,
+                        "custom width",
+                    );
+                    ui.radio_value(
+                        &mut mat.extension.raymarch_global_settings.material_blend_mode,
+                        MaterialBlendMode::DistanceLerp as u32,
+                        "normalized distance (legacy)",
+                    );
+                });
+            });
+            if mat.extension.raymarch_global_settings.material_blend_mode
+                == MaterialBlendMode::CustomWidth as u32
+            {
+                ui.horizontal(|ui| {
+                    ui.label("material blend width");
+                    ui.add(egui::Slider::new(
+                        &mut mat.extension.raymarch_global_settings.material_blend_width,
+                        0.0..=1.0,
+                    ));
+                });
+            }
         });
     }
 }
 
 fn camera_settings_ui(
     mut contexts: EguiContexts,
-    mut cameras: Query<&mut SpinningCam>,
+    mut cameras: Query<(&mut SpinningCam, &Transform, &mut Projection)>,
+    mut controller: ResMut<CameraController>,
+    mut camera_path: ResMut<CameraPath>,
+    playback: Res<Playback>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
     ui_state: Res<UiState>,
 ) {
     if ui_state.into_inner() == &UiState::Minimal {
         return;
     }
     egui::Window::new("Camera Settings").show(contexts.ctx_mut(), |ui| {
-        for mut cam in cameras.iter_mut() {
+        for (mut cam, transform, mut projection) in cameras.iter_mut() {
             ui.horizontal(|ui| {
-                ui.label("Speed:");
-                ui.add(egui::Slider::new(&mut cam.speed, 0.0..=1.0));
+                ui.label("Mode:");
+                let mut mode = controller.mode();
+                ui.vertical(|ui| {
+                    ui.radio_value(&mut mode, CameraMode::Spinning, "spinning");
+                    ui.radio_value(&mut mode, CameraMode::Orbit, "orbit");
+                    ui.radio_value(&mut mode, CameraMode::Fly, "fly");
+                    ui.radio_value(&mut mode, CameraMode::Path, "path");
+                });
+                controller.set_mode(mode, transform);
             });
-            ui.horizontal(|ui| {
-                ui.label("Distance:");
-                ui.add(egui::Slider::new(&mut cam.distance, 0.0..=10.0));
+            match controller.mode() {
+                CameraMode::Spinning => {
+                    ui.horizontal(|ui| {
+                        ui.label("Speed:");
+                        ui.add(egui::Slider::new(&mut cam.speed, 0.0..=1.0));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Distance:");
+                        ui.add(egui::Slider::new(&mut cam.distance, 0.0..=10.0));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("height:");
+                        ui.add(egui::Slider::new(&mut cam.height, 0.0..=10.0));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("sway:");
+                        ui.add(egui::Slider::new(&mut cam.sway_amount, 0.0..=1.0));
+                    });
+                }
+                CameraMode::Orbit => {
+                    ui.label("left drag: rotate, right drag: pan, scroll: zoom");
+                    ui.horizontal(|ui| {
+                        ui.label("Distance:");
+                        ui.add(egui::Slider::new(
+                            &mut controller.orbit.distance,
+                            0.05..=100.0,
+                        ));
+                    });
+                }
+                CameraMode::Fly => {
+                    ui.label("right drag: look, wasd: move, q/e: down/up, shift: faster");
+                    ui.horizontal(|ui| {
+                        ui.label("Speed:");
+                        ui.add(egui::Slider::new(&mut controller.fly.speed, 0.1..=20.0));
+                    });
+                }
+                CameraMode::Path => {
+                    ui.label("follows the path below with the playback time");
+                }
+            }
+            if let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) {
+                ui.horizontal(|ui| {
+                    ui.label("Frame:");
+                    for (name, desc) in [
+                        ("object 1", &mat.extension.material1),
+                        ("object 2", &mat.extension.material2),
+                    ] {
+                        if ui.button(name).clicked() {
+                            controller.frame(desc.world_position, object_radius(desc), transform);
+                        }
+                    }
+                });
+                let objects = [
+                    mat.extension.material1.world_position,
+                    mat.extension.material2.world_position,
+                ];
+                camera_lens_ui(
+                    ui,
+                    &mut projection,
+                    &mut mat.extension.raymarch_global_settings,
+                    transform,
+                    objects,
+                );
+            }
+            let fov = match projection.as_ref() {
+                Projection::Perspective(perspective) => perspective.fov,
+                _ => PerspectiveProjection::default().fov,
+            };
+            // the orbit camera knows what it looks at, the others just look ahead
+            let look_at = match controller.mode() {
+                CameraMode::Orbit => controller.orbit.focus,
+                CameraMode::Spinning => cam.look_at,
+                _ => transform.translation + transform.forward() * 2.0,
+            };
+            camera_path_ui(ui, &mut camera_path, transform, look_at, fov, playback.time);
+        }
+    });
+}
+
+// the marcher reads all of this from the view, and the aperture/focus from the settings
+fn camera_lens_ui(
+    ui: &mut egui::Ui,
+    projection: &mut Projection,
+    settings: &mut RaymarchGlobalSettings,
+    transform: &Transform,
+    objects: [Vec3; 2],
+) {
+    ui.heading("Lens");
+    let mut orthographic = matches!(projection, Projection::Orthographic(_));
+    ui.horizontal(|ui| {
+        ui.label("Projection:");
+        ui.radio_value(&mut orthographic, false, "perspective");
+        ui.radio_value(&mut orthographic, true, "orthographic");
+    });
+    match (orthographic, &*projection) {
+        (true, Projection::Perspective(perspective)) => {
+            // things at the focus distance stay the same size
+            let viewport_height = 2.0 * (perspective.fov * 0.5).tan() * settings.focus_distance;
+            *projection = Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::FixedVertical { viewport_height },
+                ..OrthographicProjection::default_3d()
             });
+        }
+        (false, Projection::Orthographic(_)) => {
+            *projection = Projection::Perspective(PerspectiveProjection::default());
+        }
+        _ => {}
+    }
+    match projection {
+        Projection::Perspective(perspective) => {
             ui.horizontal(|ui| {
-                ui.label("height:");
-                ui.add(egui::Slider::new(&mut cam.height, 0.0..=10.0));
+                ui.label("fov");
+                let mut degrees = perspective.fov.to_degrees();
+                // only write it back on changes, the round trip isn't exact
+                if ui
+                    .add(egui::Slider::new(&mut degrees, 10.0..=120.0).suffix("°"))
+                    .changed()
+                {
+                    perspective.fov = degrees.to_radians();
+                }
             });
-            ui.horizontal(|ui| {
-                ui.label("sway:");
-                ui.add(egui::Slider::new(&mut cam.sway_amount, 0.0..=1.0));
+        }
+        Projection::Orthographic(orthographic) => {
+            if let ScalingMode::FixedVertical { viewport_height } = &mut orthographic.scaling_mode {
+                ui.horizontal(|ui| {
+                    ui.label("view height");
+                    ui.add(egui::Slider::new(viewport_height, 0.1..=20.0).logarithmic(true));
+                });
+            }
+        }
+        Projection::Custom(_) => {}
+    }
+    ui.horizontal(|ui| {
+        ui.label("aperture");
+        ui.add(egui::Slider::new(&mut settings.aperture, 0.0..=0.5));
+    });
+    ui.horizontal(|ui| {
+        ui.label("focus distance");
+        ui.add(egui::Slider::new(&mut settings.focus_distance, 0.1..=20.0).logarithmic(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Focus on:");
+        for (name, position) in [("object 1", objects[0]), ("object 2", objects[1])] {
+            if ui.button(name).clicked() {
+                let distance = (position - transform.translation).dot(*transform.forward());
+                settings.focus_distance = distance.max(0.1);
+            }
+        }
+    });
+    if settings.aperture > 0.0 {
+        ui.label("the blur is noisy while the camera moves, it smooths out when it stops");
+    }
+}
+
+fn camera_path_ui(
+    ui: &mut egui::Ui,
+    camera_path: &mut CameraPath,
+    transform: &Transform,
+    look_at: Vec3,
+    fov: f32,
+    time: f32,
+) {
+    ui.heading("Camera Path");
+    ui.horizontal(|ui| {
+        if ui.button("key from view").clicked() {
+            camera_path.keys.retain(|key| key.time != time);
+            camera_path.selected = None;
+            camera_path.keys.push(CameraKey {
+                time,
+                position: transform.translation,
+                look_at,
+                fov,
             });
+            camera_path.sort_keys();
         }
+        ui.checkbox(&mut camera_path.show, "show path");
     });
+    let mut remove_key = None;
+    let mut moved = false;
+    let selected_key = camera_path.selected.map(|selected| selected.key);
+    for (index, key) in camera_path.keys.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            // clicked in the viewport, the gizmo moves it there
+            if selected_key == Some(index) {
+                ui.label(">");
+            }
+            moved |= ui
+                .add(
+                    egui::DragValue::new(&mut key.time)
+                        .speed(0.01)
+                        .range(0.0..=f32::MAX)
+                        .suffix(" s"),
+                )
+                .changed();
+            ui.label("pos");
+            ui.add(egui::DragValue::new(&mut key.position.x).speed(0.01));
+            ui.add(egui::DragValue::new(&mut key.position.y).speed(0.01));
+            ui.add(egui::DragValue::new(&mut key.position.z).speed(0.01));
+            ui.label("look at");
+            ui.add(egui::DragValue::new(&mut key.look_at.x).speed(0.01));
+            ui.add(egui::DragValue::new(&mut key.look_at.y).speed(0.01));
+            ui.add(egui::DragValue::new(&mut key.look_at.z).speed(0.01));
+            ui.label("fov");
+            ui.drag_angle(&mut key.fov);
+            if ui.button("x").clicked() {
+                remove_key = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove_key {
+        camera_path.remove_key(index);
+    }
+    if moved {
+        camera_path.sort_keys();
+    }
 }
 
 fn ui_settings_ui(
@@ -137,6 +631,14 @@ fn ui_settings_ui(
     mut ui_state: ResMut<UiState>,
     mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
     rm_material_handle: Res<RaymarchMaterialHandle>,
+    mut playback: ResMut<Playback>,
+    controller: Res<CameraController>,
+    #[cfg(not(target_arch = "wasm32"))] mut camera_path: ResMut<CameraPath>,
+    #[cfg(not(target_arch = "wasm32"))] mut timeline: ResMut<Timeline>,
+    #[cfg(not(target_arch = "wasm32"))] asset_server: Res<AssetServer>,
+    #[cfg(not(target_arch = "wasm32"))] mut screenshot: ResMut<ScreenshotSettings>,
+    cameras: Query<(&Camera, &GlobalTransform, &Projection, Option<&SpinningCam>), With<Camera3d>>,
+    lights: Query<&GlobalTransform, With<PointLight>>,
 ) {
     egui::Window::new("Quick Settings").show(contexts.ctx_mut(), |ui| {
         ui.label("depth reading cannot work on web!!");
@@ -171,10 +673,135 @@ fn ui_settings_ui(
                         if mandelbulb_prototype_button.clicked() {
                             mat.extension = RaymarchMaterial::get_mandelbulb_config()
                         }
+                        let cloud_prototype_button = ui.button("Cloud");
+                        if cloud_prototype_button.clicked() {
+                            mat.extension = RaymarchMaterial::get_cloud_config()
+                        }
                     });
                 });
             }
         });
+        ui.horizontal(|ui| {
+            ui.label("Playback");
+            let play_label = if playback.playing { "pause" } else { "play" };
+            if ui.button(play_label).clicked() {
+                playback.playing = !playback.playing;
+            }
+            ui.add(
+                egui::DragValue::new(&mut playback.time)
+                    .speed(0.01)
+                    .range(0.0..=f32::MAX)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("speed");
+            ui.add(egui::Slider::new(&mut playback.speed, -2.0..=4.0));
+            if ui.button("1x").clicked() {
+                playback.speed = 1.0;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut playback.looping, "loop");
+            if playback.looping {
+                let playback = &mut *playback;
+                ui.add(
+                    egui::DragValue::new(&mut playback.loop_start)
+                        .speed(0.01)
+                        .range(0.0..=playback.loop_end)
+                        .suffix(" s"),
+                );
+                ui.label("to");
+                ui.add(
+                    egui::DragValue::new(&mut playback.loop_end)
+                        .speed(0.01)
+                        .range(playback.loop_start..=f32::MAX)
+                        .suffix(" s"),
+                );
+            }
+        });
+        ui.label("space: play/pause, arrows: step a frame, home: back to the start");
+        // the objects, global settings, textures, timeline and the camera path
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("Scene");
+            let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+                return;
+            };
+            if ui.button("save").clicked() {
+                match save_scene(&mat.extension, &timeline, &camera_path) {
+                    Ok(()) => info!("saved the scene to {SCENE_FILE}"),
+                    Err(err) => error!("could not save {SCENE_FILE}: {err}"),
+                }
+            }
+            if ui.button("load").clicked() {
+                if let Err(err) = load_scene(
+                    &mut mat.extension,
+                    &mut timeline,
+                    &mut camera_path,
+                    &asset_server,
+                ) {
+                    error!("could not load {SCENE_FILE}: {err}");
+                }
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("Screenshot");
+            if screenshot.is_capturing() {
+                ui.label("capturing...");
+                return;
+            }
+            if ui.button("take (F12)").clicked() {
+                screenshot.requested = true;
+            }
+            ui.add(egui::Slider::new(&mut screenshot.scale, 0.5..=4.0).suffix("x"));
+            ui.checkbox(&mut screenshot.transparent, "transparent");
+            ui.radio_value(&mut screenshot.format, ScreenshotFormat::Png, "png");
+            ui.radio_value(&mut screenshot.format, ScreenshotFormat::Exr, "exr");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Shadertoy export");
+            let Some(mat) = rm_materials.get(&rm_material_handle.0) else {
+                return;
+            };
+            // the main camera draws last
+            let Some((_, transform, projection, spinning_cam)) = cameras
+                .iter()
+                .filter(|(camera, ..)| camera.is_active)
+                .max_by_key(|(camera, ..)| camera.order)
+            else {
+                return;
+            };
+            let fov = match projection {
+                Projection::Perspective(perspective) => perspective.fov,
+                _ => PerspectiveProjection::default().fov,
+            };
+            let light_position = lights
+                .iter()
+                .next()
+                .map_or(Vec3::splat(4.0), |light| light.translation());
+            // the interactive cameras get exported where they are right now
+            let spinning_cam = spinning_cam.filter(|_| controller.mode() == CameraMode::Spinning);
+            let export = || {
+                let camera = ShadertoyCamera {
+                    transform,
+                    fov,
+                    spinning_cam,
+                };
+                return export_shadertoy(&mat.extension, camera, light_position);
+            };
+            if ui.button("copy").clicked() {
+                ui.ctx().copy_text(export());
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("save").clicked() {
+                match std::fs::write("shadertoy_export.glsl", export()) {
+                    Ok(()) => info!("saved the scene to shadertoy_export.glsl"),
+                    Err(err) => error!("could not save shadertoy_export.glsl: {err}"),
+                }
+            }
+        });
     });
 }
 
@@ -183,19 +810,421 @@ fn object_settings_ui(
     mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
     rm_material_handle: Res<RaymarchMaterialHandle>,
     ui_state: Res<UiState>,
+    selection: Res<Selection>,
+    mut texture_paths: ResMut<TexturePathInputs>,
+    asset_server: Res<AssetServer>,
 ) {
-    if ui_state.into_inner() == &UiState::Minimal {
-        return;
+    // in the minimal ui, only the object that was clicked on gets its window
+    let minimal = ui_state.into_inner() == &UiState::Minimal;
+    let visible = |object| !minimal || selection.object == Some(object);
+    let ctx = contexts.ctx_mut();
+    if selection.is_changed() {
+        if let Some(object) = selection.object {
+            ctx.move_to_top(egui::LayerId::new(
+                egui::Order::Middle,
+                object_window_id(object),
+            ));
+        }
     }
     let rm_material = rm_materials.get_mut(&rm_material_handle.0);
     if let Some(mat) = rm_material {
-        egui::Window::new("Object 1 Settings").show(contexts.ctx_mut(), |ui| {
-            create_object_settings(ui, &mut mat.extension.material1);
+        let ext = &mut mat.extension;
+        if visible(SdfObject::Object1) {
+            object_window(SdfObject::Object1, &selection).show(ctx, |ui| {
+                create_object_settings(ui, &mut ext.material1);
+                create_texture_settings(
+                    ui,
+                    &mut ext.material1,
+                    [
+                        &mut ext.object1_base_color_texture,
+                        &mut ext.object1_metallic_roughness_texture,
+                        &mut ext.object1_normal_map_texture,
+                    ],
+                    &mut texture_paths.object1,
+                    &asset_server,
+                );
+            });
+        }
+        if visible(SdfObject::Object2) {
+            object_window(SdfObject::Object2, &selection).show(ctx, |ui| {
+                create_object_settings(ui, &mut ext.material2);
+                create_texture_settings(
+                    ui,
+                    &mut ext.material2,
+                    [
+                        &mut ext.object2_base_color_texture,
+                        &mut ext.object2_metallic_roughness_texture,
+                        &mut ext.object2_normal_map_texture,
+                    ],
+                    &mut texture_paths.object2,
+                    &asset_server,
+                );
+            });
+        }
+    }
+}
+
+// the title changes with the selection, so the id can't come from it
+fn object_window_id(object: SdfObject) -> egui::Id {
+    return egui::Id::new(("object settings", object.name()));
+}
+
+fn object_window(object: SdfObject, selection: &Selection) -> egui::Window<'static> {
+    let mut title = format!("{} Settings", object.name());
+    if selection.object == Some(object) {
+        title.push_str(" (selected)");
+    }
+    return egui::Window::new(title).id(object_window_id(object));
+}
+
+// numbers for the selected object, next to the gizmo in the viewport
+fn transform_gizmo_ui(
+    mut contexts: EguiContexts,
+    mut gizmo: ResMut<TransformGizmo>,
+    selection: Res<Selection>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+) {
+    let Some(object) = selection.object else {
+        return;
+    };
+    let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+        return;
+    };
+    let desc = object.descriptor_mut(&mut mat.extension);
+    egui::Window::new("Transform").show(contexts.ctx_mut(), |ui| {
+        ui.label(format!(
+            "{} (click into the void to deselect)",
+            object.name()
+        ));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut gizmo.mode, GizmoMode::Translate, "Move");
+            ui.radio_value(&mut gizmo.mode, GizmoMode::Rotate, "Rotate");
+            ui.radio_value(&mut gizmo.mode, GizmoMode::Scale, "Scale");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Axes");
+            ui.radio_value(&mut gizmo.space, GizmoSpace::World, "World");
+            ui.radio_value(&mut gizmo.space, GizmoSpace::Local, "Local");
         });
-        egui::Window::new("Object 2 Settings").show(contexts.ctx_mut(), |ui| {
-            create_object_settings(ui, &mut mat.extension.material2);
+        ui.checkbox(&mut gizmo.snap, "Snap")
+            .on_hover_text("hold ctrl while dragging to flip this");
+        ui.horizontal(|ui| {
+            ui.label("steps");
+            ui.add(
+                egui::DragValue::new(&mut gizmo.translate_step)
+                    .speed(0.01)
+                    .range(0.001..=10.0),
+            );
+            ui.drag_angle(&mut gizmo.rotate_step);
+            ui.add(
+                egui::DragValue::new(&mut gizmo.scale_step)
+                    .speed(0.01)
+                    .range(0.001..=10.0),
+            );
         });
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("position");
+            ui.add(egui::DragValue::new(&mut desc.world_position.x).speed(0.01));
+            ui.add(egui::DragValue::new(&mut desc.world_position.y).speed(0.01));
+            ui.add(egui::DragValue::new(&mut desc.world_position.z).speed(0.01));
+        });
+        ui.horizontal(|ui| {
+            ui.label("rotation x");
+            ui.drag_angle(&mut desc.rotation.x);
+        });
+        if can_scale(desc) {
+            ui.horizontal(|ui| {
+                ui.label("size");
+                ui.add(
+                    egui::DragValue::new(&mut desc.shape_var)
+                        .speed(0.01)
+                        .range(0.01..=10.0),
+                );
+            });
+        } else {
+            ui.label("the mandelbulb can't be scaled, its shape variable is the power");
+        }
+    });
+}
+
+fn timeline_ui(
+    mut contexts: EguiContexts,
+    mut timeline: ResMut<Timeline>,
+    mut playback: ResMut<Playback>,
+    mut new_track: ResMut<NewTrackInputs>,
+    mut rm_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, RaymarchMaterial>>>,
+    rm_material_handle: Res<RaymarchMaterialHandle>,
+    ui_state: Res<UiState>,
+) {
+    if ui_state.into_inner() == &UiState::Minimal {
+        return;
     }
+    let Some(mat) = rm_materials.get_mut(&rm_material_handle.0) else {
+        return;
+    };
+    let timeline = &mut *timeline;
+    egui::Window::new("Timeline").show(contexts.ctx_mut(), |ui| {
+        let time = mat.extension.raymarch_global_settings.time;
+        ui.horizontal(|ui| {
+            ui.label(format!("time: {:.2} s", time));
+            ui.label("length");
+            ui.add(
+                egui::DragValue::new(&mut timeline.length)
+                    .range(1.0..=600.0)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("scrub");
+            let mut scrub_time = time.min(timeline.length);
+            let slider = ui.add(egui::Slider::new(&mut scrub_time, 0.0..=timeline.length));
+            if slider.changed() {
+                playback.scrub_to(scrub_time);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(ui.id().with("track_target"))
+                .selected_text(new_track.target.name())
+                .show_ui(ui, |ui| {
+                    for target in TrackTarget::ALL {
+                        ui.selectable_value(&mut new_track.target, target, target.name());
+                    }
+                });
+            let param_names = new_track.target.param_names();
+            new_track.param = new_track.param.min(param_names.len() - 1);
+            egui::ComboBox::from_id_salt(ui.id().with("track_param"))
+                .selected_text(param_names[new_track.param])
+                .show_ui(ui, |ui| {
+                    for (index, name) in param_names.iter().enumerate() {
+                        ui.selectable_value(&mut new_track.param, index, *name);
+                    }
+                });
+            if ui.button("add track").clicked() {
+                let mut track = Track {
+                    target: new_track.target,
+                    param: new_track.param,
+                    keys: Vec::new(),
+                };
+                // starts out with the current value, so nothing jumps
+                track.keys.push(Keyframe {
+                    time,
+                    value: track.access(&mut mat.extension, None),
+                    easing: Easing::Linear,
+                });
+                timeline.tracks.push(track);
+            }
+        });
+
+        let mut remove_track = None;
+        for (track_index, track) in timeline.tracks.iter_mut().enumerate() {
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(track.name());
+                if ui.button("key at current time").clicked() {
+                    track.keys.retain(|key| key.time != time);
+                    track.keys.push(Keyframe {
+                        time,
+                        value: track.access(&mut mat.extension, None),
+                        easing: Easing::Linear,
+                    });
+                    track.sort_keys();
+                }
+                if ui.button("delete track").clicked() {
+                    remove_track = Some(track_index);
+                }
+            });
+            if let Some(clicked_time) = timeline_strip(ui, track, time, timeline.length) {
+                playback.scrub_to(clicked_time);
+            }
+            let mut remove_key = None;
+            let mut moved = false;
+            for (key_index, key) in track.keys.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    moved |= ui
+                        .add(
+                            egui::DragValue::new(&mut key.time)
+                                .speed(0.01)
+                                .range(0.0..=f32::MAX)
+                                .suffix(" s"),
+                        )
+                        .changed();
+                    ui.add(egui::DragValue::new(&mut key.value).speed(0.01));
+                    egui::ComboBox::from_id_salt(ui.id().with((track_index, key_index)))
+                        .selected_text(key.easing.name())
+                        .show_ui(ui, |ui| {
+                            for easing in Easing::ALL {
+                                ui.selectable_value(&mut key.easing, easing, easing.name());
+                            }
+                        });
+                    if ui.button("x").clicked() {
+                        remove_key = Some(key_index);
+                    }
+                });
+            }
+            if let Some(key_index) = remove_key {
+                track.keys.remove(key_index);
+            }
+            if moved {
+                track.sort_keys();
+            }
+        }
+        if let Some(track_index) = remove_track {
+            timeline.tracks.remove(track_index);
+        }
+    });
+}
+
+// the keys of one track on a bar from 0 to length, clicking it scrubs there
+fn timeline_strip(ui: &mut egui::Ui, track: &Track, time: f32, length: f32) -> Option<f32> {
+    let size = egui::vec2(ui.available_width().max(200.0), 16.0);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    let painter = ui.painter();
+    let x_of = |t: f32| rect.left() + (t / length).clamp(0.0, 1.0) * rect.width();
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    for key in track.keys.iter() {
+        painter.circle_filled(
+            egui::pos2(x_of(key.time), rect.center().y),
+            4.0,
+            Color32::YELLOW,
+        );
+    }
+    let playhead = x_of(time);
+    painter.line_segment(
+        [
+            egui::pos2(playhead, rect.top()),
+            egui::pos2(playhead, rect.bottom()),
+        ],
+        (1.0, Color32::RED),
+    );
+    if response.clicked() || response.dragged() {
+        let pos = response.interact_pointer_pos()?;
+        return Some(((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0) * length);
+    }
+    return None;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn offline_render_ui(
+    mut contexts: EguiContexts,
+    mut offline_render: ResMut<OfflineRender>,
+    ui_state: Res<UiState>,
+) {
+    if ui_state.into_inner() == &UiState::Minimal {
+        return;
+    }
+    egui::Window::new("Offline Render").show(contexts.ctx_mut(), |ui| {
+        if let Some(frame) = offline_render.progress() {
+            ui.label(format!(
+                "rendering frame {} / {}",
+                frame + 1,
+                offline_render.frame_count
+            ));
+            if ui.button("cancel (esc)").clicked() {
+                offline_render.cancel();
+            }
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("resolution");
+            ui.add(egui::DragValue::new(&mut offline_render.width).range(16..=8192));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut offline_render.height).range(16..=8192));
+        });
+        ui.horizontal(|ui| {
+            ui.label("supersampling");
+            ui.add(egui::Slider::new(&mut offline_render.supersample, 1..=4));
+        });
+        ui.horizontal(|ui| {
+            ui.label("fps");
+            ui.add(egui::Slider::new(&mut offline_render.fps, 1..=60));
+        });
+        ui.horizontal(|ui| {
+            ui.label("start time");
+            ui.add(
+                egui::DragValue::new(&mut offline_render.start_time)
+                    .speed(0.01)
+                    .range(0.0..=f32::MAX)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("frames");
+            ui.add(egui::DragValue::new(&mut offline_render.frame_count).range(1..=10000));
+            let length = offline_render.frame_count as f32 / offline_render.fps as f32;
+            ui.label(format!("({:.2} s)", length));
+        });
+        ui.horizontal(|ui| {
+            ui.label("output folder");
+            ui.text_edit_singleline(&mut offline_render.output_dir);
+        });
+        ui.checkbox(&mut offline_render.gif, "also write a gif");
+        ui.checkbox(
+            &mut offline_render.cpu,
+            "cpu renderer (slow, simple shading)",
+        );
+        if ui.button("render").clicked() {
+            offline_render.requested = true;
+        }
+    });
+}
+
+/// what the "add track" row of the timeline has selected
+#[derive(Resource)]
+struct NewTrackInputs {
+    target: TrackTarget,
+    param: usize,
+}
+
+impl Default for NewTrackInputs {
+    fn default() -> Self {
+        return NewTrackInputs {
+            target: TrackTarget::Object1,
+            param: 0,
+        };
+    }
+}
+
+/// the text in the texture path fields
+/// order: base color, metallic roughness, normal map
+#[derive(Resource, Default)]
+struct TexturePathInputs {
+    object1: [String; 3],
+    object2: [String; 3],
+}
+
+fn create_texture_settings(
+    ui: &mut egui::Ui,
+    desc: &mut RaymarchObjectDescriptor,
+    textures: [&mut Option<Handle<Image>>; 3],
+    paths: &mut [String; 3],
+    asset_server: &AssetServer,
+) {
+    ui.heading("Textures");
+    ui.label("paths are relative to the assets folder");
+    let names = ["base color", "metallic roughness", "normal map"];
+    for (i, texture) in textures.into_iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(names[i]);
+            ui.text_edit_singleline(&mut paths[i]);
+            if ui.button("load").clicked() && !paths[i].is_empty() {
+                *texture = Some(load_texture(asset_server, paths[i].clone(), i == 0));
+            }
+            if ui.button("clear").clicked() {
+                *texture = None;
+            }
+        });
+    }
+    ui.horizontal(|ui| {
+        ui.label("texture scale");
+        ui.add(egui::Slider::new(&mut desc.texture_scale, 0.1..=10.0))
+    });
+    ui.horizontal(|ui| {
+        ui.label("blend sharpness");
+        ui.add(egui::Slider::new(&mut desc.texture_blend_sharpness, 1.0..=16.0))
+    });
 }
 
 fn create_object_settings(ui: &mut egui::Ui, desc: &mut RaymarchObjectDescriptor) {
@@ -233,6 +1262,15 @@ fn create_object_settings(ui: &mut egui::Ui, desc: &mut RaymarchObjectDescriptor
             0.0..=2.0,
         ))
     });
+    if desc.shape_type_id == Shape::Cone as u32 {
+        ui.horizontal(|ui| {
+            ui.label("cone angle");
+            ui.add(egui::Slider::new(
+                &mut desc.scale,
+                0.0..=f32::consts::FRAC_PI_2,
+            ))
+        });
+    }
     ui.horizontal(|ui| {
         ui.label("rotation over time");
         ui.add(egui::Slider::new(&mut desc.rotation_amount, 0.0..=1.0))
@@ -284,9 +1322,171 @@ fn create_object_settings(ui: &mut egui::Ui, desc: &mut RaymarchObjectDescriptor
             0.0..=1.0,
         ))
     });
-    ui.add(egui::Label::new(
-        "there are more knobs to turn, but these are enough for now",
-    ));
+    ui.horizontal(|ui| {
+        ui.label("anisotropy");
+        ui.add(egui::Slider::new(&mut desc.anisotropy_strength, 0.0..=1.0))
+    });
+    ui.horizontal(|ui| {
+        ui.label("anisotropy rotation");
+        let mut angle = desc.anisotropy_rotation.y.atan2(desc.anisotropy_rotation.x);
+        ui.add(egui::Slider::new(
+            &mut angle,
+            -f32::consts::PI..=f32::consts::PI,
+        ));
+        desc.anisotropy_rotation = Vec2::new(angle.cos(), angle.sin());
+    });
+
+    ui.heading("Transmission");
+    ui.label("specular transmission only refracts the non-raymarched stuff behind the object");
+    ui.horizontal(|ui| {
+        ui.label("specular transmission");
+        ui.add(egui::Slider::new(
+            &mut desc.specular_transmission,
+            0.0..=1.0,
+        ))
+    });
+    ui.horizontal(|ui| {
+        ui.label("diffuse transmission");
+        ui.add(egui::Slider::new(&mut desc.diffuse_transmission, 0.0..=1.0))
+    });
+    ui.horizontal(|ui| {
+        ui.label("thickness");
+        ui.add(egui::Slider::new(&mut desc.thickness, 0.0..=2.0))
+    });
+    ui.horizontal(|ui| {
+        ui.label("ior");
+        ui.add(egui::Slider::new(&mut desc.ior, 1.0..=3.0))
+    });
+    ui.horizontal(|ui| {
+        ui.label("attenuation distance");
+        ui.add(egui::Slider::new(&mut desc.attenuation_distance, 0.01..=10.0).logarithmic(true))
+    });
+    ui.horizontal(|ui| {
+        ui.label("attenuation color");
+        let mut color32 = vec4_to_color32(&desc.attenuation_color);
+        ui.color_edit_button_srgba(&mut color32);
+        desc.attenuation_color = color32_to_vec4(color32);
+    });
+
+    ui.heading("Glow");
+    ui.label("glow_range is in the global settings");
+    ui.horizontal(|ui| {
+        ui.label("glow color");
+        let mut color32 = vec4_to_color32(&desc.glow_color);
+        ui.color_edit_button_srgba(&mut color32);
+        desc.glow_color = color32_to_vec4(color32);
+    });
+    ui.horizontal(|ui| {
+        ui.label("glow intensity");
+        ui.add(egui::Slider::new(&mut desc.glow_intensity, 0.0..=10.0))
+    });
+
+    ui.heading("Volume");
+    ui.horizontal(|ui| {
+        ui.label("volume");
+        ui.vertical(|ui| {
+            ui.radio_value(&mut desc.volume_type_id, Volume::Solid as u32, "solid");
+            ui.radio_value(
+                &mut desc.volume_type_id,
+                Volume::Constant as u32,
+                "constant",
+            );
+            ui.radio_value(&mut desc.volume_type_id, Volume::Noise as u32, "noise");
+            ui.radio_value(
+                &mut desc.volume_type_id,
+                Volume::SdfFalloff as u32,
+                "sdf falloff",
+            );
+        });
+    });
+    if desc.volume_type_id != Volume::Solid as u32 {
+        ui.label("base color -> scattering color, emissive -> emission");
+        ui.horizontal(|ui| {
+            ui.label("density");
+            ui.add(egui::Slider::new(&mut desc.volume_density, 0.0..=50.0))
+        });
+        ui.horizontal(|ui| {
+            ui.label("absorption");
+            ui.add(egui::Slider::new(&mut desc.volume_absorption, 0.0..=2.0))
+        });
+        ui.horizontal(|ui| {
+            ui.label("scattering");
+            ui.add(egui::Slider::new(&mut desc.volume_scattering, 0.0..=2.0))
+        });
+        ui.horizontal(|ui| {
+            ui.label("phase g");
+            ui.add(egui::Slider::new(&mut desc.volume_phase_g, -0.99..=0.99))
+        });
+        if desc.volume_type_id == Volume::Noise as u32 {
+            ui.horizontal(|ui| {
+                ui.label("noise scale");
+                ui.add(egui::Slider::new(&mut desc.volume_noise_scale, 0.1..=20.0))
+            });
+        }
+        if desc.volume_type_id == Volume::SdfFalloff as u32 {
+            ui.horizontal(|ui| {
+                ui.label("falloff");
+                ui.add(egui::Slider::new(&mut desc.volume_falloff, 0.01..=1.0))
+            });
+        }
+    }
+
+    ui.heading("Pattern");
+    ui.horizontal(|ui| {
+        ui.label("pattern");
+        egui::ComboBox::from_id_salt(ui.id().with("pattern"))
+            .selected_text(Pattern::from_id(desc.pattern_type_id).name())
+            .show_ui(ui, |ui| {
+                for pattern in Pattern::ALL {
+                    ui.selectable_value(&mut desc.pattern_type_id, pattern as u32, pattern.name());
+                }
+            });
+    });
+    if desc.pattern_type_id == Pattern::OrbitTrap as u32 {
+        ui.horizontal(|ui| {
+            ui.label("orbit trap");
+            ui.vertical(|ui| {
+                ui.radio_value(
+                    &mut desc.orbit_trap_type_id,
+                    OrbitTrap::Origin as u32,
+                    "origin",
+                );
+                ui.radio_value(
+                    &mut desc.orbit_trap_type_id,
+                    OrbitTrap::Planes as u32,
+                    "planes",
+                );
+                ui.radio_value(
+                    &mut desc.orbit_trap_type_id,
+                    OrbitTrap::Iterations as u32,
+                    "iterations",
+                );
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("palette");
+            for color in desc.palette.iter_mut() {
+                let mut color32 = vec4_to_color32(color);
+                ui.color_edit_button_srgba(&mut color32);
+                *color = color32_to_vec4(color32);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("trap scale");
+            ui.add(egui::Slider::new(&mut desc.pattern_scale, 0.1..=20.0))
+        });
+    } else if desc.pattern_type_id != Pattern::None as u32 {
+        ui.horizontal(|ui| {
+            ui.label("pattern color");
+            let mut color32 = vec4_to_color32(&desc.pattern_color);
+            ui.color_edit_button_srgba(&mut color32);
+            desc.pattern_color = color32_to_vec4(color32);
+        });
+        ui.horizontal(|ui| {
+            ui.label("pattern scale");
+            ui.add(egui::Slider::new(&mut desc.pattern_scale, 0.1..=20.0))
+        });
+    }
 }
 
 fn vec4_to_color32(vec: &Vec4) -> Color32 {
@@ -313,6 +1513,86 @@ enum Shape {
     Mandelbulb = 4,
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum Pattern {
+    None = 0,
+    Checker = 1,
+    Stripes = 2,
+    Noise = 3,
+    Marble = 4,
+    HeightGradient = 5,
+    CurvatureGradient = 6,
+    OrbitTrap = 7,
+}
+
+impl Pattern {
+    const ALL: [Pattern; 8] = [
+        Pattern::None,
+        Pattern::Checker,
+        Pattern::Stripes,
+        Pattern::Noise,
+        Pattern::Marble,
+        Pattern::HeightGradient,
+        Pattern::CurvatureGradient,
+        Pattern::OrbitTrap,
+    ];
+
+    fn from_id(id: u32) -> Pattern {
+        return Pattern::ALL
+            .into_iter()
+            .find(|p| *p as u32 == id)
+            .unwrap_or(Pattern::None);
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Pattern::None => "None",
+            Pattern::Checker => "Checker",
+            Pattern::Stripes => "Stripes",
+            Pattern::Noise => "Noise",
+            Pattern::Marble => "Marble",
+            Pattern::HeightGradient => "Gradient by height",
+            Pattern::CurvatureGradient => "Gradient by curvature",
+            Pattern::OrbitTrap => "Orbit trap (fractals)",
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum GlowMode {
+    ClosestApproach = 0,
+    Accumulated = 1,
+}
+
+#[derive(PartialEq)]
+enum NormalMethod {
+    Tetrahedron = 0,
+    Central = 1,
+    Forward = 2,
+}
+
+#[derive(PartialEq)]
+enum DynamicResolution {
+    Off = 0,
+    Fixed = 1,
+    Adaptive = 2,
+}
+
+#[derive(PartialEq)]
+enum Volume {
+    Solid = 0,
+    Constant = 1,
+    Noise = 2,
+    SdfFalloff = 3,
+}
+
+#[derive(PartialEq)]
+enum OrbitTrap {
+    Origin = 0,
+    Planes = 1,
+    Iterations = 2,
+}
+
 #[derive(PartialEq)]
 enum IntersectionMethod {
     Or = 0,
@@ -320,6 +1600,14 @@ enum IntersectionMethod {
     Not = 2,
 }
 
+#[derive(PartialEq)]
+enum MaterialBlendMode {
+    DistanceLerp = 0,
+    Nearest = 1,
+    SmoothWeight = 2,
+    CustomWidth = 3,
+}
+
 #[derive(Resource, PartialEq)]
 enum UiState {
     Full,